@@ -0,0 +1,65 @@
+//! Single instructions, draws and whole frames of the bundled roms
+
+use chiper::chip8::Chip8;
+use chiper::instruction::Instruction;
+use chiper::platform::Quirks;
+use chiper::screen::NoScreen;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+const ROMS: [&str; 5] = [
+    "Fishie.ch8",
+    "Maze.ch8",
+    "partircle.ch8",
+    "pong.ch8",
+    "space_invader_alien_sprite.ch8",
+];
+
+/// Frames run per iteration of the rom benchmarks
+const FRAMES: u64 = 60;
+
+fn machine(rom: &str) -> Chip8<NoScreen> {
+    let mut chip8 = Chip8::new(NoScreen {});
+    chip8.load_rom(&format!("roms/{}", rom)).unwrap();
+    chip8.set_seed(1);
+    chip8
+}
+
+fn emulate_op(c: &mut Criterion) {
+    // pong never halts
+    let mut chip8 = machine("pong.ch8");
+    c.bench_function("emulate_op", |b| b.iter(|| chip8.emulate_op().unwrap()));
+}
+
+fn op_draw(c: &mut Criterion) {
+    let mut chip8 = machine("pong.ch8");
+    // draws of pong wait for the vertical blank
+    chip8.set_quirks(Quirks::default());
+    // the font digit 8
+    chip8.set_i(8 * 5);
+    let mut n: u8 = 0;
+    c.bench_function("op_draw", |b| {
+        b.iter(|| {
+            n = n.wrapping_add(1);
+            chip8.v()[0] = n.wrapping_mul(37);
+            chip8.v()[1] = n.wrapping_mul(11);
+            black_box(chip8.execute(Instruction::Draw(0, 1, 5)).unwrap())
+        })
+    });
+}
+
+fn roms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("roms");
+    for rom in ROMS {
+        group.bench_function(rom, |b| {
+            b.iter_batched(
+                || machine(rom),
+                |mut chip8| chip8.run_frames(FRAMES).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, emulate_op, op_draw, roms);
+criterion_main!(benches);
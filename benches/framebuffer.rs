@@ -0,0 +1,77 @@
+//! Sprite draws of the bit-parallel framebuffer against drawing pixel by
+//! pixel into the display memory, the way DXYN used to
+
+use chiper::framebuffer::{Hires, Lores};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SPRITE: [u8; 15] = [
+    0x3c, 0x7e, 0xff, 0xdb, 0xff, 0x66, 0x3c, 0x18, 0x3c, 0x7e, 0xff, 0xdb, 0xff, 0x66, 0x3c,
+];
+
+/// Positions all over the screen, some sprites cross the edges
+fn positions() -> Vec<(u32, u32)> {
+    (0..256).map(|n| ((n * 37) % 64, (n * 11) % 32)).collect()
+}
+
+/// Draws to the 64x32 display memory a bit at a time
+fn draw_per_pixel(screen: &mut [u8; 256], x: u32, y: u32, sprite: &[u8], wrap: bool) -> bool {
+    let (x, y) = (x as usize % 64, y as usize % 32);
+    let mut collision = false;
+    for (i, &line) in sprite.iter().enumerate() {
+        let mut cy = y + i;
+        if cy >= 32 {
+            if !wrap {
+                break;
+            }
+            cy %= 32;
+        }
+        for (cx, bit) in (x..).zip((0..8).rev()) {
+            let cx = match cx {
+                cx if cx < 64 => cx,
+                cx if wrap => cx % 64,
+                _ => break,
+            };
+            if line & (1 << bit) != 0 {
+                let (idx, px) = (cy * 8 + cx / 8, 7 - cx % 8);
+                collision |= screen[idx] & (1 << px) != 0;
+                screen[idx] ^= 1 << px;
+            }
+        }
+    }
+    collision
+}
+
+fn draw(c: &mut Criterion) {
+    let positions = positions();
+    let mut group = c.benchmark_group("draw");
+    for (mode, wrap) in [("clip", false), ("wrap", true)] {
+        group.bench_function(format!("per_pixel/{}", mode), |b| {
+            let mut screen = [0; 256];
+            b.iter(|| {
+                for &(x, y) in &positions {
+                    black_box(draw_per_pixel(&mut screen, x, y, &SPRITE, wrap));
+                }
+            })
+        });
+        group.bench_function(format!("lores/{}", mode), |b| {
+            let mut fb = Lores::default();
+            b.iter(|| {
+                for &(x, y) in &positions {
+                    black_box(fb.draw(x, y, &SPRITE, wrap, |_, _, _| {}));
+                }
+            })
+        });
+        group.bench_function(format!("hires/{}", mode), |b| {
+            let mut fb = Hires::default();
+            b.iter(|| {
+                for &(x, y) in &positions {
+                    black_box(fb.draw(x * 2, y * 2, &SPRITE, wrap, |_, _, _| {}));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, draw);
+criterion_main!(benches);
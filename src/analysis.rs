@@ -0,0 +1,600 @@
+//! Static analysis of roms
+//!
+//! Roms missing from the rom database are checked for the code depending on
+//! quirks and for opcodes of the later platforms, which gives a platform and
+//! quirks to run them with. All checks look only at the code reachable in
+//! the control-flow graph, so they are heuristics: coordinates of sprites
+//! are known only when they're set in the same block as the draw. I is
+//! followed through the graph to tell sprites and tables between the code
+//! apart from dead code.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+use crate::cfg::{Cfg, Exit};
+use crate::chip8::{MEMORY_START, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::instruction::{Extension, Instruction};
+use crate::platform::{Platform, Quirks};
+
+/// Blocks followed after FX55 and FX65 looking for a use of I
+const I_USE_SEARCH_LIMIT: usize = 64;
+/// Values of I followed at once, more are taken as any value
+const I_VALUES_LIMIT: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Behaviour depends on the quirk called so in `platform::QUIRK_NAMES`
+    Quirk(&'static str),
+    /// Opcode of a later platform
+    Extension(Platform),
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub addr: usize,
+    pub kind: Kind,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Quirk(name) => name.to_string(),
+            Kind::Extension(platform) => platform.to_string(),
+            Kind::Invalid => "invalid".to_string(),
+        };
+        write!(f, "{:04x}: {:<21} {}", self.addr, kind, self.message)
+    }
+}
+
+/// Rom bytes never reached from the entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    /// Every word of the region is a valid instruction
+    pub code: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recommendation {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Reasons of the choices, a line each
+    pub reasons: Vec<String>,
+}
+
+impl Recommendation {
+    /// Command line options selecting the recommendation
+    pub fn options(&self) -> String {
+        let mut options = format!("--platform {}", self.platform);
+        let preset = self.platform.quirks();
+        for ((name, value), (_, default)) in self.quirks.iter().zip(preset.iter()) {
+            if value != default {
+                let value = if value { "on" } else { "off" };
+                options.push_str(&format!(" --quirk {}={}", name, value));
+            }
+        }
+        options
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    pub findings: Vec<Finding>,
+    pub unreachable: Vec<Region>,
+    pub recommendation: Recommendation,
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Findings:")?;
+        if self.findings.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for finding in &self.findings {
+            writeln!(f, "  {}", finding)?;
+        }
+        writeln!(f, "Unreachable:")?;
+        if self.unreachable.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for region in &self.unreachable {
+            writeln!(
+                f,
+                "  {:04x}-{:04x}: {} bytes of {}",
+                region.start,
+                region.end - 1,
+                region.end - region.start,
+                if region.code { "code" } else { "data" }
+            )?;
+        }
+        let recommendation = &self.recommendation;
+        writeln!(
+            f,
+            "Recommended: {} with quirks: {}",
+            recommendation.platform, recommendation.quirks
+        )?;
+        for reason in &recommendation.reasons {
+            writeln!(f, "  - {}", reason)?;
+        }
+        writeln!(f, "  options: {}", recommendation.options())
+    }
+}
+
+/// Values of the registers known from the instructions seen so far
+struct Constants {
+    v: [Option<u8>; 16],
+    /// Values I can have, None for any
+    i: Option<BTreeSet<usize>>,
+}
+
+impl Constants {
+    fn new(i: Option<BTreeSet<usize>>) -> Constants {
+        Constants { v: [None; 16], i }
+    }
+
+    fn update(&mut self, instruction: Instruction) {
+        use Instruction::*;
+
+        let v = &mut self.v;
+        match instruction {
+            MovI(nnn) => self.i = Some(BTreeSet::from([nnn as usize])),
+            AddI(x) => {
+                self.i = match (self.i.take(), v[x]) {
+                    (Some(i), Some(vx)) => Some(i.iter().map(|i| i + vx as usize).collect()),
+                    _ => None,
+                }
+            }
+            // fonts are outside the rom, FX55 may advance I with the quirk
+            Font(_) | Store(_) => self.i = None,
+            MovImm(x, nn) => v[x] = Some(nn),
+            AddImm(x, nn) => v[x] = v[x].map(|value| value.wrapping_add(nn)),
+            MovReg(x, y) => v[x] = v[y],
+            Or(x, _)
+            | And(x, _)
+            | Xor(x, _)
+            | AddReg(x, _)
+            | SubReg(x, _)
+            | ShiftRight(x, _)
+            | SubReverse(x, _)
+            | ShiftLeft(x, _) => {
+                v[x] = None;
+                v[0xf] = None;
+            }
+            Rand(x, _) | GetDelay(x) | WaitKey(x) => v[x] = None,
+            Load(x) => {
+                v[..=x].iter_mut().for_each(|value| *value = None);
+                self.i = None;
+            }
+            Draw(..) => v[0xf] = None,
+            _ => (),
+        }
+    }
+
+    /// Memory read as data through a known I
+    fn reads(&self, instruction: Instruction) -> Vec<Range<usize>> {
+        use Instruction::*;
+
+        let len = match instruction {
+            // 16x16 sprites of SUPER-CHIP
+            Draw(_, _, 0) => 32,
+            Draw(_, _, n) => n as usize,
+            Load(x) => x + 1,
+            // the start of a table indexed by VX
+            AddI(_) => 1,
+            _ => return Vec::new(),
+        };
+        let values = self.i.iter().flatten();
+        values.map(|&i| i..i + len).collect()
+    }
+
+    /// Runs the code of a block
+    fn run(&mut self, code: &[(usize, u16)]) {
+        for &(_, word) in code {
+            match Instruction::decode(word) {
+                Ok(instruction) => self.update(instruction),
+                // e.g. the long I load
+                Err(_) => self.i = None,
+            }
+        }
+    }
+}
+
+/// Values I can have at the start of the blocks, None for any. Subroutines
+/// may change I, it's unknown after calls
+fn i_at_blocks(cfg: &Cfg) -> BTreeMap<usize, Option<BTreeSet<usize>>> {
+    let mut entries: BTreeMap<usize, Option<BTreeSet<usize>>> = BTreeMap::new();
+    let mut pending: Vec<(usize, Option<BTreeSet<usize>>)> = vec![(MEMORY_START, None)];
+    while let Some((start, i)) = pending.pop() {
+        let i = match (entries.get(&start), i) {
+            (None, i) => i,
+            (Some(None), _) => continue,
+            (Some(Some(known)), Some(i)) if i.is_subset(known) => continue,
+            (Some(Some(known)), Some(i)) => Some(known | &i).filter(|i| i.len() <= I_VALUES_LIMIT),
+            (Some(Some(_)), None) => None,
+        };
+        entries.insert(start, i.clone());
+        let block = match cfg.block(start) {
+            Some(block) => block,
+            None => continue,
+        };
+        let mut constants = Constants::new(i);
+        constants.run(&cfg.code(block));
+        match block.exit {
+            Exit::Call { target, ret } => {
+                pending.push((target, constants.i));
+                pending.push((ret, None));
+            }
+            exit => pending.extend(
+                exit.successors()
+                    .into_iter()
+                    .map(|s| (s, constants.i.clone())),
+            ),
+        }
+    }
+    entries
+}
+
+fn uses_i(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        Draw(..) | Bcd(_) | Store(_) | Load(_) | AddI(_)
+    )
+}
+
+/// Follows the code after the instruction at `addr` until I is used or set
+/// again, returns the address of the use
+fn i_use_after(cfg: &Cfg, addr: usize) -> Option<usize> {
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![addr + 2];
+    while let Some(addr) = pending.pop() {
+        if visited.len() >= I_USE_SEARCH_LIMIT || !visited.insert(addr) {
+            continue;
+        }
+        let block = match cfg.block_at(addr) {
+            Some(block) => block,
+            None => continue,
+        };
+        let mut reset = false;
+        for (addr, word) in cfg.code(block).into_iter().filter(|c| c.0 >= addr) {
+            match Instruction::decode(word) {
+                Ok(instruction) if uses_i(instruction) => return Some(addr),
+                Ok(Instruction::MovI(_)) | Ok(Instruction::Font(_)) => reset = true,
+                // long I load
+                Err(_) if word == 0xf000 => reset = true,
+                _ => (),
+            }
+            if reset {
+                break;
+            }
+        }
+        if !reset {
+            pending.extend(block.exit.successors());
+        }
+    }
+    None
+}
+
+struct Analyzer<'a> {
+    cfg: &'a Cfg,
+    findings: Vec<Finding>,
+    /// Y of the shifts with X != Y
+    shifts: Vec<usize>,
+    /// Bytes read by sprite draws, FX65 and table lookups
+    data: BTreeSet<usize>,
+}
+
+impl Analyzer<'_> {
+    fn report(&mut self, addr: usize, kind: Kind, message: String) {
+        self.findings.push(Finding {
+            addr,
+            kind,
+            message,
+        });
+    }
+
+    fn check_block(&mut self, code: &[(usize, u16)], exit: Exit, i: Option<BTreeSet<usize>>) {
+        let mut constants = Constants::new(i);
+        for &(addr, word) in code {
+            if let Some(extension) = Extension::decode(word) {
+                let message = format!("{} {}", extension.pattern, extension.description);
+                self.report(addr, Kind::Extension(extension.platform), message);
+            }
+            // other opcodes of blocks are extensions
+            match Instruction::decode(word) {
+                Ok(instruction) => {
+                    self.check(addr, instruction, &constants);
+                    self.data
+                        .extend(constants.reads(instruction).into_iter().flatten());
+                    constants.update(instruction);
+                }
+                // e.g. the long I load
+                Err(_) => constants.i = None,
+            }
+        }
+        if let Exit::Invalid(addr) = exit {
+            let message = match self.cfg.word(addr) {
+                Some(word) if addr < self.cfg.rom_end() => format!("{:04x} isn't an opcode", word),
+                _ => "jump out of the rom".to_string(),
+            };
+            self.report(addr, Kind::Invalid, message);
+        }
+        if let Exit::Indirect(nnn) = exit {
+            let addr = code.last().map_or(0, |c| c.0);
+            let message = format!(
+                "jump to {:03x} + V0, or to {:02x} + V{:x} with the jump quirk",
+                nnn,
+                nnn & 0xff,
+                nnn >> 8
+            );
+            self.report(addr, Kind::Quirk("jump"), message);
+        }
+    }
+
+    fn check(&mut self, addr: usize, instruction: Instruction, constants: &Constants) {
+        use Instruction::*;
+
+        match instruction {
+            ShiftRight(x, y) | ShiftLeft(x, y) if x != y => {
+                self.shifts.push(y);
+                let message = format!("shifts V{:x}, or V{:x} with the shift quirk", y, x);
+                self.report(addr, Kind::Quirk("shift"), message);
+            }
+            Store(_) | Load(_) => {
+                if let Some(next) = i_use_after(self.cfg, addr) {
+                    let message = format!("I is used at {:04x} without reloading it", next);
+                    self.report(addr, Kind::Quirk("memoryLeaveIUnchanged"), message);
+                }
+            }
+            Draw(x, y, n) if n > 0 => {
+                let (width, height) = (SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize);
+                let cx = constants.v[x].map(|cx| cx as usize % width);
+                let cy = constants.v[y].map(|cy| cy as usize % height);
+                let crosses_x = cx.is_some_and(|cx| cx + 8 > width);
+                let crosses_y = cy.is_some_and(|cy| cy + n as usize > height);
+                if crosses_x || crosses_y {
+                    let message = format!(
+                        "sprite at ({}, {}) crosses the screen edge",
+                        cx.map_or("?".to_string(), |cx| cx.to_string()),
+                        cy.map_or("?".to_string(), |cy| cy.to_string())
+                    );
+                    self.report(addr, Kind::Quirk("wrap"), message);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn recommend(&self) -> Recommendation {
+        let mut reasons = Vec::new();
+        let extension = |platform| {
+            self.findings
+                .iter()
+                .filter(|f| f.kind == Kind::Extension(platform))
+                .count()
+        };
+        let platform = match (extension(Platform::XoChip), extension(Platform::SuperChip)) {
+            (0, 0) => {
+                reasons.push("only CHIP-8 opcodes are used".to_string());
+                Platform::Chip8
+            }
+            (0, count) => {
+                reasons.push(format!("{} SUPER-CHIP opcodes are used", count));
+                Platform::SuperChip
+            }
+            (count, _) => {
+                reasons.push(format!("{} XO-CHIP opcodes are used", count));
+                Platform::XoChip
+            }
+        };
+
+        let mut quirks = platform.quirks();
+        let count = |name| {
+            self.findings
+                .iter()
+                .filter(|f| f.kind == Kind::Quirk(name))
+                .count()
+        };
+        if !self.shifts.is_empty() && self.shifts.iter().all(|&y| y == 0) {
+            quirks.shift = true;
+            reasons.push(format!(
+                "{} shifts have Y = 0, which is usual for code expecting VX to be shifted",
+                self.shifts.len()
+            ));
+        } else if !self.shifts.is_empty() {
+            quirks.shift = false;
+            reasons.push(format!(
+                "{} shifts have VY set, so VY is shifted as on the VIP",
+                self.shifts.len()
+            ));
+        }
+        if count("memoryLeaveIUnchanged") > 0 {
+            reasons.push(format!(
+                "I is used after {} FX55/FX65, the result depends on the memory quirks of {}",
+                count("memoryLeaveIUnchanged"),
+                platform
+            ));
+        }
+        if count("jump") > 0 {
+            reasons.push(format!(
+                "{} BNNN jumps depend on the jump quirk, their targets aren't analyzed",
+                count("jump")
+            ));
+        }
+        if count("wrap") > 0 {
+            let action = if quirks.wrap { "wrapped" } else { "clipped" };
+            reasons.push(format!(
+                "{} sprites cross the screen edge and are {}",
+                count("wrap"),
+                action
+            ));
+        }
+        Recommendation {
+            platform,
+            quirks,
+            reasons,
+        }
+    }
+}
+
+/// Parts of the rom between the blocks, the invalid opcodes left out. Parts
+/// with `data` bytes are data even if they decode as code
+fn unreachable(cfg: &Cfg, findings: &[Finding], data: &BTreeSet<usize>) -> Vec<Region> {
+    let mut reached = vec![false; cfg.rom_end()];
+    for block in cfg.blocks() {
+        let end = block.end.min(cfg.rom_end());
+        for byte in reached.iter_mut().take(end).skip(block.start) {
+            *byte = true;
+        }
+    }
+    for finding in findings.iter().filter(|f| f.kind == Kind::Invalid) {
+        for byte in reached.iter_mut().skip(finding.addr).take(2) {
+            *byte = true;
+        }
+    }
+
+    let mut regions: Vec<Region> = Vec::new();
+    for (addr, _) in reached
+        .iter()
+        .enumerate()
+        .skip(MEMORY_START)
+        .filter(|r| !r.1)
+    {
+        match regions.last_mut() {
+            Some(region) if region.end == addr => region.end += 1,
+            _ => regions.push(Region {
+                start: addr,
+                end: addr + 1,
+                code: true,
+            }),
+        }
+    }
+    for region in &mut regions {
+        region.code = data.range(region.start..region.end).next().is_none()
+            && (region.start..region.end).step_by(2).all(|addr| {
+                let word = cfg.word(addr).unwrap_or(0);
+                addr + 1 < region.end
+                    && (Instruction::decode(word).is_ok() || Extension::decode(word).is_some())
+            });
+    }
+    regions
+}
+
+pub fn analyze(cfg: &Cfg) -> Analysis {
+    let mut analyzer = Analyzer {
+        cfg,
+        findings: Vec::new(),
+        shifts: Vec::new(),
+        data: BTreeSet::new(),
+    };
+    let i_values = i_at_blocks(cfg);
+    for block in cfg.blocks() {
+        let i = i_values.get(&block.start).cloned().flatten();
+        analyzer.check_block(&cfg.code(block), block.exit, i);
+    }
+    analyzer.findings.sort_by_key(|f| f.addr);
+    let recommendation = analyzer.recommend();
+    Analysis {
+        unreachable: unreachable(cfg, &analyzer.findings, &analyzer.data),
+        findings: analyzer.findings,
+        recommendation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quirks() {
+        let cfg = Cfg::new(&[
+            0x81, 0x06, // 200: shr V1, V0
+            0xa3, 0x00, // 202: mov I, 300
+            0xf1, 0x65, // 204: movm V0-V1, I
+            0x60, 0x3c, // 206: mov V0, 3c
+            0xd0, 0x15, // 208: draw V0, V1, 5
+            0xb2, 0x00, // 20a: jmp V0, 200
+            0x12, 0x0c, // 20c: jmp 20c
+            0xff, 0xff, // 20e: data
+        ]);
+        let analysis = analyze(&cfg);
+        let kinds: Vec<_> = analysis.findings.iter().map(|f| (f.addr, f.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0x200, Kind::Quirk("shift")),
+                (0x204, Kind::Quirk("memoryLeaveIUnchanged")),
+                (0x208, Kind::Quirk("wrap")),
+                (0x20a, Kind::Quirk("jump")),
+            ]
+        );
+        assert_eq!(
+            analysis.unreachable,
+            vec![Region {
+                start: 0x20c,
+                end: 0x210,
+                code: false
+            }]
+        );
+        let recommendation = analysis.recommendation;
+        assert_eq!(recommendation.platform, Platform::Chip8);
+        assert!(recommendation.quirks.shift);
+        assert_eq!(
+            recommendation.options(),
+            "--platform chip8 --quirk shift=on"
+        );
+    }
+
+    #[test]
+    fn test_extensions() {
+        let cfg = Cfg::new(&[
+            0x00, 0xff, // 200: hires
+            0xf0, 0x00, 0x03, 0x00, // 202: long I load
+            0x85, 0x2e, // 206: shl V5, V2
+            0x5a, 0xb1, // 208: invalid
+        ]);
+        let analysis = analyze(&cfg);
+        let kinds: Vec<_> = analysis.findings.iter().map(|f| f.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Kind::Extension(Platform::SuperChip),
+                Kind::Extension(Platform::XoChip),
+                Kind::Quirk("shift"),
+                Kind::Invalid,
+            ]
+        );
+        assert!(analysis.unreachable.is_empty());
+        let recommendation = analysis.recommendation;
+        assert_eq!(recommendation.platform, Platform::XoChip);
+        assert!(!recommendation.quirks.shift);
+        assert_eq!(recommendation.options(), "--platform xochip");
+    }
+
+    #[test]
+    fn test_sprite_data() {
+        let cfg = Cfg::new(&[
+            0xa2, 0x0a, // 200: mov I, 20a
+            0x32, 0x00, // 202: skp V2 == 00
+            0xa2, 0x0c, // 204: mov I, 20c
+            0xd0, 0x12, // 206: draw V0, V1, 2
+            0x12, 0x08, // 208: jmp 208
+            0x60, 0x18, // 20a: sprite
+            0x3c, 0x3c, // 20c: sprite
+        ]);
+        let analysis = analyze(&cfg);
+        assert_eq!(
+            analysis.unreachable,
+            vec![Region {
+                start: 0x20a,
+                end: 0x20e,
+                code: false
+            }]
+        );
+    }
+}
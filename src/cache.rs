@@ -0,0 +1,194 @@
+//! Basic-block cache
+//!
+//! The cached engine decodes the instructions from pc up to the next jump,
+//! call or return once and keeps them by their start address. A block is
+//! left early when an instruction doesn't continue at the next address, so
+//! skips, draws waiting for the vertical blank and key waits end it like
+//! jumps do. Writes to memory a block was decoded from, i.e. self-modifying
+//! FX55 and FX33, drop the block.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::chip8::MEMORY_SIZE;
+use crate::instruction::Instruction;
+
+/// Longest block, in instructions
+pub(crate) const MAX_BLOCK_LEN: usize = 64;
+
+/// How instructions are executed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Fetches and decodes every instruction, the reference
+    #[default]
+    Interpreter,
+    /// Executes predecoded basic blocks
+    Cached,
+    /// Compiles hot blocks to x86-64
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Engine, String> {
+        match s {
+            "interpreter" => Ok(Engine::Interpreter),
+            "cached" => Ok(Engine::Cached),
+            #[cfg(feature = "jit")]
+            "jit" => Ok(Engine::Jit),
+            #[cfg(not(feature = "jit"))]
+            "jit" => Err("chiper is built without the jit feature".to_string()),
+            _ => Err(format!(
+                "Unknown engine '{}', use interpreter, cached or jit",
+                s
+            )),
+        }
+    }
+}
+
+/// Blocks of an engine by their start address, with the amount of bytes
+/// they were made from
+pub struct BlockCache<B> {
+    blocks: Vec<Option<(usize, B)>>,
+    /// Amount of blocks made from each address
+    covered: Vec<u8>,
+    /// A block was dropped since the last `take_invalidated`
+    invalidated: bool,
+}
+
+impl<B> Default for BlockCache<B> {
+    fn default() -> BlockCache<B> {
+        BlockCache {
+            blocks: (0..MEMORY_SIZE).map(|_| None).collect(),
+            covered: vec![0; MEMORY_SIZE],
+            invalidated: false,
+        }
+    }
+}
+
+impl<B> BlockCache<B> {
+    pub fn get(&self, addr: usize) -> Option<&B> {
+        self.blocks[addr].as_ref().map(|(_, block)| block)
+    }
+
+    /// Caches the block made from `size` bytes at `addr`
+    pub fn insert(&mut self, addr: usize, size: usize, block: B) {
+        for covered in &mut self.covered[addr..addr + size] {
+            *covered += 1;
+        }
+        self.blocks[addr] = Some((size, block));
+    }
+
+    /// Drops the blocks made from `addr` after it's written
+    pub fn invalidate(&mut self, addr: usize) {
+        if self.covered[addr] == 0 {
+            return;
+        }
+        let first = addr.saturating_sub(MAX_BLOCK_LEN * 2 - 1);
+        for start in first..=addr {
+            let size = match &self.blocks[start] {
+                Some((size, _)) if start + size > addr => *size,
+                _ => continue,
+            };
+            self.blocks[start] = None;
+            for covered in &mut self.covered[start..start + size] {
+                *covered -= 1;
+            }
+        }
+        self.invalidated = true;
+    }
+
+    /// Whether a block was dropped since the last call
+    pub fn take_invalidated(&mut self) -> bool {
+        std::mem::take(&mut self.invalidated)
+    }
+
+    pub fn clear(&mut self) {
+        *self = BlockCache::default();
+    }
+}
+
+impl BlockCache<Arc<[Instruction]>> {
+    /// Block of the cached engine starting at `addr`, decoded from `memory`
+    /// unless it's cached. None when the instruction at `addr` doesn't decode
+    pub fn decoded(&mut self, memory: &[u8], addr: usize) -> Option<Arc<[Instruction]>> {
+        if let Some(block) = self.get(addr) {
+            return Some(block.clone());
+        }
+        let block: Arc<[Instruction]> = decode(memory, addr).into();
+        if block.is_empty() {
+            return None;
+        }
+        self.insert(addr, block.len() * 2, block.clone());
+        Some(block)
+    }
+}
+
+/// Instructions from `addr` up to the first one never continuing at the
+/// next address, or up to an unknown opcode or a halt
+pub(crate) fn decode(memory: &[u8], addr: usize) -> Vec<Instruction> {
+    let mut block = Vec::new();
+    let mut addr = addr;
+    while block.len() < MAX_BLOCK_LEN && addr + 1 < MEMORY_SIZE {
+        let instruction = match Instruction::from_bytes(memory[addr], memory[addr + 1]) {
+            Ok(instruction) => instruction,
+            Err(_) => break,
+        };
+        // programs halt by jumping to themselves, the runners stop before
+        // the jump
+        if instruction == Instruction::Jump(addr as u16) && !block.is_empty() {
+            break;
+        }
+        block.push(instruction);
+        addr += 2;
+        if let Instruction::Jump(_)
+        | Instruction::Call(_)
+        | Instruction::Return
+        | Instruction::JumpV0(_) = instruction
+        {
+            break;
+        }
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate() {
+        let mut memory = [0; MEMORY_SIZE];
+        // 200: mov V0, 1; 202: add V0, 1; 204: jmp 200; 206: mov V1, 2
+        memory[0x200..0x208].copy_from_slice(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x00, 0x61, 0x02]);
+        let mut cache = BlockCache::default();
+        let block = cache.decoded(&memory, 0x200).unwrap();
+        assert_eq!(
+            &block[..],
+            &[
+                Instruction::MovImm(0, 1),
+                Instruction::AddImm(0, 1),
+                Instruction::Jump(0x200)
+            ]
+        );
+        assert_eq!(cache.decoded(&memory, 0x202).unwrap().len(), 2);
+
+        // the instruction after the jump is in no block
+        cache.invalidate(0x206);
+        assert!(!cache.take_invalidated());
+        cache.invalidate(0x203);
+        assert!(cache.take_invalidated());
+        assert!(cache.get(0x200).is_none() && cache.get(0x202).is_none());
+        assert!(cache.covered.iter().all(|&n| n == 0));
+
+        memory[0x202..0x204].copy_from_slice(&[0x12, 0x04]);
+        assert_eq!(cache.decoded(&memory, 0x200).unwrap().len(), 2);
+        memory[0x300..0x302].copy_from_slice(&[0xff, 0xff]);
+        assert!(cache.decoded(&memory, 0x300).is_none());
+        // a halt ends the block before it
+        memory[0x204..0x208].copy_from_slice(&[0x61, 0x02, 0x12, 0x06]);
+        assert_eq!(cache.decoded(&memory, 0x204).unwrap().len(), 1);
+    }
+}
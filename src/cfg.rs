@@ -0,0 +1,458 @@
+//! Control-flow graph
+//!
+//! Code is found by following the rom from its entry at 0x200: jumps, calls,
+//! returns and both outcomes of the skip instructions. BNNN jumps depend on
+//! V0 at run time, so they stay unresolved and code reached only through them
+//! is left out. Every address a jump, call, return or skip continues at
+//! starts a basic block.
+//!
+//! Every block belongs to a single function: the one with the lowest entry
+//! reaching it without following calls. A block shared by several functions,
+//! e.g. a common tail they jump to, is shown in the first of them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::chip8::{MEMORY_SIZE, MEMORY_START};
+use crate::instruction::{Extension, Instruction};
+
+/// How the control leaves a basic block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Continues with the block starting at the address
+    Next(usize),
+    Jump(usize),
+    /// Skip instruction, continues at `next` or skips to `skip`
+    Skip {
+        next: usize,
+        skip: usize,
+    },
+    /// Calls the subroutine at `target`, continues at `ret` after it returns
+    Call {
+        target: usize,
+        ret: usize,
+    },
+    Return,
+    /// BNNN, the target depends on V0
+    Indirect(u16),
+    /// 00FD exits the SUPER-CHIP interpreter
+    Halt,
+    /// Invalid opcode or the end of memory at the address
+    Invalid(usize),
+}
+
+impl Exit {
+    /// Addresses of the blocks the control can continue at, the return
+    /// address of a call included
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Next(addr) | Exit::Jump(addr) => vec![addr],
+            Exit::Skip { next, skip } => vec![next, skip],
+            Exit::Call { target, ret } => vec![target, ret],
+            Exit::Return | Exit::Indirect(_) | Exit::Halt | Exit::Invalid(_) => vec![],
+        }
+    }
+
+    /// Successors within the function, calls continue at their return
+    /// address
+    pub fn local_successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Call { ret, .. } => vec![ret],
+            _ => self.successors(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    /// Address after the last instruction, an invalid opcode is left out
+    pub end: usize,
+    pub exit: Exit,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    memory: Vec<u8>,
+    rom_end: usize,
+    blocks: BTreeMap<usize, Block>,
+    /// Entries of the subroutines, the rom entry included
+    functions: BTreeSet<usize>,
+    /// Function entry of every block
+    owners: BTreeMap<usize, usize>,
+}
+
+/// Mnemonic of the word, extensions are described
+fn mnemonic(word: u16) -> String {
+    match (Instruction::decode(word), Extension::decode(word)) {
+        (_, Some(extension)) => format!("{} ({})", extension.pattern, extension.description),
+        (Ok(instruction), None) => {
+            let text = instruction.to_string();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        }
+        (Err(_), None) => "??".to_string(),
+    }
+}
+
+/// Escapes the label of a DOT node
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Cfg {
+    /// Builds the graph of a rom loaded at 0x200
+    pub fn new(rom: &[u8]) -> Cfg {
+        let mut memory = vec![0; MEMORY_SIZE];
+        let rom = &rom[..rom.len().min(MEMORY_SIZE - MEMORY_START)];
+        memory[MEMORY_START..MEMORY_START + rom.len()].copy_from_slice(rom);
+        let mut cfg = Cfg {
+            memory,
+            rom_end: MEMORY_START + rom.len(),
+            blocks: BTreeMap::new(),
+            functions: BTreeSet::new(),
+            owners: BTreeMap::new(),
+        };
+        cfg.functions.insert(MEMORY_START);
+        let leaders = cfg.find_leaders();
+        for &start in &leaders {
+            let block = cfg.build_block(start, &leaders);
+            cfg.blocks.insert(start, block);
+        }
+        cfg.assign_owners();
+        cfg
+    }
+
+    /// Word at `addr`, none past the end of memory
+    pub fn word(&self, addr: usize) -> Option<u16> {
+        if addr + 1 >= MEMORY_SIZE {
+            return None;
+        }
+        Some((self.memory[addr] as u16) << 8 | self.memory[addr + 1] as u16)
+    }
+
+    /// Rom bytes, memory is zeroed past them
+    pub fn rom(&self) -> &[u8] {
+        &self.memory[MEMORY_START..self.rom_end]
+    }
+
+    pub fn rom_end(&self) -> usize {
+        self.rom_end
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    /// Block containing the instruction at `addr`
+    pub fn block_at(&self, addr: usize) -> Option<&Block> {
+        self.blocks
+            .range(..=addr)
+            .rev()
+            .map(|(_, block)| block)
+            .find(|block| addr < block.end)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.functions.iter().copied()
+    }
+
+    /// Entry of the function the block starting at `start` belongs to
+    pub fn function_of(&self, start: usize) -> Option<usize> {
+        self.owners.get(&start).copied()
+    }
+
+    /// Blocks of the function with the entry at `entry`
+    pub fn function_blocks(&self, entry: usize) -> impl Iterator<Item = &Block> {
+        self.blocks
+            .values()
+            .filter(move |block| self.owners.get(&block.start) == Some(&entry))
+    }
+
+    fn assign_owners(&mut self) {
+        // entries always belong to their own functions
+        for &entry in &self.functions {
+            self.owners.insert(entry, entry);
+        }
+        for &entry in &self.functions {
+            let mut pending = vec![entry];
+            let mut visited = BTreeSet::new();
+            while let Some(start) = pending.pop() {
+                if !visited.insert(start) {
+                    continue;
+                }
+                if *self.owners.entry(start).or_insert(entry) != entry {
+                    continue;
+                }
+                if let Some(block) = self.blocks.get(&start) {
+                    pending.extend(block.exit.local_successors());
+                }
+            }
+        }
+    }
+
+    /// Graphviz DOT graph with a cluster per function, blocks are labeled
+    /// with their disassembly
+    pub fn dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for entry in self.functions() {
+            let name = if entry == MEMORY_START {
+                "main".to_string()
+            } else {
+                format!("sub_{:03x}", entry)
+            };
+            writeln!(dot, "    subgraph cluster_{:03x} {{", entry).unwrap();
+            writeln!(dot, "        label=\"{}\";", name).unwrap();
+            for block in self.function_blocks(entry) {
+                writeln!(dot, "        {}", self.dot_node(block)).unwrap();
+            }
+            dot.push_str("    }\n");
+        }
+        for block in self.blocks() {
+            self.dot_edges(block, &mut dot);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn dot_node(&self, block: &Block) -> String {
+        let mut label = String::new();
+        for (addr, word) in self.code(block) {
+            label.push_str(&format!("{:04x}: {}\\l", addr, escape(&mnemonic(word))));
+        }
+        let color = match block.exit {
+            Exit::Invalid(addr) => {
+                let word = self
+                    .word(addr)
+                    .map_or("end of memory".to_string(), |w| format!("{:04x}", w));
+                label.push_str(&format!("{:04x}: invalid {}\\l", addr, word));
+                ", color=red"
+            }
+            _ => "",
+        };
+        format!("b{:03x} [label=\"{}\"{}];", block.start, label, color)
+    }
+
+    fn dot_edges(&self, block: &Block, dot: &mut String) {
+        let from = block.start;
+        match block.exit {
+            Exit::Next(to) | Exit::Jump(to) => {
+                writeln!(dot, "    b{:03x} -> b{:03x};", from, to).unwrap()
+            }
+            Exit::Skip { next, skip } => {
+                writeln!(dot, "    b{:03x} -> b{:03x};", from, next).unwrap();
+                writeln!(dot, "    b{:03x} -> b{:03x} [label=\"skip\"];", from, skip).unwrap();
+            }
+            Exit::Call { target, ret } => {
+                writeln!(dot, "    b{:03x} -> b{:03x};", from, ret).unwrap();
+                writeln!(dot, "    b{:03x} -> b{:03x} [style=dashed];", from, target).unwrap();
+            }
+            Exit::Indirect(nnn) => {
+                writeln!(
+                    dot,
+                    "    i{:03x} [label=\"{:03x} + V0\", shape=diamond, style=dashed];",
+                    from, nnn
+                )
+                .unwrap();
+                writeln!(
+                    dot,
+                    "    b{:03x} -> i{:03x} [label=\"unresolved\", style=dashed];",
+                    from, from
+                )
+                .unwrap();
+            }
+            Exit::Return | Exit::Halt | Exit::Invalid(_) => (),
+        }
+    }
+
+    /// Addresses and words of the instructions of the block
+    pub fn code(&self, block: &Block) -> Vec<(usize, u16)> {
+        let mut code = Vec::new();
+        let mut addr = block.start;
+        while addr < block.end {
+            // blocks end before the end of memory
+            let word = self.word(addr).unwrap();
+            code.push((addr, word));
+            addr += Extension::size(word);
+        }
+        code
+    }
+
+    /// Address of the instruction after the one at `addr`, or the exit when
+    /// the instruction ends a block
+    fn step(&self, addr: usize) -> Result<usize, Exit> {
+        // the interpreter area below the rom has no code
+        let word = match self.word(addr) {
+            Some(word) if addr >= MEMORY_START => word,
+            _ => return Err(Exit::Invalid(addr)),
+        };
+        let next = addr + Extension::size(word);
+        let instruction = match Instruction::decode(word) {
+            Ok(instruction) => instruction,
+            Err(_) if Extension::decode(word).is_some() => return Ok(next),
+            Err(_) => return Err(Exit::Invalid(addr)),
+        };
+        match instruction {
+            Instruction::Sys(0x0fd) => Err(Exit::Halt),
+            Instruction::Jump(nnn) => Err(Exit::Jump(nnn as usize)),
+            Instruction::Call(nnn) => Err(Exit::Call {
+                target: nnn as usize,
+                ret: next,
+            }),
+            Instruction::Return => Err(Exit::Return),
+            Instruction::JumpV0(nnn) => Err(Exit::Indirect(nnn)),
+            Instruction::SkipEqImm(..)
+            | Instruction::SkipNeImm(..)
+            | Instruction::SkipEqReg(..)
+            | Instruction::SkipNeReg(..)
+            | Instruction::SkipKey(_)
+            | Instruction::SkipNotKey(_) => {
+                let size = self.word(next).map_or(2, Extension::size);
+                Err(Exit::Skip {
+                    next,
+                    skip: next + size,
+                })
+            }
+            _ => Ok(next),
+        }
+    }
+
+    /// Follows the code from the entry, returns the starts of the blocks
+    fn find_leaders(&mut self) -> BTreeSet<usize> {
+        let mut leaders = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![MEMORY_START];
+        leaders.insert(MEMORY_START);
+        while let Some(addr) = pending.pop() {
+            if !visited.insert(addr) {
+                continue;
+            }
+            match self.step(addr) {
+                Ok(next) => pending.push(next),
+                Err(exit) => {
+                    if let Exit::Call { target, .. } = exit {
+                        self.functions.insert(target);
+                    }
+                    for addr in exit.successors() {
+                        leaders.insert(addr);
+                        pending.push(addr);
+                    }
+                }
+            }
+        }
+        leaders
+    }
+
+    fn build_block(&self, start: usize, leaders: &BTreeSet<usize>) -> Block {
+        let mut addr = start;
+        loop {
+            match self.step(addr) {
+                Ok(next) if leaders.contains(&next) => {
+                    return Block {
+                        start,
+                        end: next,
+                        exit: Exit::Next(next),
+                    }
+                }
+                Ok(next) => addr = next,
+                Err(exit) => {
+                    let end = match exit {
+                        Exit::Invalid(addr) => addr,
+                        _ => addr + Extension::size(self.word(addr).unwrap()),
+                    };
+                    return Block { start, end, exit };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        let cfg = Cfg::new(&[
+            0x60, 0x00, // 200: mov V0, 00
+            0x22, 0x0a, // 202: call 20a
+            0x30, 0x05, // 204: skipifeq V0, 05
+            0x12, 0x02, // 206: jmp 202
+            0x12, 0x08, // 208: jmp 208
+            0x70, 0x01, // 20a: add V0, 01
+            0x00, 0xee, // 20c: ret
+            0xb3, 0x00, // 20e: jmp V0, 300
+        ]);
+        let blocks: Vec<_> = cfg.blocks().map(|b| (b.start, b.end, b.exit)).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                (0x200, 0x202, Exit::Next(0x202)),
+                (
+                    0x202,
+                    0x204,
+                    Exit::Call {
+                        target: 0x20a,
+                        ret: 0x204
+                    }
+                ),
+                (
+                    0x204,
+                    0x206,
+                    Exit::Skip {
+                        next: 0x206,
+                        skip: 0x208
+                    }
+                ),
+                (0x206, 0x208, Exit::Jump(0x202)),
+                (0x208, 0x20a, Exit::Jump(0x208)),
+                (0x20a, 0x20e, Exit::Return),
+            ]
+        );
+        assert_eq!(cfg.functions().collect::<Vec<_>>(), vec![0x200, 0x20a]);
+        assert_eq!(cfg.block_at(0x20c).unwrap().start, 0x20a);
+        assert!(cfg.block_at(0x20e).is_none());
+        assert_eq!(cfg.code(cfg.block(0x20a).unwrap()).len(), 2);
+        assert_eq!(cfg.function_of(0x206), Some(0x200));
+        assert_eq!(cfg.function_of(0x20a), Some(0x20a));
+        assert_eq!(cfg.function_blocks(0x200).count(), 5);
+    }
+
+    #[test]
+    fn test_dot() {
+        let cfg = Cfg::new(&[
+            0x22, 0x06, // 200: call 206
+            0x3f, 0x00, // 202: skipifeq VF, 00
+            0xb3, 0x00, // 204: jmp V0, 300
+            0x00, 0xee, // 206: ret
+        ]);
+        let dot = cfg.dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("subgraph cluster_206 {\n        label=\"sub_206\";\n"));
+        assert!(dot.contains("b206 [label=\"0206: ret\\l\"];"));
+        assert!(dot.contains("b200 -> b206 [style=dashed];"));
+        assert!(dot.contains("b202 -> b206 [label=\"skip\"];"));
+        assert!(dot.contains("b204 -> i204 [label=\"unresolved\", style=dashed];"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        // skipping over the 4-byte F000 NNNN, then an invalid opcode
+        let cfg = Cfg::new(&[0x3f, 0x00, 0xf0, 0x00, 0x12, 0x00, 0xff, 0xff]);
+        let skip = cfg.block(0x200).unwrap();
+        assert_eq!(
+            skip.exit,
+            Exit::Skip {
+                next: 0x202,
+                skip: 0x206
+            }
+        );
+        assert_eq!(cfg.block(0x202).unwrap().exit, Exit::Next(0x206));
+        let invalid = cfg.block(0x206).unwrap();
+        assert_eq!((invalid.end, invalid.exit), (0x206, Exit::Invalid(0x206)));
+    }
+}
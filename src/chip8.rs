@@ -1,8 +1,25 @@
-use std::fs::File;
-use std::io::{self, stdin, Read, Write};
-use std::time::SystemTime;
-
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, stdin, Read};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cache::{BlockCache, Engine};
+use crate::coverage::Coverage;
+use crate::database::{Database, Detection};
+use crate::debugger::Debugger;
+use crate::framebuffer::{columns, Lores, Row};
+use crate::instruction::Instruction;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::overlay::Overlay;
+use crate::platform::Quirks;
+use crate::profile::Profiler;
 use crate::screen::Screen;
+use crate::trace::Tracer;
 
 /*
  * Memory mapping, total 4k (0x1000)
@@ -12,251 +29,333 @@ use crate::screen::Screen;
  * ---------------------------------------------------------------
  */
 
-const MEMORY_START: usize = 0x200;
-const MEMORY_SIZE: usize = 0x1000;
+pub(crate) const MEMORY_START: usize = 0x200;
+pub(crate) const MEMORY_SIZE: usize = 0x1000;
 const PROGRAM_MEMORY_SIZE: usize = 0x0ca0;
 pub const SCREEN_WIDTH: u32 = 64;
 pub const SCREEN_HEIGHT: u32 = 32;
 
-const STACK_MEMORY_END: usize = 0xf00;
+pub(crate) const STACK_MEMORY_START: usize = 0xea0;
+pub(crate) const STACK_MEMORY_END: usize = 0xf00;
 const SCREEN_MEMORY_START: usize = 0xf00;
+const FONT_START: usize = 0x0;
+const FONT_SPRITE_SIZE: usize = 5;
 //const SCREEN_MEMORY_END: u32 = 0xfff;
 
-// TODO: use logger?
-#[cfg(debug_assertions)]
-macro_rules! debug {
-    ($( $args:expr ),*) => { print!( $( $args ),* ); }
+/// Log targets, e.g. `CHIPER_LOG=chiper::cpu=trace` logs every executed
+/// instruction
+const LOG_CPU: &str = "chiper::cpu";
+const LOG_DRAW: &str = "chiper::draw";
+const LOG_LOADER: &str = "chiper::loader";
+const LOG_TRACE: &str = "chiper::trace";
+
+/// Timers are decremented at 60Hz
+pub const FRAMES_PER_SECOND: u32 = 60;
+/// Default amount of instructions executed between two timer ticks
+pub const TICKS_PER_FRAME: u64 = 10;
+
+/// Header of saved state files
+const STATE_MAGIC: &[u8; 8] = b"CHIPER\0\x02";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
 }
 
-#[cfg(not(debug_assertions))]
-macro_rules! debug {
-    ($( $args:expr ),*) => {};
+/// Data memory access made by an instruction, instruction fetches are not
+/// recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: usize,
+    pub kind: Access,
+    /// Value read or written
+    pub value: u8,
 }
 
-#[derive(Debug)]
-struct Opcode(u8, u8);
+/// Why an instruction couldn't be executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The word at pc isn't an instruction
+    UnknownOpcode,
+    /// pc is past the end of memory
+    PcOutOfMemory,
+    /// Memory from the address on is accessed past its end
+    MemoryOutOfRange(usize),
+    /// A call with the stack full
+    StackOverflow,
+    /// A return with the stack empty
+    StackUnderflow,
+}
 
-impl Opcode {
-    fn high_nib(byte: u8) -> u8 {
-        byte >> 4
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::UnknownOpcode => write!(f, "Unknown opcode"),
+            Fault::PcOutOfMemory => write!(f, "pc is past the end of memory"),
+            Fault::MemoryOutOfRange(addr) => {
+                write!(f, "Access from {:04x} runs past the end of memory", addr)
+            }
+            Fault::StackOverflow => write!(f, "Stack overflow"),
+            Fault::StackUnderflow => write!(f, "Return with an empty stack"),
+        }
     }
+}
 
-    fn low_nib(byte: u8) -> u8 {
-        byte & 0x0f
-    }
+/// Instruction the emulation stopped at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecError {
+    pub pc: usize,
+    pub opcode: u16,
+    pub fault: Fault,
+}
 
-    // return as usize since it's used only as index for V[] registers
-    fn x(&self) -> usize {
-        Opcode::low_nib(self.0).into()
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {:04x} ({:04x})", self.fault, self.pc, self.opcode)
     }
+}
+
+impl Error for ExecError {}
+
+/// Hexadecimal digits sprites, stored in the interpreter area
+const FONT: [u8; 16 * FONT_SPRITE_SIZE] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xf0, 0x10, 0xf0, 0x80, 0xf0, // 2
+    0xf0, 0x10, 0xf0, 0x10, 0xf0, // 3
+    0x90, 0x90, 0xf0, 0x10, 0x10, // 4
+    0xf0, 0x80, 0xf0, 0x10, 0xf0, // 5
+    0xf0, 0x80, 0xf0, 0x90, 0xf0, // 6
+    0xf0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xf0, 0x90, 0xf0, 0x90, 0xf0, // 8
+    0xf0, 0x90, 0xf0, 0x10, 0xf0, // 9
+    0xf0, 0x90, 0xf0, 0x90, 0x90, // A
+    0xe0, 0x90, 0xe0, 0x90, 0xe0, // B
+    0xf0, 0x80, 0x80, 0x80, 0xf0, // C
+    0xe0, 0x90, 0x90, 0x90, 0xe0, // D
+    0xf0, 0x80, 0xf0, 0x80, 0xf0, // E
+    0xf0, 0x80, 0xf0, 0x80, 0x80, // F
+];
 
-    // return as usize since it's used only as index for V[] registers
-    fn y(&self) -> usize {
-        Opcode::high_nib(self.1).into()
+fn rand(seed: u64) -> u64 {
+    // https://en.wikipedia.org/wiki/Xorshift
+    let mut rnd = seed;
+    rnd ^= rnd << 13;
+    rnd ^= rnd >> 7;
+    rnd ^= rnd << 17;
+    rnd
+}
+
+/// Fails when the `len` bytes from `addr` on don't fit in memory
+fn check_range(addr: usize, len: usize) -> Result<(), Fault> {
+    match addr.checked_add(len) {
+        Some(end) if end <= MEMORY_SIZE => Ok(()),
+        _ => Err(Fault::MemoryOutOfRange(addr)),
     }
+}
 
-    fn n(&self) -> u8 {
-        Opcode::low_nib(self.1)
+/// Copy of the whole machine state, the screen is restored from the screen
+/// memory
+#[derive(Clone)]
+pub struct Snapshot {
+    v: [u8; 16],
+    i: u16,
+    sp: usize,
+    pc: usize,
+    dt: u8,
+    st: u8,
+    memory: [u8; MEMORY_SIZE],
+    seed: u64,
+    cycles: u64,
+    /// Pressed keys, the program sees the same input when it's restored
+    keys: [bool; 16],
+}
+
+impl Snapshot {
+    /// Amount of instructions executed before the snapshot was taken
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
 
-    fn nnn(&self) -> u16 {
-        (Opcode::low_nib(self.0) as u16) << 8 | self.1 as u16
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
     }
 
-    fn disassemble(&self, pc: usize) {
-        debug!("{:04x}:\t{:02x} {:02x}\t", pc, self.0, self.1);
-        match Opcode::high_nib(self.0) {
-            0x00 => match self.1 {
-                0xe0 => {
-                    debug!("dclr");
-                }
-                0xee => {
-                    debug!("ret");
-                }
-                _ => {
-                    debug!("UNKNOWN");
-                }
-            },
-            0x01 => {
-                // Jumps to address NNN.
-                debug!("jmp\t\t{:03x}", self.nnn());
-            }
-            0x02 => {
-                debug!("call\t\t{:03x}", self.nnn());
-            }
-            0x03 => {
-                // Skips the next instruction if VX equals NN.
-                // Usually the next instruction is a jump to skip a code block
-                debug!("skipifeq\t\tV{:01x}, {:02x}", self.x(), self.1);
-            }
-            0x04 => {
-                // Skips the next instruction if VX doesn't equal NN. (Usually the next instruction
-                // is a jump to skip a code block)
-                debug!("skipifne\t\tV{:01x}, {:02x}", self.x(), self.1);
-            }
-            0x05 => {
-                // Skips the next instruction if VX equals VY.
-                // Usually the next instruction is a jump to skip a code block
-                debug!("skipifeq\t\tV{:01x}, V{:01x}", self.x(), self.y());
-            }
-            0x06 => {
-                // Sets VX to NN
-                debug!("mov\t\tV{:01x}, {:02x}", self.x(), self.1);
-            }
-            0x07 => {
-                // Adds NN to VX. (Carry flag is not changed)
-                debug!("add\t\tV{:01x}, {:02x}", self.x(), self.1);
-            }
-            0x08 => {
-                match Opcode::low_nib(self.1) {
-                    0x0 => {
-                        // Sets VX to the value of VY.
-                        debug!("mov\t\tV{:01x}, V{:01x}", self.x(), self.y());
-                    }
-                    0x1 => {
-                        // Sets VX to VX or VY. (Bitwise OR operation)
-                        debug!("or\t\tV{:01x}, V{:01x}", self.x(), self.y());
-                    }
-                    0x2 => {
-                        // Sets VX to VX and VY. (Bitwise AND operation)
-                        debug!("and\t\tV{:01x}, V{:01x}", self.x(), self.y());
-                    }
-                    0x3 => {
-                        // Sets VX to VX xor VY.
-                        debug!("xor\t\tV{:01x}, V{:01x}", self.x(), self.y());
-                    }
-                    0x4 => {
-                        // Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-                        debug!("addwc\t\tV{:01x}, V{:01x}", self.x(), self.y());
-                    }
-                    0x5 => {
-                        // VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                        debug!("subwc\t\tV{:01x}, V{:01x}", self.x(), self.y());
-                    }
-                    0x6 => {
-                        // Stores the least significant bit of VX in VF and then shifts VX to the right by 1
-                        debug!("shr\t\tV{:01x}", self.x());
-                    }
-                    0x7 => {
-                        // Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                        debug!(
-                            "subwc\t\tV{:01x}, V{:01x}, V{:01x}",
-                            self.x(),
-                            self.y(),
-                            self.x()
-                        );
-                    }
-                    0xe => {
-                        // Stores the most significant bit of VX in VF and then shifts VX to the left by 1
-                        debug!("shl\t\tV{:01x}", self.x());
-                    }
-                    _ => {
-                        debug!("UNKNOWN");
-                    }
-                }
-            }
-            0x0a => {
-                //Sets I to the address NNN
+    /// Serializes the state, multibyte values are big endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = STATE_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.v);
+        bytes.extend_from_slice(&self.i.to_be_bytes());
+        bytes.extend_from_slice(&(self.sp as u16).to_be_bytes());
+        bytes.extend_from_slice(&(self.pc as u16).to_be_bytes());
+        bytes.push(self.dt);
+        bytes.push(self.st);
+        bytes.extend_from_slice(&self.seed.to_be_bytes());
+        bytes.extend_from_slice(&self.cycles.to_be_bytes());
+        let keys = (0..16).fold(0u16, |bits, key| bits | (self.keys[key] as u16) << key);
+        bytes.extend_from_slice(&keys.to_be_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes
+    }
 
-                debug!("mov\t\tI, {:03x}", self.nnn());
-            }
-            0x0c => {
-                debug!("rnd\t\tV{:01x}", self.x());
-            }
-            0x0d => {
-                // draw(Vx,Vy,N)
-                debug!(
-                    "draw\t\tV{:01x}, V{:01x}, {:01x}",
-                    self.x(),
-                    self.y(),
-                    self.n()
-                );
-            }
-            0x0f => match self.1 {
-                0x1e => {
-                    // Adds VX to I. VF is not affected
-                    debug!("add\t\tI, V{:01x}", self.x());
-                }
-                0x55 => {
-                    // Stores V0 to VX (including VX) in memory starting at
-                    // address I. The offset from I is increased by 1 for each
-                    // value written, but I itself is left unmodified
-                    debug!("movm\t\tI, V0-V{:01x}", self.x());
-                }
-                0x65 => {
-                    // Fills V0 to VX (including VX) with values from memory
-                    // starting at address I. The offset from I is increased by
-                    // 1 for each value written, but I itself is left unmodified
-                    debug!("movm\t\tV0-V{:01x}, I", self.x());
-                }
-                _ => {
-                    debug!("Opcode is not handled yet");
-                }
-            },
-            _ => {
-                debug!("Opcode is not handled yet");
-            }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, String> {
+        let rest = bytes
+            .strip_prefix(&STATE_MAGIC[..])
+            .ok_or("Not a chiper state file")?;
+        if rest.len() != 16 + 2 * 3 + 2 + 8 * 2 + 2 + MEMORY_SIZE {
+            return Err(format!("Invalid state size: {} bytes", bytes.len()));
+        }
+        let (v, rest) = rest.split_at(16);
+        let (regs, rest) = rest.split_at(2 * 3 + 2);
+        let (counters, rest) = rest.split_at(8 * 2);
+        let (keys, memory) = rest.split_at(2);
+        let keys = u16::from_be_bytes([keys[0], keys[1]]);
+        let word = |idx: usize| u16::from_be_bytes([regs[idx], regs[idx + 1]]);
+        let long = |idx: usize| u64::from_be_bytes(counters[idx..idx + 8].try_into().unwrap());
+        let snapshot = Snapshot {
+            v: v.try_into().unwrap(),
+            i: word(0),
+            sp: word(2) as usize,
+            pc: word(4) as usize,
+            dt: regs[6],
+            st: regs[7],
+            memory: memory.try_into().unwrap(),
+            seed: long(0),
+            cycles: long(8),
+            keys: std::array::from_fn(|key| keys & 1 << key != 0),
+        };
+        if snapshot.pc + 1 >= MEMORY_SIZE || snapshot.sp > STACK_MEMORY_END {
+            return Err("Invalid registers in state file".to_string());
         }
-        debug!("\n");
+        Ok(snapshot)
     }
 }
 
-fn rand(seed: u64) -> u64 {
-    // https://en.wikipedia.org/wiki/Xorshift
-    let mut rnd = seed;
-    rnd ^= rnd << 13;
-    rnd ^= rnd >> 7;
-    rnd ^= rnd << 17;
-    rnd
+/// Code generated by `chiper recompile`, runs the block at pc and returns
+/// false when there's no compiled block for it
+pub type Compiled<T> = fn(&mut Chip8<T>) -> Result<bool, ExecError>;
+
+/// Executed draws and the time spent in them
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrawTiming {
+    pub draws: u64,
+    pub time: Duration,
+}
+
+/// What an iteration of a loop without side effects does depends on
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct LoopState {
+    pc: usize,
+    v: [u8; 16],
+    i: u16,
+    sp: usize,
+    keys: [bool; 16],
 }
 
 pub struct Chip8<T> {
     ///  16 8-bit data registers named V0 to VF
-    v: [u8; 16],
+    pub(crate) v: [u8; 16],
     /// Memory address register
-    i: u16,
+    pub(crate) i: u16,
     /// Stack pointer
-    sp: usize,
+    pub(crate) sp: usize,
     /// Program counter
-    pc: usize,
-    /*
-       uint8_t     delay;
-       uint8_t     sound;
-       uint8_t     *screen;  //this is memory[0xF00];
-    */
-    /// RAM
-    memory: [u8; MEMORY_SIZE],
+    pub(crate) pc: usize,
+    /// Delay timer
+    pub(crate) dt: u8,
+    /// Sound timer
+    pub(crate) st: u8,
+    /// RAM, screen is mapped to memory[0xF00]
+    pub(crate) memory: [u8; MEMORY_SIZE],
     /// amount of memory occupied by rom
-    used_memory: usize,
+    pub(crate) used_memory: usize,
 
     screen: T,
 
+    /// Pixels, mirrored to the display memory
+    framebuffer: Lores,
+
     /// Seed for a random number generator
     seed: u64,
+    /// Behaviour of ambiguous instructions
+    quirks: Quirks,
+    /// Instructions executed between two timer ticks
+    pub(crate) ticks_per_frame: u64,
+    /// Amount of executed instructions
+    pub(crate) cycles: u64,
+    /// Memory accessed by the last executed instruction
+    accesses: Vec<MemAccess>,
+    /// State of the hex keypad, true for pressed keys
+    pub(crate) keys: [bool; 16],
+    /// Records every executed instruction
+    pub(crate) tracer: Option<Tracer>,
+    /// Collects execution statistics
+    pub(crate) profiler: Option<Profiler>,
+    /// Marks executed and accessed memory
+    coverage: Option<Coverage>,
+    /// Measures the draws
+    draw_timing: Option<DrawTiming>,
+    /// Entry of the rom database matching the loaded rom
+    detection: Option<Detection>,
+    /// Executes the instructions in `run_frames` and `emulate`
+    engine: Engine,
+    /// Decoded blocks of the cached engine
+    cache: BlockCache<Arc<[Instruction]>>,
+    /// Native blocks of the JIT engine
+    #[cfg(feature = "jit")]
+    jit: Jit,
+    /// Cycle and state when a loop last went back to its start, dropped by
+    /// every side effect
+    idle: Option<(u64, LoopState)>,
 }
 
 impl<T: Screen> Chip8<T> {
     pub fn new(screen: T) -> Chip8<T> {
+        let mut memory = [0; MEMORY_SIZE];
+        memory[FONT_START..FONT_START + FONT.len()].copy_from_slice(&FONT);
         Chip8 {
             v: [0; 16],
             i: 0,
             sp: STACK_MEMORY_END,
             pc: MEMORY_START,
-            memory: [0; MEMORY_SIZE],
+            dt: 0,
+            st: 0,
+            memory,
             used_memory: 0,
             screen,
+            framebuffer: Lores::default(),
             seed: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .expect("Time go backwards!")
                 .as_secs(),
+            quirks: Quirks::default(),
+            ticks_per_frame: TICKS_PER_FRAME,
+            cycles: 0,
+            accesses: Vec::new(),
+            keys: [false; 16],
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            draw_timing: None,
+            detection: None,
+            engine: Engine::default(),
+            cache: BlockCache::default(),
+            #[cfg(feature = "jit")]
+            jit: Jit::default(),
+            idle: None,
         }
     }
 
-    fn load_from_slice(&mut self, slice: &[u8]) {
+    pub(crate) fn load_from_slice(&mut self, slice: &[u8]) {
         // CHIP-8 convention puts programs in memory at `MEMORY_START`
         // They will all have hardcoded addresses expecting that
         self.memory[MEMORY_START..MEMORY_START + slice.len()].copy_from_slice(slice);
         self.used_memory = slice.len();
+        self.idle = None;
+        self.cache.clear();
+        #[cfg(feature = "jit")]
+        self.jit.clear();
     }
 
     pub fn load_rom(&mut self, rom_path: &str) -> io::Result<()> {
@@ -267,16 +366,63 @@ impl<T: Screen> Chip8<T> {
         file.read_to_end(&mut buffer)?;
 
         if buffer.len() >= PROGRAM_MEMORY_SIZE {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Program size is too big!",
-            ));
+            log::error!(target: LOG_LOADER, "{} is {} bytes, at most {} fit into memory",
+                rom_path, buffer.len(), PROGRAM_MEMORY_SIZE - 1);
+            return Err(io::Error::other("Program size is too big!"));
         }
 
         self.load_from_slice(&buffer);
+        log::info!(target: LOG_LOADER, "Loaded {} bytes from {}", buffer.len(), rom_path);
+        // known roms get quirks and speed they were written for, both can
+        // be overridden afterwards
+        self.detection = Database::bundled().detect(&buffer);
+        if let Some(detection) = &self.detection {
+            log::info!(target: LOG_LOADER, "Detected {}", detection);
+            self.quirks = detection.quirks;
+            if let Some(ticks) = detection.tickrate {
+                self.set_ticks_per_frame(ticks);
+            }
+        }
         Ok(())
     }
 
+    /// Entry of the rom database found by `load_rom`
+    pub fn detection(&self) -> Option<&Detection> {
+        self.detection.as_ref()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.idle = None;
+        // native code has the quirks compiled in
+        #[cfg(feature = "jit")]
+        self.jit.clear();
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Sets amount of instructions executed per frame, i.e. 60 times a second
+    pub fn set_ticks_per_frame(&mut self, ticks: u64) {
+        assert!(ticks > 0, "At least one instruction per frame is required");
+        self.ticks_per_frame = ticks;
+        self.idle = None;
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        self.cache.clear();
+        #[cfg(feature = "jit")]
+        self.jit.clear();
+    }
+
+    /// Seeds the random number generator making RND repeatable, zero is
+    /// replaced as xorshift would only produce zeroes
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed.max(1);
+    }
+
     /// Dump all Chip8 registers, but not memory
     pub fn dump_registers(&self) {
         print!("REGISTERS:\n");
@@ -289,20 +435,43 @@ impl<T: Screen> Chip8<T> {
         print!("I  = {:02x?}\n", self.i);
         print!("SP = {:02x?}\n", self.sp);
         print!("PC = {:02x?}\n", self.pc);
+        print!("DT = {:02x?}\n", self.dt);
+        print!("ST = {:02x?}\n", self.st);
     }
 
-    pub fn dump_memory(&self) {
-        let mut pc = MEMORY_START;
+    /// End of the memory occupied by rom, aligned to instruction size
+    pub(crate) fn rom_end(&self) -> usize {
         let mut memory_end = MEMORY_START + self.used_memory;
         // ensure that we are 2 bytes alighned
         // XXX: not sure it's usefull for actual program or just for debugging
-        if memory_end % 2 != 0 {
+        if !memory_end.is_multiple_of(2) {
             memory_end += 1;
         }
-        for two_bytes in self.memory[MEMORY_START..memory_end].chunks(2) {
-            let opcode = Opcode(two_bytes[0], two_bytes[1]);
-            opcode.disassemble(pc);
-            pc += 2;
+        memory_end
+    }
+
+    /// Prints disassembly of the loaded rom
+    pub fn dump_memory(&self) {
+        print!("{}", self.listing());
+    }
+
+    /// Disassembly of the rom, one instruction per line
+    pub fn listing(&self) -> String {
+        (MEMORY_START..self.rom_end())
+            .step_by(2)
+            .map(|addr| self.disassembly_line(addr) + "\n")
+            .collect()
+    }
+
+    /// Address, opcode and mnemonic of the instruction at `addr`
+    pub fn disassembly_line(&self, addr: usize) -> String {
+        if addr + 1 >= MEMORY_SIZE {
+            return format!("{:04x}:\t??", addr);
+        }
+        let (high, low) = (self.memory[addr], self.memory[addr + 1]);
+        match Instruction::from_bytes(high, low) {
+            Ok(ins) => format!("{:04x}:\t{:02x} {:02x}\t{}", addr, high, low, ins),
+            Err(_) => format!("{:04x}:\t{:02x} {:02x}\tUNKNOWN", addr, high, low),
         }
     }
 
@@ -310,179 +479,643 @@ impl<T: Screen> Chip8<T> {
         self.pc += 2;
     }
 
-    fn emulate_op(&mut self) {
-        let opcode = Opcode(self.memory[self.pc], self.memory[self.pc + 1]);
-        opcode.disassemble(self.pc);
+    fn read_mem(&mut self, addr: usize) -> u8 {
+        let value = self.memory[addr];
+        self.accesses.push(MemAccess {
+            addr,
+            kind: Access::Read,
+            value,
+        });
+        value
+    }
 
-        let mut pc_inc_required = true;
+    fn write_mem(&mut self, addr: usize, value: u8) {
+        self.memory[addr] = value;
+        self.idle = None;
+        self.cache.invalidate(addr);
+        if addr >= SCREEN_MEMORY_START {
+            // keeps the pixels in sync with the display memory
+            let y = (addr - SCREEN_MEMORY_START) / 8;
+            let line = SCREEN_MEMORY_START + y * 8;
+            let row = u64::from_bytes(&self.memory[line..line + 8]);
+            self.framebuffer.set_row(y as u32, row);
+        }
+        #[cfg(feature = "jit")]
+        self.jit.invalidate(addr);
+        self.accesses.push(MemAccess {
+            addr,
+            kind: Access::Write,
+            value,
+        });
+    }
 
-        match Opcode::high_nib(opcode.0) {
-            0x00 => match opcode.1 {
-                0xe0 => self.op_disp_clear(),
-                0xee => {
-                    //Returns from a subroutine.
-
-                    // restore pc from the stack memory
-                    self.pc = (((self.memory[self.sp] as u16) << 8)
-                        | (self.memory[self.sp + 1]) as u16) as usize;
-                    // increase stack size back
-                    self.sp += 2;
-                    pc_inc_required = false;
-                }
-                _ => unimplemented!(),
-            },
-            0x01 => {
-                // Jumps to address NNN.
-                let target = opcode.nnn();
-                if target as usize == self.pc {
-                    print!("Press ENTER to exit..\n");
-                    let mut buffer = [0];
-                    stdin().read_exact(&mut buffer).unwrap();
-                    std::process::exit(0);
+    /// Writes `data` to memory from `addr` on for debuggers, the pixels and
+    /// the compiled code follow the new contents. The writes aren't
+    /// recorded as accesses
+    pub fn poke_memory(&mut self, addr: usize, data: &[u8]) -> Result<(), Fault> {
+        check_range(addr, data.len())?;
+        self.memory[addr..addr + data.len()].copy_from_slice(data);
+        self.idle = None;
+        for addr in addr..addr + data.len() {
+            self.cache.invalidate(addr);
+            #[cfg(feature = "jit")]
+            self.jit.invalidate(addr);
+        }
+        if addr + data.len() > SCREEN_MEMORY_START {
+            self.redraw();
+        }
+        Ok(())
+    }
+
+    /// Memory read and written by the last executed instruction
+    pub fn last_accesses(&self) -> &[MemAccess] {
+        &self.accesses
+    }
+
+    /// Instruction at the current pc
+    pub(crate) fn current_instruction(&self) -> Option<Instruction> {
+        self.instruction_at(self.pc)
+    }
+
+    pub(crate) fn instruction_at(&self, addr: usize) -> Option<Instruction> {
+        if addr + 1 >= MEMORY_SIZE {
+            return None;
+        }
+        Instruction::from_bytes(self.memory[addr], self.memory[addr + 1]).ok()
+    }
+
+    /// Program is considered finished once it jumps to itself
+    pub fn is_halted(&self) -> bool {
+        self.current_instruction() == Some(Instruction::Jump(self.pc as u16))
+    }
+
+    /// Amount of executed instructions
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Amount of frames passed since start
+    pub fn frames(&self) -> u64 {
+        self.cycles / self.ticks_per_frame
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            v: self.v,
+            i: self.i,
+            sp: self.sp,
+            pc: self.pc,
+            dt: self.dt,
+            st: self.st,
+            memory: self.memory,
+            seed: self.seed,
+            cycles: self.cycles,
+            keys: self.keys,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.sp = snapshot.sp;
+        self.pc = snapshot.pc;
+        self.dt = snapshot.dt;
+        self.st = snapshot.st;
+        self.memory = snapshot.memory;
+        self.seed = snapshot.seed;
+        self.cycles = snapshot.cycles;
+        self.keys = snapshot.keys;
+        self.accesses.clear();
+        self.idle = None;
+        self.cache.clear();
+        #[cfg(feature = "jit")]
+        self.jit.clear();
+        self.redraw();
+    }
+
+    /// Saves the whole machine state to `path`
+    pub fn save_state(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.snapshot().to_bytes())
+    }
+
+    /// Restores the machine state saved by `save_state`
+    pub fn load_state(&mut self, path: &str) -> io::Result<()> {
+        let snapshot = Snapshot::from_bytes(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.restore(&snapshot);
+        Ok(())
+    }
+
+    /// Repaints the whole screen from the screen memory
+    fn redraw(&mut self) {
+        self.framebuffer = Lores::from_bytes(self.framebuffer());
+        self.screen.clear();
+        for (y, &row) in self.framebuffer.rows().iter().enumerate() {
+            for x in columns(row) {
+                self.screen.draw_px(x as i32, y as i32);
+            }
+        }
+        self.screen.present();
+    }
+
+    fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Stops profiling and returns collected data
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    /// Stops coverage recording and returns collected data
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Starts measuring the draws
+    pub fn set_draw_timing(&mut self, timing: DrawTiming) {
+        self.draw_timing = Some(timing);
+    }
+
+    /// Stops measuring the draws and returns the timing
+    pub fn take_draw_timing(&mut self) -> Option<DrawTiming> {
+        self.draw_timing.take()
+    }
+
+    /// Size of the loaded rom
+    pub fn rom_size(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Registers V0-VF
+    pub fn v(&mut self) -> &mut [u8; 16] {
+        &mut self.v
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Screen memory, a bit per pixel and 8 bytes per row
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.memory[SCREEN_MEMORY_START..]
+    }
+
+    /// Clears the screen like 00E0
+    pub fn clear_screen(&mut self) {
+        self.op_disp_clear();
+    }
+
+    /// Draws the `n` rows sprite at I to (`x`, `y`) like DXYN, returns false
+    /// without drawing when it has to wait for the next frame
+    fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<bool, Fault> {
+        if self.quirks.vblank && !self.frame_start() {
+            // wait for the next frame like the VIP waits for the vertical
+            // blank interrupt
+            return Ok(false);
+        }
+        let start = self.draw_timing.is_some().then(Instant::now);
+        self.op_draw(x.into(), y.into(), n)?;
+        if let (Some(timing), Some(start)) = (&mut self.draw_timing, start) {
+            timing.draws += 1;
+            timing.time += start.elapsed();
+        }
+        Ok(true)
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.dt = value;
+        self.idle = None;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.st = value;
+        self.idle = None;
+    }
+
+    /// Sets the state of the hex keypad, true for pressed keys
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+    }
+
+    pub fn key_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0xf) as usize]
+    }
+
+    /// Lowest of the pressed keys
+    pub fn pressed_key(&self) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|&pressed| pressed)
+            .map(|key| key as u8)
+    }
+
+    /// Next byte of the random generator used by CXNN
+    pub fn random(&mut self) -> u8 {
+        self.rand_gen() as u8
+    }
+
+    fn trace(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            match tracer.record(self) {
+                Ok(()) => self.tracer = Some(tracer),
+                Err(e) => log::error!(target: LOG_TRACE, "Trace is stopped: {}", e),
+            }
+        }
+    }
+
+    /// Executes the instruction at pc on the interpreter
+    pub fn emulate_op(&mut self) -> Result<(), ExecError> {
+        self.accesses.clear();
+        log::trace!(target: LOG_CPU, "{}", self.disassembly_line(self.pc));
+        let instruction = self.fetch()?;
+        self.trace();
+
+        let pc = self.pc;
+        if self.execute(instruction)? {
+            self.inc_pc();
+        }
+
+        let frame_end = self.retire();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, instruction, frame_end);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, instruction, &self.accesses);
+        }
+        Ok(())
+    }
+
+    /// Decodes the instruction at pc
+    pub(crate) fn fetch(&self) -> Result<Instruction, ExecError> {
+        let error = |opcode, fault| ExecError {
+            pc: self.pc,
+            opcode,
+            fault,
+        };
+        if self.pc + 1 >= MEMORY_SIZE {
+            return Err(error(0, Fault::PcOutOfMemory));
+        }
+        let opcode = (self.memory[self.pc] as u16) << 8 | self.memory[self.pc + 1] as u16;
+        Instruction::decode(opcode).map_err(|_| error(opcode, Fault::UnknownOpcode))
+    }
+
+    /// Executes `instruction` as if it was at pc, returns false when it
+    /// changed pc itself or has to be repeated. Nothing is changed when it
+    /// fails
+    pub fn execute(&mut self, instruction: Instruction) -> Result<bool, ExecError> {
+        self.apply(instruction).map_err(|fault| ExecError {
+            pc: self.pc,
+            opcode: instruction.encode(),
+            fault,
+        })
+    }
+
+    fn apply(&mut self, instruction: Instruction) -> Result<bool, Fault> {
+        let mut pc_inc_required = true;
+        match instruction {
+            // machine code routines of the VIP aren't emulated
+            Instruction::Sys(_) => {}
+            Instruction::ClearScreen => self.op_disp_clear(),
+            Instruction::Return => {
+                if self.sp + 2 > STACK_MEMORY_END {
+                    return Err(Fault::StackUnderflow);
                 }
+                // restore pc from the stack memory
+                self.pc = (((self.read_mem(self.sp) as u16) << 8)
+                    | (self.read_mem(self.sp + 1)) as u16) as usize;
+                // increase stack size back
+                self.sp += 2;
+                pc_inc_required = false;
+            }
+            Instruction::Jump(target) => {
                 self.pc = target.into();
                 pc_inc_required = false;
             }
-            0x02 => {
-                // Calls subroutine at NNN.
-
+            Instruction::Call(target) => {
+                if self.sp < STACK_MEMORY_START + 2 {
+                    return Err(Fault::StackOverflow);
+                }
                 // store current value of next instruction on the stack
-
                 self.sp -= 2;
-                self.memory[self.sp] = ((self.pc + 2) >> 8) as u8;
-                self.memory[self.sp + 1] = ((self.pc + 2) & 0xff) as u8;
+                self.write_mem(self.sp, ((self.pc + 2) >> 8) as u8);
+                self.write_mem(self.sp + 1, ((self.pc + 2) & 0xff) as u8);
 
-                self.pc = opcode.nnn().into();
+                self.pc = target.into();
                 pc_inc_required = false;
             }
-            0x03 => {
-                // Skips the next instruction if VX equals NN.
+            Instruction::SkipEqImm(x, nn) => {
                 // Usually the next instruction is a jump to skip a code block
-                if self.v[opcode.x()] == opcode.1 {
+                if self.v[x] == nn {
                     self.inc_pc();
                 }
             }
-            0x04 => {
-                // Skips the next instruction if VX doesn't equal NN.
-                // Usually the next instruction is a jump to skip a code block
-                if self.v[opcode.x()] != opcode.1 {
+            Instruction::SkipNeImm(x, nn) => {
+                if self.v[x] != nn {
                     self.inc_pc();
                 }
             }
-            0x05 => {
-                // Skips the next instruction if VX equals VY
-                // Usually the next instruction is a jump to skip a code block
-                if self.v[opcode.x()] == self.v[opcode.y()] {
+            Instruction::SkipEqReg(x, y) => {
+                if self.v[x] == self.v[y] {
                     self.inc_pc();
                 }
             }
-            0x06 => {
-                //Sets VX to NN
-                self.v[opcode.x()] = opcode.1;
-            }
-            0x07 => {
-                // Adds NN to VX. (Carry flag is not changed)
-                self.v[opcode.x()] = self.v[opcode.x()].wrapping_add(opcode.1);
-            }
-            0x08 => {
-                match Opcode::low_nib(opcode.1) {
-                    0x0 => {
-                        // Sets VX to the value of VY.
-                        self.v[opcode.x()] = self.v[opcode.y()];
-                    }
-                    0x1 => {
-                        // Sets VX to VX or VY. (Bitwise OR operation)
-                        self.v[opcode.x()] |= self.v[opcode.y()];
-                    }
-                    0x2 => {
-                        // Sets VX to VX and VY. (Bitwise AND operation)
-                        self.v[opcode.x()] &= self.v[opcode.y()];
-                    }
-                    0x3 => {
-                        // Sets VX to VX xor VY.
-                        self.v[opcode.x()] ^= self.v[opcode.y()];
-                    }
-                    0x4 => {
-                        // Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-                        let (val, carry) = self.v[opcode.x()].overflowing_add(self.v[opcode.y()]);
-                        self.v[opcode.x()] = val;
-                        self.v[0xf] = carry as u8;
-                    }
-                    0x5 => {
-                        // VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                        let (val, borrow) = self.v[opcode.x()].overflowing_sub(self.v[opcode.y()]);
-                        self.v[opcode.x()] = val;
-                        self.v[0xf] = (!borrow) as u8;
-                    }
-                    0x6 => {
-                        // Stores the least significant bit of VX in VF and then shifts VX to the right by 1
-                        self.v[0xf] = self.v[opcode.x()] & 0x1;
-                        self.v[opcode.x()] >>= 1;
-                    }
-                    0x7 => {
-                        // Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                        let (val, borrow) = self.v[opcode.y()].overflowing_sub(self.v[opcode.x()]);
-                        self.v[opcode.x()] = val;
-                        self.v[0xf] = (!borrow) as u8;
-                    }
-                    0xe => {
-                        // Stores the most significant bit of VX in VF and then shifts VX to the left by 1
-                        self.v[0xf] = self.v[opcode.x()] & 0x1;
-                        self.v[opcode.x()] <<= 1;
-                    }
-                    _ => unreachable!("UNKNOW COMMAND: {:02x} {:02x}", opcode.0, opcode.1),
+            Instruction::SkipNeReg(x, y) => {
+                if self.v[x] != self.v[y] {
+                    self.inc_pc();
                 }
             }
-            0x0a => {
-                //Sets I to the address NNN
-                self.i = opcode.nnn();
+            Instruction::MovImm(x, nn) => self.v[x] = nn,
+            Instruction::AddImm(x, nn) => self.v[x] = self.v[x].wrapping_add(nn),
+            Instruction::MovReg(x, y) => self.v[x] = self.v[y],
+            Instruction::Or(x, y) => {
+                self.v[x] |= self.v[y];
+                self.logic_quirk();
+            }
+            Instruction::And(x, y) => {
+                self.v[x] &= self.v[y];
+                self.logic_quirk();
+            }
+            Instruction::Xor(x, y) => {
+                self.v[x] ^= self.v[y];
+                self.logic_quirk();
+            }
+            Instruction::AddReg(x, y) => {
+                let (val, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.v[x] = val;
+                self.v[0xf] = carry as u8;
+            }
+            Instruction::SubReg(x, y) => {
+                let (val, borrow) = self.v[x].overflowing_sub(self.v[y]);
+                self.v[x] = val;
+                self.v[0xf] = (!borrow) as u8;
+            }
+            Instruction::ShiftRight(x, y) => {
+                // Shifts VY right by 1 and stores it in VX, the least
+                // significant bit before the shift is stored in VF
+                let val = self.v[if self.quirks.shift { x } else { y }];
+                self.v[x] = val >> 1;
+                self.v[0xf] = val & 0x1;
             }
-            0x0c => {
+            Instruction::SubReverse(x, y) => {
+                let (val, borrow) = self.v[y].overflowing_sub(self.v[x]);
+                self.v[x] = val;
+                self.v[0xf] = (!borrow) as u8;
+            }
+            Instruction::ShiftLeft(x, y) => {
+                // Shifts VY left by 1 and stores it in VX, the most
+                // significant bit before the shift is stored in VF
+                let val = self.v[if self.quirks.shift { x } else { y }];
+                self.v[x] = val << 1;
+                self.v[0xf] = val >> 7;
+            }
+            Instruction::MovI(nnn) => self.i = nnn,
+            Instruction::JumpV0(nnn) => {
+                // BXNN is a jump to XNN + VX on SUPER-CHIP
+                let x = if self.quirks.jump {
+                    nnn as usize >> 8
+                } else {
+                    0
+                };
+                self.pc = nnn as usize + self.v[x] as usize;
+                pc_inc_required = false;
+            }
+            Instruction::Rand(x, nn) => {
                 // Sets VX to the result of a bitwise and operation on a
                 // random number (0 to 255) and NN
-                self.v[opcode.x()] = (self.rand_gen()) as u8 & opcode.1;
+                self.v[x] = self.random() & nn;
             }
-            0x0d => {
-                self.op_draw(
-                    self.v[opcode.x()].into(),
-                    self.v[opcode.y()].into(),
-                    opcode.n(),
-                );
+            Instruction::Draw(x, y, n) => pc_inc_required = self.draw(self.v[x], self.v[y], n)?,
+            Instruction::SkipKey(x) => {
+                if self.key_pressed(self.v[x]) {
+                    self.inc_pc();
+                }
+            }
+            Instruction::SkipNotKey(x) => {
+                if !self.key_pressed(self.v[x]) {
+                    self.inc_pc();
+                }
             }
-            0x0f => match opcode.1 {
-                0x1e => {
-                    // Adds VX to I. VF is not affected
-                    self.i = self.i.wrapping_add(self.v[opcode.x()].into());
+            Instruction::WaitKey(x) => {
+                // Execution is blocked by repeating the instruction until a
+                // key is pressed, timers keep ticking meanwhile
+                match self.pressed_key() {
+                    Some(key) => self.v[x] = key,
+                    None => pc_inc_required = false,
                 }
-                0x55 => {
-                    // Stores V0 to VX (including VX) in memory starting at
-                    // address I. The offset from I is increased by 1 for each
-                    // value written, but I itself is left unmodified
-                    for i in 0..=opcode.x() {
-                        self.memory[self.i as usize + i] = self.v[i];
-                    }
+            }
+            Instruction::GetDelay(x) => self.v[x] = self.dt,
+            Instruction::SetDelay(x) => self.set_delay_timer(self.v[x]),
+            Instruction::SetSound(x) => self.set_sound_timer(self.v[x]),
+            Instruction::AddI(x) => {
+                // Adds VX to I. VF is not affected
+                self.i = self.i.wrapping_add(self.v[x].into());
+            }
+            Instruction::Font(x) => {
+                // Characters 0-F are represented by a 4x5 font
+                self.i = (FONT_START + (self.v[x] & 0xf) as usize * FONT_SPRITE_SIZE) as u16;
+            }
+            Instruction::Bcd(x) => {
+                // Stores hundreds digit at I, tens digit at I+1 and ones at I+2
+                check_range(self.i as usize, 3)?;
+                let val = self.v[x];
+                self.write_mem(self.i as usize, val / 100);
+                self.write_mem(self.i as usize + 1, val / 10 % 10);
+                self.write_mem(self.i as usize + 2, val % 10);
+            }
+            Instruction::Store(x) => {
+                // The offset from I is increased by 1 for each value
+                // written
+                check_range(self.i as usize, x + 1)?;
+                for i in 0..=x {
+                    self.write_mem(self.i as usize + i, self.v[i]);
                 }
-                0x65 => {
-                    // Fills V0 to VX (including VX) with values from memory
-                    // starting at address I. The offset from I is increased by
-                    // 1 for each value written, but I itself is left unmodified
-                    for i in 0..=opcode.x() {
-                        self.v[i] = self.memory[self.i as usize + i];
-                    }
+                self.memory_quirk(x);
+            }
+            Instruction::Load(x) => {
+                // The offset from I is increased by 1 for each value
+                // written
+                check_range(self.i as usize, x + 1)?;
+                for i in 0..=x {
+                    self.v[i] = self.read_mem(self.i as usize + i);
                 }
-                _ => unimplemented!(),
-            },
-            _ => unimplemented!(),
+                self.memory_quirk(x);
+            }
         }
+        // pc is incremented if no jump, call or ret was executed
+        Ok(pc_inc_required)
+    }
 
-        // increment pc if, no jump, call or ret was called
-        if pc_inc_required {
-            self.inc_pc();
+    /// Counts an executed instruction and ticks the timers once a frame is
+    /// over, returns whether it is
+    pub fn retire(&mut self) -> bool {
+        self.cycles += 1;
+        let frame_end = self.frame_start();
+        if frame_end {
+            self.tick_timers();
+        }
+        frame_end
+    }
+
+    /// Executes the next instructions with the engine, at most up to the
+    /// end of the frame or the `end` cycle. Recorders need every
+    /// instruction, they always run on the interpreter
+    fn step(&mut self, end: u64) -> Result<(), ExecError> {
+        let recording = self.tracer.is_some() || self.profiler.is_some() || self.coverage.is_some();
+        let pc = self.pc;
+        match self.engine {
+            _ if recording => return self.emulate_op(),
+            Engine::Interpreter => self.emulate_op()?,
+            Engine::Cached => self.step_cached(end)?,
+            #[cfg(feature = "jit")]
+            Engine::Jit => self.step_jit(end)?,
+        }
+        // went back to the start of a loop
+        if self.pc <= pc {
+            self.skip_idle(end);
+        }
+        Ok(())
+    }
+
+    /// Skips the iterations of an idle loop, e.g. `FX07; 3X00; 1NNN` or a
+    /// waiting FX0A, up to the next timer tick or input. A loop is idle
+    /// when an iteration went back to its start in the same state without
+    /// side effects, every further iteration in the frame is the same then
+    fn skip_idle(&mut self, end: u64) {
+        let state = LoopState {
+            pc: self.pc,
+            v: self.v,
+            i: self.i,
+            sp: self.sp,
+            keys: self.keys,
+        };
+        let tpf = self.ticks_per_frame;
+        match self.idle {
+            Some((start, last)) if last == state && start / tpf == self.cycles / tpf => {
+                // the last instruction of the frame ticks the timers, it's
+                // executed
+                let limit = end.min((self.cycles / tpf + 1) * tpf) - 1;
+                let period = self.cycles - start;
+                self.cycles += limit.saturating_sub(self.cycles) / period * period;
+            }
+            _ => {}
+        }
+        self.idle = Some((self.cycles, state));
+    }
+
+    fn step_cached(&mut self, end: u64) -> Result<(), ExecError> {
+        // pc past the end of memory is reported by the interpreter
+        if self.pc + 1 >= MEMORY_SIZE {
+            return self.emulate_op();
+        }
+        let block = match self.cache.decoded(&self.memory, self.pc) {
+            Some(block) => block,
+            // unknown opcodes are reported by the interpreter
+            None => return self.emulate_op(),
+        };
+        for &instruction in block.iter() {
+            let next = self.pc + 2;
+            self.accesses.clear();
+            if self.execute(instruction)? {
+                self.inc_pc();
+            }
+            let frame_end = self.retire();
+            // the rest of the block is stale once it's overwritten
+            if frame_end || self.pc != next || self.cache.take_invalidated() || self.cycles >= end {
+                break;
+            }
         }
+        Ok(())
+    }
+
+    #[cfg(feature = "jit")]
+    fn step_jit(&mut self, end: u64) -> Result<(), ExecError> {
+        let native = match self.jit.block(&self.memory, self.pc, self.quirks) {
+            Some(native) => native,
+            // code between the native blocks runs predecoded
+            None => return self.step_cached(end),
+        };
+        // native code stops at the end of the frame
+        let tpf = self.ticks_per_frame;
+        let budget = (end - self.cycles).min(tpf - self.cycles % tpf);
+        let mut left = budget;
+        self.pc = native.run(&mut self.v, &mut self.i, &mut left);
+        self.cycles += budget - left - 1;
+        self.retire();
+        Ok(())
+    }
+
+    /// Next instruction is the first one of a frame
+    fn frame_start(&self) -> bool {
+        self.cycles.is_multiple_of(self.ticks_per_frame)
+    }
+
+    /// 8XY1, 8XY2 and 8XY3 reset VF on the VIP
+    fn logic_quirk(&mut self) {
+        if self.quirks.logic {
+            self.v[0xf] = 0;
+        }
+    }
+
+    /// Advances I past the registers stored or loaded by FX55 and FX65
+    fn memory_quirk(&mut self, x: usize) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
+        let inc = if self.quirks.memory_increment_by_x {
+            x
+        } else {
+            x + 1
+        };
+        self.i = self.i.wrapping_add(inc as u16);
     }
 
     /// Clears the screen
     fn op_disp_clear(&mut self) {
+        log::debug!(target: LOG_DRAW, "Clear screen");
+        self.memory[SCREEN_MEMORY_START..].fill(0);
+        self.framebuffer.clear();
+        self.idle = None;
         // TODO: think should we use sdl2 or webasm, or both
         // Ideally would be to provide trait:Display(Renderer) and anyone who implements
         // it can be passed to chip8 to be use as graphical interface
@@ -490,6 +1123,7 @@ impl<T: Screen> Chip8<T> {
         self.screen.present();
     }
 
+    #[cfg(test)]
     /// translates pixel location on screen to pixel location in memory
     /// returns idx of line and position in that line
     fn get_screen_memory_px(&mut self, x: usize, y: usize) -> (usize, usize) {
@@ -517,115 +1151,126 @@ impl<T: Screen> Chip8<T> {
     }
 
     /// Draw the sprite
-    fn op_draw(&mut self, x: usize, y: usize, len: u8) {
+    fn op_draw(&mut self, x: usize, y: usize, len: u8) -> Result<(), Fault> {
         // Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height
         // of N+1 pixels. Each row of 8 pixels is read as bit-coded starting from memory
         // location I; I value doesn’t change after the execution of this instruction. As
         // described above, VF is set to 1 if any screen pixels are flipped from set to
         // unset when the sprite is drawn, and to 0 if that doesn’t happen
-        let mut cy;
-        self.v[0xf] = 0;
-        for i in 0..len {
-            cy = y + i as usize;
-            if cy >= SCREEN_HEIGHT as usize {
-                // sprite goes out of screen, stop drawing
-                break;
-            }
-
-            let sprite_line = self.memory[(self.i + i as u16) as usize];
-            let mut cx = x;
-            for bi in (0..8).rev() {
-                let mut px = ((sprite_line & (1 << bi)) != 0) as u8;
+        log::debug!(target: LOG_DRAW, "Draw {} rows from {:04x} at ({}, {})", len, self.i, x, y);
+        let wrap = self.quirks.wrap;
+        // rows clipped at the bottom edge aren't read
+        let rows = match SCREEN_HEIGHT as usize - y % SCREEN_HEIGHT as usize {
+            _ if wrap => len as usize,
+            left => left.min(len as usize),
+        };
+        check_range(self.i as usize, rows)?;
+        self.idle = None;
+        let mut sprite = [0; 15];
+        for (i, row) in sprite[..rows].iter_mut().enumerate() {
+            *row = self.read_mem(self.i as usize + i);
+        }
 
-                if cx >= SCREEN_WIDTH as usize {
-                    // sprite goes out of screen, stop drawing line
-                    break;
+        let (screen, memory) = (&mut self.screen, &mut self.memory);
+        let collision = self.framebuffer.draw(
+            x as u32,
+            y as u32,
+            &sprite[..rows],
+            wrap,
+            |cy, flipped, row| {
+                let line = SCREEN_MEMORY_START + cy as usize * 8;
+                row.write_to(&mut memory[line..line + 8]);
+                let set = flipped & row;
+                for cx in columns(set) {
+                    screen.draw_px(cx as i32, cy as i32);
                 }
-
-                // if sprite px is 0, just skip it, it takes not affect to
-                // the current screen
-                if px != 0 {
-                    // Determine the address of the effected byte on the screen
-                    // Required to check
-                    let (screen_line_idx, screen_px_idx) = self.get_screen_memory_px(cx, cy);
-                    let screen_line = self.memory[screen_line_idx];
-                    // Determine the effected bit in the byte
-                    let screen_px = (screen_line & (1 << screen_px_idx) != 0) as u8;
-                    if screen_px != 0 {
-                        self.v[0xf] = 1;
-                    }
-
-                    // Write the effected bit to the screen memory
-                    self.memory[screen_line_idx] ^= 1 << screen_px_idx;
-
-                    // draw px
-                    px ^= screen_px;
-                    if px == 0 {
-                        self.screen.clear_px(cx as i32, cy as i32);
-                    } else {
-                        self.screen.draw_px(cx as i32, cy as i32);
-                    }
+                for cx in columns(flipped ^ set) {
+                    screen.clear_px(cx as i32, cy as i32);
                 }
-                cx += 1;
-            }
-        }
+            },
+        );
+        self.v[0xf] = collision as u8;
         self.screen.present();
+        Ok(())
     }
 
     fn rand_gen(&mut self) -> u64 {
         let number = rand(self.seed);
         self.seed = number;
+        self.idle = None;
         number
     }
 
-    pub fn emulate(&mut self) {
-        loop {
-            self.emulate_op();
+    /// Handles window events and repaints the debug overlay if it's shown,
+    /// returns false once the window is closed
+    pub fn update_frontend(&mut self, breakpoints: &[usize]) -> bool {
+        let input = self.screen.poll_events(&mut self.keys);
+        if input.redraw {
+            self.redraw();
         }
+        if self.screen.overlay_visible() {
+            let overlay = Overlay::new(self, breakpoints);
+            self.screen.draw_overlay(&overlay);
+            self.screen.present();
+        }
+        !input.quit
     }
 
-    pub fn debugger(&mut self) -> io::Result<()> {
-        print!("Enter debug mode:\n");
-        print!("\t'r' - to run program\n");
-        print!("\t'n' - for next instruction\n");
-        print!("\t'q' - to exit\n");
-        let mut buffer = String::new();
-        let mut last_cmd = String::new();
-        loop {
-            print!("(chiper - db) ");
-            io::stdout().flush().expect("Could not flush stdout");
-            stdin().read_line(&mut buffer)?;
-            let mut cmd = buffer.trim_end();
-            if cmd.is_empty() {
-                cmd = &last_cmd;
-            } else {
-                last_cmd = cmd.to_string();
-            }
-            match cmd {
-                "n" => {
-                    self.emulate_op();
-                    self.dump_registers();
-                }
-                "r" => loop {
-                    self.emulate_op();
-                    self.dump_registers();
-                },
-                "q" => {
-                    break;
+    pub fn emulate(&mut self) -> Result<(), ExecError> {
+        let frame_time = Duration::from_secs(1) / FRAMES_PER_SECOND;
+        let mut next_frame = Instant::now() + frame_time;
+        while !self.is_halted() {
+            self.step(u64::MAX)?;
+            if self.frame_start() {
+                if !self.update_frontend(&[]) {
+                    return Ok(());
                 }
-                unknown => {
-                    eprint!("Unknown debug command '{}'\n", unknown);
+                // keep timers ticking at 60Hz
+                let now = Instant::now();
+                if next_frame > now {
+                    thread::sleep(next_frame - now);
                 }
+                next_frame += frame_time;
+            }
+        }
+        print!("Press ENTER to exit..\n");
+        let mut buffer = [0];
+        stdin().read_exact(&mut buffer).unwrap();
+        Ok(())
+    }
+
+    /// Runs `frames` frames without pacing and without polling the
+    /// frontend, stops early once the program halts or fails
+    pub fn run_frames(&mut self, frames: u64) -> Result<(), ExecError> {
+        let end = self.cycles + frames * self.ticks_per_frame;
+        while self.cycles < end && !self.is_halted() {
+            self.step(end)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `frames` frames like `run_frames`, with the recompiled code
+    /// where it has a block for pc and the interpreter everywhere else
+    pub fn run_compiled(&mut self, frames: u64, compiled: Compiled<T>) -> Result<(), ExecError> {
+        let end = self.cycles + frames * self.ticks_per_frame;
+        while self.cycles < end && !self.is_halted() {
+            self.accesses.clear();
+            if !compiled(self)? {
+                self.emulate_op()?;
             }
-            buffer.clear();
         }
         Ok(())
     }
+
+    pub fn debugger(&mut self) -> io::Result<()> {
+        Debugger::new().run(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::Platform;
     use crate::screen::NoScreen;
 
     fn print_sprite(sprite: &Vec<u8>) {
@@ -646,7 +1291,7 @@ mod tests {
         let mut chip8 = Chip8::new(NoScreen {});
         //call  345
         chip8.load_from_slice(&[0x23, 0x45]);
-        chip8.emulate_op();
+        chip8.emulate_op().unwrap();
         assert_eq!(chip8.sp, STACK_MEMORY_END - 2);
         assert_eq!(chip8.pc, 0x345);
         assert_eq!(chip8.memory[chip8.sp], 0x02);
@@ -660,18 +1305,48 @@ mod tests {
         //202: 0000
         //204: ret
         chip8.load_from_slice(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xee]);
-        chip8.emulate_op();
-        chip8.emulate_op();
+        chip8.emulate_op().unwrap();
+        chip8.emulate_op().unwrap();
         assert_eq!(chip8.sp, STACK_MEMORY_END);
         assert_eq!(chip8.pc, 0x202);
     }
 
+    #[test]
+    fn test_errors() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        //200: sys 123
+        //202: ret
+        chip8.load_from_slice(&[0x01, 0x23, 0x00, 0xee]);
+        chip8.emulate_op().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        let error = chip8.emulate_op().unwrap_err();
+        assert_eq!((error.pc, error.opcode), (0x202, 0x00ee));
+        assert_eq!(error.fault, Fault::StackUnderflow);
+        // nothing changed
+        assert_eq!((chip8.pc, chip8.sp), (0x202, STACK_MEMORY_END));
+
+        //ff ff
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[0xff, 0xff]);
+        let error = chip8.run_frames(1).unwrap_err();
+        assert_eq!(error.fault, Fault::UnknownOpcode);
+        assert_eq!(error.to_string(), "Unknown opcode at 0200 (ffff)");
+
+        //af fe   mov I, ffe
+        //f2 55   mov [I], V0-V2
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[0xaf, 0xfe, 0xf2, 0x55]);
+        chip8.emulate_op().unwrap();
+        let error = chip8.emulate_op().unwrap_err();
+        assert_eq!(error.fault, Fault::MemoryOutOfRange(0xffe));
+    }
+
     #[test]
     fn test_op_a_mov_toi() {
         let mut chip8 = Chip8::new(NoScreen {});
         //a2 20   mov             I, 220
         chip8.load_from_slice(&[0xa2, 0x20]);
-        chip8.emulate_op();
+        chip8.emulate_op().unwrap();
         assert_eq!(chip8.i, 0x220);
     }
 
@@ -694,7 +1369,7 @@ mod tests {
 
         chip8.load_from_slice(&ops);
         for _ in 0..ops.len() / 2 {
-            chip8.emulate_op();
+            chip8.emulate_op().unwrap();
         }
 
         chip8.dump_registers();
@@ -706,13 +1381,10 @@ mod tests {
     #[test]
     fn test_op_fx65_reg_load() {
         let mut chip8 = Chip8::new(NoScreen {});
-        let mut ops = vec![];
-        // mov I, 200
-        ops.push(0xa2);
-        ops.push(0x04);
-        // reg_load
-        ops.push(0xff);
-        ops.push(0x65);
+        let mut ops = vec![
+            0xa2, 0x04, // mov I, 204
+            0xff, 0x65, // reg_load
+        ];
         // data(values to be loaded)
         for i in 0..16 {
             //ops.push(0x00);
@@ -720,8 +1392,8 @@ mod tests {
         }
         chip8.load_from_slice(&ops);
 
-        chip8.emulate_op();
-        chip8.emulate_op();
+        chip8.emulate_op().unwrap();
+        chip8.emulate_op().unwrap();
 
         chip8.dump_registers();
         for i in 0..16 {
@@ -729,6 +1401,255 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_op_keys() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[
+            0x60, 0x0a, // mov       V0, 0a
+            0xe0, 0x9e, // skipifkey V0
+            0xf1, 0x0a, // waitkey   V1
+            0xe0, 0xa1, // skipifnkey V0
+        ]);
+        chip8.emulate_op().unwrap();
+        chip8.emulate_op().unwrap();
+        assert_eq!(chip8.pc, 0x204);
+
+        // waits while no key is pressed
+        chip8.emulate_op().unwrap();
+        chip8.emulate_op().unwrap();
+        assert_eq!(chip8.pc, 0x204);
+        chip8.keys[0xa] = true;
+        chip8.emulate_op().unwrap();
+        assert_eq!((chip8.pc, chip8.v[1]), (0x206, 0xa));
+
+        chip8.emulate_op().unwrap();
+        assert_eq!(chip8.pc, 0x208);
+    }
+
+    #[test]
+    fn test_quirks() {
+        let prog = [
+            0x60, 0x81, // mov       V0, 81
+            0x61, 0x03, // mov       V1, 03
+            0x80, 0x1e, // shl       V0, V1
+            0x6f, 0x01, // mov       VF, 01
+            0x80, 0x11, // or        V0, V1
+            0xa3, 0x00, // mov       I, 300
+            0xf1, 0x55, // movm      I, V0-V1
+        ];
+        let run = |quirks: Quirks| {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.set_quirks(quirks);
+            chip8.load_from_slice(&prog);
+            for _ in 0..3 {
+                chip8.emulate_op().unwrap();
+            }
+            let shifted = (chip8.v[0], chip8.v[0xf]);
+            for _ in 0..4 {
+                chip8.emulate_op().unwrap();
+            }
+            (shifted, chip8.v[0xf], chip8.i)
+        };
+        // V0 is shifted in place, VF and I are kept
+        assert_eq!(run(Quirks::default()), ((0x02, 1), 1, 0x300));
+        // V1 is shifted into V0, VF is reset by OR, I is incremented
+        assert_eq!(run(Platform::Chip8.quirks()), ((0x06, 0), 0, 0x302));
+        let mut quirks = Platform::Chip8.quirks();
+        quirks.set("memoryIncrementByX", true).unwrap();
+        assert_eq!(run(quirks).2, 0x301);
+    }
+
+    #[test]
+    fn test_draw_wrap() {
+        let prog = [
+            0x60, 0x3c, // mov       V0, 3c
+            0x61, 0x5f, // mov       V1, 5f
+            0xa2, 0x08, // mov       I, 208
+            0xd0, 0x12, // draw      V0, V1, 2
+            0xff, 0xff, // sprite
+        ];
+        for &wrap in &[false, true] {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.set_quirks(Quirks {
+                wrap,
+                ..Quirks::default()
+            });
+            chip8.load_from_slice(&prog);
+            for _ in 0..4 {
+                chip8.emulate_op().unwrap();
+            }
+            // starting position wraps to (60, 31) in both modes
+            assert_eq!(chip8.sprite_from_memory(56, 31, 1), vec![0x0f]);
+            let wrapped = if wrap { 0xf0 } else { 0x00 };
+            assert_eq!(chip8.sprite_from_memory(0, 0, 1), vec![wrapped]);
+            assert_eq!(chip8.sprite_from_memory(0, 31, 1), vec![wrapped]);
+        }
+    }
+
+    #[test]
+    fn test_state() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.set_seed(7);
+        chip8.load_from_slice(&[0xc0, 0xff, 0x22, 0x00]);
+        chip8.emulate_op().unwrap();
+        chip8.emulate_op().unwrap();
+        chip8.keys[0xc] = true;
+        let bytes = chip8.snapshot().to_bytes();
+
+        let mut other = Chip8::new(NoScreen {});
+        other.restore(&Snapshot::from_bytes(&bytes).unwrap());
+        assert_eq!((other.v, other.pc, other.sp), (chip8.v, chip8.pc, chip8.sp));
+        assert_eq!(other.cycles(), 2);
+        assert_eq!(other.keys, chip8.keys);
+        // random numbers continue the same sequence
+        chip8.pc = 0x200;
+        other.pc = 0x200;
+        chip8.emulate_op().unwrap();
+        other.emulate_op().unwrap();
+        assert_eq!(other.v[0], chip8.v[0]);
+
+        assert!(Snapshot::from_bytes(&bytes[1..]).is_err());
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_poke_memory() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.set_engine(Engine::Cached);
+        //200: mov V0, 01
+        //202: jmp 202
+        chip8.load_from_slice(&[0x60, 0x01, 0x12, 0x02]);
+        chip8.run_frames(1).unwrap();
+        chip8.pc = 0x200;
+        // mov V0, 02 replaces the cached block
+        chip8.poke_memory(0x201, &[0x02]).unwrap();
+        chip8.run_frames(1).unwrap();
+        assert_eq!(chip8.v[0], 2);
+
+        chip8.poke_memory(SCREEN_MEMORY_START + 8, &[0x80]).unwrap();
+        assert_eq!(
+            columns(chip8.framebuffer.rows()[1]).collect::<Vec<_>>(),
+            [0]
+        );
+        assert!(chip8.poke_memory(MEMORY_SIZE - 1, &[0, 0]).is_err());
+    }
+
+    #[cfg(not(feature = "jit"))]
+    const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::Cached];
+    #[cfg(feature = "jit")]
+    const ENGINES: [Engine; 3] = [Engine::Interpreter, Engine::Cached, Engine::Jit];
+
+    #[test]
+    fn test_engines() {
+        let roms = ["Fishie.ch8", "Maze.ch8", "partircle.ch8", "pong.ch8"];
+        for (rom, platform) in roms
+            .iter()
+            .flat_map(|r| [(r, Platform::Chip8), (r, Platform::SuperChip)])
+        {
+            let mut machines = ENGINES.map(|engine| {
+                let mut chip8 = Chip8::new(NoScreen {});
+                chip8.set_engine(engine);
+                chip8.set_seed(3);
+                chip8.load_rom(&format!("roms/{}", rom)).unwrap();
+                // the rom database sets the quirks of known roms
+                chip8.set_quirks(platform.quirks());
+                assert_eq!(chip8.quirks, platform.quirks());
+                chip8
+            });
+            for frame in 0..300 {
+                for chip8 in &mut machines {
+                    chip8.run_frames(1).unwrap();
+                }
+                let reference = machines[0].snapshot().to_bytes();
+                for chip8 in &machines[1..] {
+                    assert!(
+                        chip8.snapshot().to_bytes() == reference,
+                        "{} {:?} {:?} differs at frame {}",
+                        rom,
+                        chip8.engine,
+                        platform,
+                        frame
+                    );
+                }
+            }
+        }
+
+        // FX55 overwrites the instruction at 20c with add V0, 5 in the
+        // middle of the block
+        for engine in ENGINES {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.set_engine(engine);
+            chip8.load_from_slice(&[
+                0x60, 0x70, 0x61, 0x05, 0xa2, 0x0c, 0xf1, 0x55, 0x62, 0x00, 0x63, 0x00, 0x64, 0x01,
+                0x12, 0x0e,
+            ]);
+            chip8.run_frames(1).unwrap();
+            assert!(chip8.is_halted());
+            assert_eq!((chip8.v[0], chip8.v[4]), (0x75, 0));
+        }
+
+        // jmp V0, fff jumps past the end of memory
+        for engine in ENGINES {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.set_engine(engine);
+            chip8.load_from_slice(&[0x60, 0xff, 0xbf, 0xff]);
+            let error = chip8.run_frames(2).unwrap_err();
+            assert_eq!(error.fault, Fault::PcOutOfMemory, "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn test_idle() {
+        // skipping idle loops changes nothing
+        let roms = ["Fishie.ch8", "Maze.ch8", "partircle.ch8", "pong.ch8"];
+        for (rom, platform) in roms
+            .iter()
+            .flat_map(|r| [(r, Platform::Chip8), (r, Platform::SuperChip)])
+        {
+            let mut skipping = Chip8::new(NoScreen {});
+            let mut stepping = Chip8::new(NoScreen {});
+            for chip8 in [&mut skipping, &mut stepping] {
+                chip8.set_seed(4);
+                chip8.load_rom(&format!("roms/{}", rom)).unwrap();
+                // the rom database sets the quirks of known roms
+                chip8.set_quirks(platform.quirks());
+                assert_eq!(chip8.quirks, platform.quirks());
+            }
+            for frame in 0..300 {
+                skipping.run_frames(1).unwrap();
+                while stepping.cycles < skipping.cycles && !stepping.is_halted() {
+                    stepping.emulate_op().unwrap();
+                }
+                assert!(
+                    skipping.snapshot().to_bytes() == stepping.snapshot().to_bytes(),
+                    "{} {:?} differs at frame {}",
+                    rom,
+                    platform,
+                    frame
+                );
+            }
+        }
+
+        // 200: mov V0, 3; 202: mov DT, V0; 204: mov V1, DT; 206: skp V1 == 0;
+        // 208: jmp 204; 20a: wait key V0; 20c: jmp 20c
+        let rom = [
+            0x60, 0x03, 0xf0, 0x15, 0xf1, 0x07, 0x31, 0x00, 0x12, 0x04, 0xf0, 0x0a, 0x12, 0x0c,
+        ];
+        for engine in ENGINES {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.set_engine(engine);
+            chip8.load_from_slice(&rom);
+            // far too many instructions per frame to execute them all
+            chip8.set_ticks_per_frame(1 << 40);
+            chip8.run_frames(4).unwrap();
+            assert_eq!((chip8.cycles, chip8.pc), (4 << 40, 0x20a));
+            assert_eq!((chip8.v[0], chip8.v[1], chip8.dt), (3, 0, 0));
+            chip8.keys[7] = true;
+            chip8.run_frames(1).unwrap();
+            assert_eq!((chip8.pc, chip8.v[0]), (0x20c, 7));
+        }
+    }
+
     #[test]
     fn test_op_dxyn_draw() {
         let mut chip8 = Chip8::new(NoScreen {});
@@ -750,7 +1671,7 @@ mod tests {
         chip8.load_from_slice(&prog);
 
         for _ in 0..(ops.len() / 2) {
-            chip8.emulate_op();
+            chip8.emulate_op().unwrap();
         }
         let sprite_in_mem = chip8.sprite_from_memory(x, y, sprite.len());
         assert_eq!(sprite, sprite_in_mem);
@@ -797,7 +1718,7 @@ mod tests {
         print_sprite(&expected_screen_sprite);
 
         for _ in 0..(ops.len() / 2) {
-            chip8.emulate_op();
+            chip8.emulate_op().unwrap();
         }
         let sprite_in_mem = chip8.sprite_from_memory(x, y, sprite.len());
         assert_eq!(expected_screen_sprite, sprite_in_mem);
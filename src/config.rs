@@ -0,0 +1,330 @@
+//! Configuration file
+//!
+//! Settings are read from a TOML file with global defaults and sections for
+//! single roms, keyed either by the file name or by the SHA-1 of the rom:
+//!
+//! ```toml
+//! [defaults]
+//! ipf = 15
+//! palette = "#ffcc00,#202020"
+//!
+//! [roms."pong.ch8"]
+//! platform = "chip8"
+//! effects = "grid,scanlines"
+//!
+//! [roms.0123456789abcdef0123456789abcdef01234567]
+//! keymap = "X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V"
+//! quirks = { wrap = true, vblank = false }
+//! ```
+//!
+//! The section of the hash takes precedence over the one of the file name,
+//! both take precedence over the defaults. Command line options override the
+//! whole file.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chip8::TICKS_PER_FRAME;
+use crate::platform::{Platform, Quirks};
+use crate::screen::{Effects, Keymap, Options, Palette};
+
+/// Environment variable with the path of the config file
+pub const CONFIG_ENV: &str = "CHIPER_CONFIG";
+
+/// Values parsed from strings with `FromStr` and written with `Display`
+mod as_string {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map(Some).map_err(de::Error::custom)
+    }
+}
+
+/// Settings of a single layer, unset values are taken from the layers below
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Quirk preset, single quirks are applied on top of it
+    #[serde(with = "as_string", skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    /// Instructions per frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipf: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<u32>,
+    #[serde(with = "as_string", skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+    #[serde(with = "as_string", skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<Keymap>,
+    #[serde(with = "as_string", skip_serializing_if = "Option::is_none")]
+    pub effects: Option<Effects>,
+    /// Keypad keys of game actions, e.g. `up = 5`, mapped to arrows, space
+    /// and enter in addition to the keymap
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, u8>,
+    /// Quirks by their names in `platform::QUIRK_NAMES`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub quirks: BTreeMap<String, bool>,
+}
+
+impl Settings {
+    /// Overrides settings with the ones set in `other`
+    pub fn merge(&mut self, other: &Settings) {
+        self.platform = other.platform.or(self.platform);
+        self.ipf = other.ipf.or(self.ipf);
+        self.scale = other.scale.or(self.scale);
+        self.palette = other.palette.or(self.palette);
+        self.keymap = other.keymap.or(self.keymap);
+        self.effects = other.effects.or(self.effects);
+        // a preset replaces quirks of the layers below
+        if other.platform.is_some() {
+            self.quirks.clear();
+        }
+        self.quirks.extend(
+            other
+                .quirks
+                .iter()
+                .map(|(name, &value)| (name.clone(), value)),
+        );
+        self.keys.extend(
+            other
+                .keys
+                .iter()
+                .map(|(action, &key)| (action.clone(), key)),
+        );
+    }
+
+    pub fn quirks(&self) -> Result<Quirks, String> {
+        let mut quirks = self.platform.map(Platform::quirks).unwrap_or_default();
+        for (name, &value) in &self.quirks {
+            quirks.set(name, value)?;
+        }
+        Ok(quirks)
+    }
+
+    pub fn ticks_per_frame(&self) -> u64 {
+        self.ipf.unwrap_or(TICKS_PER_FRAME)
+    }
+
+    pub fn screen_options(&self) -> Result<Options, String> {
+        let defaults = Options::default();
+        let mut keymap = self.keymap.unwrap_or(defaults.keymap);
+        for (action, &key) in &self.keys {
+            keymap.bind_action(action, key)?;
+        }
+        Ok(Options {
+            scale: self.scale.unwrap_or(defaults.scale),
+            palette: self.palette.unwrap_or(defaults.palette),
+            keymap,
+            effects: self.effects.unwrap_or(defaults.effects),
+        })
+    }
+
+    /// Settings with every value set, quirks are listed one by one
+    pub fn effective(&self) -> Result<Settings, String> {
+        let options = self.screen_options()?;
+        Ok(Settings {
+            platform: self.platform,
+            ipf: Some(self.ticks_per_frame()),
+            scale: Some(options.scale),
+            palette: Some(options.palette),
+            keymap: Some(options.keymap),
+            effects: Some(options.effects),
+            keys: self.keys.clone(),
+            quirks: self
+                .quirks()?
+                .iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.ipf == Some(0) {
+            return Err("ipf must be at least 1".to_string());
+        }
+        if self.scale == Some(0) {
+            return Err("scale must be at least 1".to_string());
+        }
+        self.screen_options()?;
+        self.quirks().map(|_| ())
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", toml::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub defaults: Settings,
+    /// Settings of roms by file name or SHA-1
+    pub roms: BTreeMap<String, Settings>,
+}
+
+/// Lowercase hex SHA-1 of the rom
+pub fn sha1_hex(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+impl Config {
+    /// Path given by `CHIPER_CONFIG`, otherwise `chiper/config.toml` in the
+    /// user config directory
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os(CONFIG_ENV) {
+            return Some(path.into());
+        }
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("chiper").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Config> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Settings of the rom at `path` with `rom` contents, defaults included.
+    /// `detected` settings of the rom database override the defaults, but
+    /// not the rom sections
+    pub fn settings(&self, path: &str, rom: &[u8], detected: Option<&Settings>) -> Settings {
+        let mut settings = self.defaults.clone();
+        if let Some(detected) = detected {
+            settings.merge(detected);
+        }
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        for key in name.into_iter().chain(Some(sha1_hex(rom))) {
+            if let Some(section) = self.roms.get(&key) {
+                settings.merge(section);
+            }
+        }
+        settings
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(s).map_err(|e| e.to_string())?;
+        config
+            .defaults
+            .validate()
+            .map_err(|e| format!("[defaults]: {}", e))?;
+        for (key, settings) in &config.roms {
+            settings
+                .validate()
+                .map_err(|e| format!("[roms.\"{}\"]: {}", key, e))?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r##"
+[defaults]
+ipf = 15
+palette = "#ffcc00,#202020"
+quirks = { wrap = true }
+
+[roms."pong.ch8"]
+platform = "chip8"
+effects = "grid"
+
+[roms.da39a3ee5e6b4b0d3255bfef95601890afd80709]
+ipf = 20
+quirks = { vblank = false }
+"##;
+
+    #[test]
+    fn test_layers() {
+        let config: Config = CONFIG.parse().unwrap();
+        let other = config.settings("roms/maze.ch8", &[0x12, 0x00], None);
+        assert_eq!(other.ticks_per_frame(), 15);
+        assert!(other.quirks().unwrap().wrap);
+
+        // sha1 of the empty rom
+        let pong = config.settings("roms/pong.ch8", &[], None);
+        assert_eq!(pong.ticks_per_frame(), 20);
+        let options = pong.screen_options().unwrap();
+        assert_eq!(options.palette, "#ffcc00,#202020".parse().unwrap());
+        assert!(options.effects.grid && !options.effects.scanlines);
+        // the preset replaces the default quirks
+        let mut quirks = Platform::Chip8.quirks();
+        quirks.vblank = false;
+        assert_eq!(pong.quirks().unwrap(), quirks);
+
+        // command line overrides the file
+        let cli = Settings {
+            ipf: Some(30),
+            ..Settings::default()
+        };
+        let mut settings = pong.clone();
+        settings.merge(&cli);
+        assert_eq!(settings.ticks_per_frame(), 30);
+        assert_eq!(settings.platform, Some(Platform::Chip8));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!("[defaults]\nipf = 0".parse::<Config>().is_err());
+        assert!("[defaults]\nspeed = 5".parse::<Config>().is_err());
+        assert!("[defaults]\npalette = \"red\"".parse::<Config>().is_err());
+        let err = "[roms.\"a.ch8\"]\nquirks = { warp = true }"
+            .parse::<Config>()
+            .unwrap_err();
+        assert!(err.starts_with("[roms.\"a.ch8\"]: Unknown quirk 'warp'"));
+    }
+
+    #[test]
+    fn test_dump() {
+        let settings = Settings {
+            platform: Some(Platform::XoChip),
+            ipf: Some(12),
+            palette: Some(Palette::default()),
+            ..Settings::default()
+        };
+        let text = settings.to_string();
+        assert_eq!(
+            text,
+            "platform = \"xochip\"\nipf = 12\npalette = \"#ffffff,#000000\"\n"
+        );
+        let parsed: Config = format!("[defaults]\n{}", text).parse().unwrap();
+        assert_eq!(parsed.defaults, settings);
+    }
+}
@@ -0,0 +1,337 @@
+//! Memory coverage
+//!
+//! Every byte is marked when it's executed as a part of an instruction, read
+//! as data by DXYN or FX65, or written by FX55 or FX33. Data accesses to the
+//! display memory aren't tracked.
+//!
+//! Coverage is saved as text with a line per touched byte, so sessions are
+//! merged by loading the previous file before recording the next one:
+//!
+//! ```text
+//! # chiper coverage
+//! 0200 x
+//! 0300 r
+//! 0301 rw
+//! ```
+//!
+//! Bytes which were read or written but never executed are data, so the
+//! listing shows them as bytes instead of disassembling them.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::chip8::{Access, Chip8, MemAccess, MEMORY_SIZE, MEMORY_START, STACK_MEMORY_END};
+use crate::instruction::Instruction;
+use crate::screen::Screen;
+
+const EXECUTED: u8 = 1;
+const READ: u8 = 2;
+const WRITTEN: u8 = 4;
+
+const HEADER: &str = "# chiper coverage";
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Coverage {
+    flags: Vec<u8>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+/// Amount of rom bytes touched in every way
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub size: usize,
+    pub executed: usize,
+    pub read: usize,
+    pub written: usize,
+    /// Bytes touched in any way
+    pub covered: usize,
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Coverage of {} rom bytes:", self.size)?;
+        for (name, count) in &[
+            ("executed", self.executed),
+            ("read", self.read),
+            ("written", self.written),
+            ("covered", self.covered),
+        ] {
+            writeln!(
+                f,
+                "  {:<9} {:>5}  {:6.2}%",
+                name,
+                count,
+                percent(*count, self.size)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            flags: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    /// Marks `instruction` executed at `pc` along with data it accessed
+    pub fn record(&mut self, pc: usize, instruction: Instruction, accesses: &[MemAccess]) {
+        for addr in pc..(pc + 2).min(MEMORY_SIZE) {
+            self.flags[addr] |= EXECUTED;
+        }
+        match instruction {
+            Instruction::Draw(..)
+            | Instruction::Load(_)
+            | Instruction::Store(_)
+            | Instruction::Bcd(_) => {}
+            // stack accesses of calls and returns aren't data
+            _ => return,
+        }
+        for access in accesses.iter().filter(|a| a.addr < STACK_MEMORY_END) {
+            self.flags[access.addr] |= match access.kind {
+                Access::Read => READ,
+                Access::Write => WRITTEN,
+            };
+        }
+    }
+
+    pub fn executed(&self, addr: usize) -> bool {
+        self.flags[addr] & EXECUTED != 0
+    }
+
+    pub fn read(&self, addr: usize) -> bool {
+        self.flags[addr] & READ != 0
+    }
+
+    pub fn written(&self, addr: usize) -> bool {
+        self.flags[addr] & WRITTEN != 0
+    }
+
+    /// Byte was accessed as data and never executed
+    pub fn is_data(&self, addr: usize) -> bool {
+        self.flags[addr] & (READ | WRITTEN) != 0 && !self.executed(addr)
+    }
+
+    /// Loads coverage saved by previous sessions, missing file is empty
+    /// coverage
+    pub fn load(path: &str) -> io::Result<Coverage> {
+        match fs::read_to_string(path) {
+            Ok(text) => text
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Coverage::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Adds coverage of another session
+    pub fn merge(&mut self, other: &Coverage) {
+        for (flags, other) in self.flags.iter_mut().zip(&other.flags) {
+            *flags |= other;
+        }
+    }
+
+    fn flags_text(&self, addr: usize) -> String {
+        [(EXECUTED, 'x'), (READ, 'r'), (WRITTEN, 'w')]
+            .iter()
+            .map(|&(flag, c)| if self.flags[addr] & flag != 0 { c } else { '-' })
+            .collect()
+    }
+
+    /// Coverage of the `size` bytes long rom
+    pub fn summary(&self, size: usize) -> Summary {
+        let mut summary = Summary {
+            size,
+            ..Summary::default()
+        };
+        let end = (MEMORY_START + size).min(MEMORY_SIZE);
+        for addr in MEMORY_START..end {
+            summary.executed += self.executed(addr) as usize;
+            summary.read += self.read(addr) as usize;
+            summary.written += self.written(addr) as usize;
+            summary.covered += (self.flags[addr] != 0) as usize;
+        }
+        summary
+    }
+
+    /// Disassembly of the rom separating code and data, every line is
+    /// prefixed with the coverage flags, e.g. `xr-`. Bytes which were never
+    /// touched are disassembled unless the next byte starts an instruction
+    pub fn listing<T: Screen>(&self, chip8: &Chip8<T>) -> String {
+        let mut out = String::new();
+        let end = chip8.rom_end();
+        let mut addr = MEMORY_START;
+        while addr < end {
+            let next_is_code = addr + 1 < end && self.executed(addr + 1) && !self.executed(addr);
+            if self.is_data(addr) || next_is_code || addr + 1 >= end {
+                let byte = chip8.memory[addr];
+                let bits: String = (0..8)
+                    .rev()
+                    .map(|bit| if byte & (1 << bit) != 0 { '#' } else { '.' })
+                    .collect();
+                out.push_str(&format!(
+                    "{} {:04x}:\t{:02x}\tdb {:02x}\t\t{}\n",
+                    self.flags_text(addr),
+                    addr,
+                    byte,
+                    byte,
+                    bits
+                ));
+                addr += 1;
+            } else {
+                out.push_str(&format!(
+                    "{} {}\n",
+                    self.flags_text(addr),
+                    chip8.disassembly_line(addr)
+                ));
+                addr += 2;
+            }
+        }
+        out
+    }
+}
+
+impl FromStr for Coverage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Coverage, String> {
+        let mut coverage = Coverage::new();
+        for (idx, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = || format!("Invalid coverage line {}: '{}'", idx + 1, line);
+            let (addr, flags) = line.split_once(' ').ok_or_else(err)?;
+            let addr = usize::from_str_radix(addr, 16)
+                .ok()
+                .filter(|&a| a < MEMORY_SIZE)
+                .ok_or_else(err)?;
+            for c in flags.trim().chars() {
+                coverage.flags[addr] |= match c {
+                    'x' => EXECUTED,
+                    'r' => READ,
+                    'w' => WRITTEN,
+                    _ => return Err(err()),
+                };
+            }
+        }
+        Ok(coverage)
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for addr in (0..MEMORY_SIZE).filter(|&a| self.flags[a] != 0) {
+            let flags: String = self
+                .flags_text(addr)
+                .chars()
+                .filter(|&c| c != '-')
+                .collect();
+            writeln!(f, "{:04x} {}", addr, flags)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+
+    const PROG: &[u8] = &[
+        0x60, 0xff, // 200: mov V0, ff
+        0xa2, 0x0c, // 202: mov I, 20c
+        0xd0, 0x01, // 204: draw V0, V0, 1
+        0xf0, 0x33, // 206: bcd I, V0
+        0xf1, 0x65, // 208: movm V0-V1, I
+        0x12, 0x0a, // 20a: jmp 20a
+        0xf0, // 20c: sprite
+        0x00, 0x00, 0x00,
+    ];
+
+    fn run() -> (Chip8<NoScreen>, Coverage) {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(PROG);
+        chip8.set_coverage(Coverage::new());
+        for _ in 0..6 {
+            chip8.emulate_op().unwrap();
+        }
+        let coverage = chip8.take_coverage().unwrap();
+        (chip8, coverage)
+    }
+
+    #[test]
+    fn test_record() {
+        let (_, coverage) = run();
+        assert!(coverage.executed(0x200) && coverage.executed(0x20b));
+        assert!(coverage.is_data(0x20c));
+        assert!(coverage.read(0x20c) && coverage.written(0x20c));
+        // FX33 wrote 3 bytes and FX65 read 2 of them
+        assert!(coverage.written(0x20e) && !coverage.read(0x20e));
+        assert!(!coverage.is_data(0x20f));
+        // display memory isn't tracked
+        assert!(!coverage.read(0xf00));
+
+        let summary = coverage.summary(PROG.len());
+        assert_eq!(
+            summary,
+            Summary {
+                size: 16,
+                executed: 12,
+                read: 2,
+                written: 3,
+                covered: 15,
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_and_merge() {
+        let (_, coverage) = run();
+        let text = coverage.to_string();
+        assert!(text.starts_with("# chiper coverage\n0200 x\n"));
+        assert!(text.contains("020c rw\n"));
+        assert!(coverage == text.parse().unwrap());
+
+        let mut other: Coverage = "0300 x\n020d r\n".parse().unwrap();
+        other.merge(&coverage);
+        assert!(other.executed(0x300) && other.read(0x20d) && other.executed(0x200));
+        assert!("0200 z".parse::<Coverage>().is_err());
+    }
+
+    #[test]
+    fn test_listing() {
+        let (chip8, coverage) = run();
+        let listing = coverage.listing(&chip8);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[1], "x-- 0202:\ta2 0c\tmov\t\tI, 20c");
+        // BCD of ff is 2, 5, 5
+        assert_eq!(lines[6], "-rw 020c:\t02\tdb 02\t\t......#.");
+        assert_eq!(lines[7], "-rw 020d:\t05\tdb 05\t\t.....#.#");
+        assert_eq!(lines[8], "--w 020e:\t05\tdb 05\t\t.....#.#");
+        // never touched, but there is no room for an instruction
+        assert_eq!(lines[9], "--- 020f:\t00\tdb 00\t\t........");
+    }
+}
@@ -0,0 +1,869 @@
+//! Debug Adapter Protocol server
+//!
+//! Editors connect over TCP (`debugServer` in VS Code) and send either
+//! `launch` with a `program` to load or `attach` to debug the already loaded
+//! rom. Source breakpoints are set by line of a listing where every line
+//! starts with an address, like the one produced by `Chip8::listing`. When the
+//! editor doesn't have such file, the listing is served as a source with
+//! `sourceReference` 1.
+//!
+//! https://microsoft.github.io/debug-adapter-protocol/specification
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use serde_json::{json, Value};
+
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::debugger::expr::Expr;
+use crate::debugger::{backtrace, register, set_register, Breakpoint, Debugger, StopReason};
+use crate::instruction::Instruction;
+use crate::screen::Screen;
+
+const THREAD_ID: u64 = 1;
+const LISTING_REFERENCE: u64 = 1;
+
+const REGISTERS_SCOPE: u64 = 1;
+const TIMERS_SCOPE: u64 = 2;
+const STACK_SCOPE: u64 = 3;
+
+/// How often running program checks for incoming requests
+const POLL_INTERVAL: u64 = 0x1000;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes the data of `writeMemory`, None when it isn't base64
+fn unbase64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut n) = (0u32, 0);
+    for c in text.bytes() {
+        let value = ALPHABET.iter().position(|&a| a == c)? as u32;
+        bits = bits << 6 | value;
+        n += 6;
+        if n >= 8 {
+            n -= 8;
+            out.push((bits >> n) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Parses memory and instruction references, which are sent as "0x0200"
+fn parse_reference(s: &str) -> Option<i64> {
+    let s = s.trim();
+    match s.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Address of `memoryReference` and `offset` of a request
+fn memory_reference(args: &Value) -> Result<i64, String> {
+    args["memoryReference"]
+        .as_str()
+        .and_then(parse_reference)
+        .and_then(|addr| addr.checked_add(args["offset"].as_i64().unwrap_or(0)))
+        .ok_or_else(|| "Invalid memory reference".to_string())
+}
+
+/// Mapping between listing lines and addresses
+#[derive(Default)]
+pub struct SourceMap {
+    /// Listing file used by the editor, None for the generated one
+    path: Option<String>,
+    addr_by_line: BTreeMap<usize, usize>,
+    line_by_addr: BTreeMap<usize, usize>,
+}
+
+impl SourceMap {
+    /// Reads listing where lines with code start with hex address, e.g.
+    /// `0200:  60 12  mov V0, 12`, other lines are ignored
+    pub fn from_listing(path: Option<String>, listing: &str) -> SourceMap {
+        let mut map = SourceMap {
+            path,
+            ..SourceMap::default()
+        };
+        for (idx, line) in listing.lines().enumerate() {
+            let word = line
+                .trim_start()
+                .split(|c: char| c == ':' || c.is_whitespace())
+                .next()
+                .unwrap_or("");
+            if let Ok(addr) = usize::from_str_radix(word, 16) {
+                if addr < MEMORY_SIZE && word.len() >= 3 {
+                    map.addr_by_line.insert(idx + 1, addr);
+                    map.line_by_addr.entry(addr).or_insert(idx + 1);
+                }
+            }
+        }
+        map
+    }
+
+    fn addr(&self, line: usize) -> Option<usize> {
+        self.addr_by_line.get(&line).copied()
+    }
+
+    /// Line of the closest instruction at or before `addr`
+    fn line(&self, addr: usize) -> Option<usize> {
+        self.line_by_addr
+            .range(..=addr)
+            .next_back()
+            .map(|(_, &l)| l)
+    }
+
+    fn source(&self) -> Value {
+        match &self.path {
+            Some(path) => json!({ "path": path }),
+            None => json!({ "name": "listing.asm", "sourceReference": LISTING_REFERENCE }),
+        }
+    }
+}
+
+/// Content-Length framed connection, which can be polled while the program
+/// runs
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    /// Extracts complete message from the buffer
+    fn take_message(&mut self) -> io::Result<Option<Value>> {
+        let header_end = match self.buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(idx) => idx,
+            None => return Ok(None),
+        };
+        let header = String::from_utf8_lossy(&self.buffer[..header_end]).into_owned();
+        let len = header
+            .lines()
+            .filter_map(|l| l.strip_prefix("Content-Length:"))
+            .find_map(|l| l.trim().parse::<usize>().ok())
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "No Content-Length"))?;
+        let body_start = header_end + 4;
+        if self.buffer.len() < body_start + len {
+            return Ok(None);
+        }
+        let body: Vec<u8> = self
+            .buffer
+            .drain(..body_start + len)
+            .skip(body_start)
+            .collect();
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Blocks until the next message, None once connection is closed
+    fn read_message(&mut self) -> io::Result<Option<Value>> {
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(msg) = self.take_message()? {
+                return Ok(Some(msg));
+            }
+            match self.stream.read(&mut chunk)? {
+                0 => return Ok(None),
+                n => self.buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+
+    /// Checks without blocking whether there is a pending message
+    fn poll(&mut self) -> bool {
+        let mut chunk = [0; 4096];
+        if self.stream.set_nonblocking(true).is_ok() {
+            while let Ok(n) = self.stream.read(&mut chunk) {
+                if n == 0 {
+                    break;
+                }
+                self.buffer.extend_from_slice(&chunk[..n]);
+            }
+            let _ = self.stream.set_nonblocking(false);
+        }
+        self.buffer.windows(4).any(|w| w == b"\r\n\r\n")
+    }
+
+    fn send(&mut self, msg: &Value) -> io::Result<()> {
+        let body = msg.to_string();
+        write!(
+            self.stream,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.stream.flush()
+    }
+}
+
+/// Debug session with a single editor
+pub struct Session<'a, T> {
+    chip8: &'a mut Chip8<T>,
+    conn: Connection,
+    debugger: Debugger,
+    source_map: SourceMap,
+    source_breakpoints: Vec<Breakpoint>,
+    instruction_breakpoints: Vec<Breakpoint>,
+    seq: u64,
+    running: bool,
+    stop_on_entry: bool,
+    finished: bool,
+}
+
+impl<'a, T: Screen> Session<'a, T> {
+    pub fn new(chip8: &'a mut Chip8<T>, stream: TcpStream) -> Session<'a, T> {
+        let source_map = SourceMap::from_listing(None, &chip8.listing());
+        Session {
+            chip8,
+            conn: Connection {
+                stream,
+                buffer: Vec::new(),
+            },
+            debugger: Debugger::new(),
+            source_map,
+            source_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            seq: 1,
+            running: false,
+            stop_on_entry: false,
+            finished: false,
+        }
+    }
+
+    /// Serves requests until the editor disconnects
+    pub fn run(&mut self) -> io::Result<()> {
+        while !self.finished {
+            if self.running {
+                self.resume()?;
+                continue;
+            }
+            match self.conn.read_message()? {
+                Some(msg) => self.handle(&msg)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the program until it stops or a request arrives
+    fn resume(&mut self) -> io::Result<()> {
+        let conn = &mut self.conn;
+        let mut interrupted = false;
+        let reason = self.debugger.run_until(self.chip8, |c, _| {
+            if c.cycles.is_multiple_of(POLL_INTERVAL) {
+                interrupted = conn.poll();
+            }
+            interrupted
+        });
+        if interrupted {
+            // serve requests, `pause` stops the program
+            while let Some(msg) = self.conn.take_message()? {
+                self.handle(&msg)?;
+            }
+            return Ok(());
+        }
+        self.running = false;
+        self.stopped(reason)
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq - 1
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let msg = json!({
+            "seq": self.next_seq(),
+            "type": "event",
+            "event": event,
+            "body": body,
+        });
+        self.conn.send(&msg)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut msg = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => msg["body"] = body,
+            Err(e) => msg["message"] = json!(e),
+        }
+        self.conn.send(&msg)
+    }
+
+    fn stopped(&mut self, reason: StopReason) -> io::Result<()> {
+        if reason == StopReason::Quit {
+            // the window is gone, so is the debuggee
+            self.event("exited", json!({ "exitCode": 0 }))?;
+            self.finished = true;
+            return self.event("terminated", json!({}));
+        }
+        let (reason, description) = match reason {
+            StopReason::Done => ("step", String::new()),
+            StopReason::Breakpoint(_) => ("breakpoint", String::new()),
+            StopReason::Watchpoint(_) => ("data breakpoint", reason.to_string()),
+            StopReason::Fault(_) => ("exception", reason.to_string()),
+            _ => ("pause", reason.to_string()),
+        };
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "description": description,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn handle(&mut self, msg: &Value) -> io::Result<()> {
+        if msg["type"] != "request" {
+            return Ok(());
+        }
+        let args = &msg["arguments"];
+        let command = msg["command"].as_str().unwrap_or("");
+        // requests which change execution state are answered before the
+        // resulting `stopped` event
+        match command {
+            "continue" | "reverseContinue" => {
+                self.respond(msg, Ok(json!({ "allThreadsContinued": true })))?;
+                if command == "continue" {
+                    self.running = true;
+                } else {
+                    let reason = self.debugger.reverse_continue(self.chip8);
+                    self.stopped(reason)?;
+                }
+                return Ok(());
+            }
+            "next" | "stepIn" | "stepOut" | "stepBack" => {
+                self.respond(msg, Ok(json!({})))?;
+                let reason = match command {
+                    "next" => self.debugger.step_over(self.chip8),
+                    "stepIn" => self.debugger.run_until(self.chip8, |_, _| true),
+                    "stepOut" => self
+                        .debugger
+                        .step_out(self.chip8)
+                        .unwrap_or_else(|_| self.debugger.run_until(self.chip8, |_, _| true)),
+                    _ => self.debugger.step_back(self.chip8, 1),
+                };
+                return self.stopped(reason);
+            }
+            "pause" => {
+                self.respond(msg, Ok(json!({})))?;
+                self.running = false;
+                return self.event(
+                    "stopped",
+                    json!({ "reason": "pause", "threadId": THREAD_ID, "allThreadsStopped": true }),
+                );
+            }
+            "configurationDone" => {
+                self.respond(msg, Ok(json!({})))?;
+                if self.stop_on_entry {
+                    return self.event(
+                        "stopped",
+                        json!({ "reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true }),
+                    );
+                }
+                self.running = true;
+                return Ok(());
+            }
+            "disconnect" => {
+                self.finished = true;
+                return self.respond(msg, Ok(json!({})));
+            }
+            _ => {}
+        }
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsSetVariable": true,
+                "supportsStepBack": true,
+                "supportsEvaluateForHovers": true,
+            })),
+            "launch" | "attach" => self.launch(command, args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_SCOPE, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS_SCOPE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_SCOPE, "expensive": false },
+            ]})),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64().unwrap_or(0))),
+            "setVariable" => self.set_variable(args),
+            "source" => Ok(json!({ "content": self.chip8.listing() })),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            "evaluate" => args["expression"]
+                .as_str()
+                .unwrap_or("")
+                .parse::<Expr>()
+                .and_then(|e| e.eval(self.chip8))
+                .map(
+                    |v| json!({ "result": format!("0x{:x} ({})", v, v), "variablesReference": 0 }),
+                ),
+            other => Err(format!("Unsupported request '{}'", other)),
+        };
+        let initialized = command == "initialize" && result.is_ok();
+        self.respond(msg, result)?;
+        if initialized {
+            self.event("initialized", json!({}))?;
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        if command == "launch" {
+            if let Some(program) = args["program"].as_str() {
+                self.chip8.load_rom(program).map_err(|e| e.to_string())?;
+                self.debugger.state_changed();
+                self.source_map = SourceMap::from_listing(None, &self.chip8.listing());
+            }
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    fn parse_condition(bp: &Value) -> Result<Option<crate::debugger::expr::Condition>, String> {
+        match bp["condition"].as_str() {
+            Some(cond) if !cond.trim().is_empty() => cond.parse().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn update_breakpoints(&mut self) {
+        self.debugger.clear_breakpoints();
+        for bp in self
+            .source_breakpoints
+            .iter()
+            .chain(&self.instruction_breakpoints)
+        {
+            self.debugger.add_breakpoint(bp.clone());
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        if let Some(path) = args["source"]["path"].as_str() {
+            if self.source_map.path.as_deref() != Some(path) {
+                let listing = fs::read_to_string(path).map_err(|e| e.to_string())?;
+                self.source_map = SourceMap::from_listing(Some(path.to_string()), &listing);
+            }
+        }
+        let mut results = Vec::new();
+        self.source_breakpoints.clear();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = bp["line"].as_u64().unwrap_or(0) as usize;
+            let addr = self.source_map.addr(line);
+            let condition = Self::parse_condition(bp);
+            match (addr, condition) {
+                (Some(addr), Ok(condition)) => {
+                    self.source_breakpoints.push(Breakpoint {
+                        addr: Some(addr),
+                        condition,
+                    });
+                    results.push(json!({ "verified": true, "line": line }));
+                }
+                (None, _) => results.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No instruction at this line",
+                })),
+                (_, Err(e)) => {
+                    results.push(json!({ "verified": false, "line": line, "message": e }))
+                }
+            }
+        }
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let mut results = Vec::new();
+        self.instruction_breakpoints.clear();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let addr = bp["instructionReference"]
+                .as_str()
+                .and_then(parse_reference)
+                .and_then(|a| a.checked_add(bp["offset"].as_i64().unwrap_or(0)))
+                .filter(|&a| a >= 0 && (a as usize) < MEMORY_SIZE);
+            match (addr, Self::parse_condition(bp)) {
+                (Some(addr), Ok(condition)) => {
+                    self.instruction_breakpoints.push(Breakpoint {
+                        addr: Some(addr as usize),
+                        condition,
+                    });
+                    results.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("0x{:04x}", addr),
+                    }));
+                }
+                (_, Err(e)) => results.push(json!({ "verified": false, "message": e })),
+                (None, _) => results.push(json!({ "verified": false, "message": "Bad address" })),
+            }
+        }
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    fn frame(&self, id: usize, addr: usize) -> Value {
+        let mut frame = json!({
+            "id": id,
+            "name": if id == 0 { format!("{:04x}", addr) } else { format!("call at {:04x}", addr) },
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{:04x}", addr),
+        });
+        if let Some(line) = self.source_map.line(addr) {
+            frame["source"] = self.source_map.source();
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+
+    fn stack_trace(&self) -> Value {
+        let mut frames = vec![self.frame(0, self.chip8.pc)];
+        for (idx, ret) in backtrace(self.chip8).into_iter().enumerate() {
+            frames.push(self.frame(idx + 1, ret.saturating_sub(2)));
+        }
+        let total = frames.len();
+        json!({ "stackFrames": frames, "totalFrames": total })
+    }
+
+    fn variables(&self, reference: u64) -> Value {
+        let var = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let c = &self.chip8;
+        let vars: Vec<Value> = match reference {
+            REGISTERS_SCOPE => (0..16)
+                .map(|i| var(format!("V{:X}", i), format!("0x{:02x}", c.v[i])))
+                .chain(vec![
+                    var("I".to_string(), format!("0x{:03x}", c.i)),
+                    var("PC".to_string(), format!("0x{:03x}", c.pc)),
+                    var("SP".to_string(), format!("0x{:03x}", c.sp)),
+                ])
+                .collect(),
+            TIMERS_SCOPE => vec![
+                var("DT".to_string(), format!("0x{:02x}", c.dt)),
+                var("ST".to_string(), format!("0x{:02x}", c.st)),
+            ],
+            STACK_SCOPE => backtrace(c)
+                .into_iter()
+                .enumerate()
+                .map(|(i, ret)| var(format!("#{}", i), format!("0x{:03x}", ret)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": vars })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = args["name"].as_str().unwrap_or("");
+        let reg = name.parse()?;
+        let value = args["value"]
+            .as_str()
+            .and_then(parse_reference)
+            .filter(|&v| (0..=0xffff).contains(&v))
+            .ok_or_else(|| format!("Invalid value {}", args["value"]))?;
+        set_register(self.chip8, reg, value as u16).map_err(|e| e.to_string())?;
+        self.debugger.state_changed();
+        let value = register(self.chip8, reg);
+        Ok(json!({ "value": format!("0x{:02x}", value) }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let addr = memory_reference(args)?;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        if addr < 0 || addr as usize >= MEMORY_SIZE {
+            return Ok(json!({ "address": format!("0x{:04x}", addr), "unreadableBytes": count }));
+        }
+        let start = addr as usize;
+        let end = start.saturating_add(count).min(MEMORY_SIZE);
+        Ok(json!({
+            "address": format!("0x{:04x}", start),
+            "data": base64(&self.chip8.memory[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = memory_reference(args)?;
+        if addr < 0 {
+            return Err("Invalid memory reference".to_string());
+        }
+        let data = args["data"]
+            .as_str()
+            .and_then(unbase64)
+            .ok_or("Invalid data")?;
+        self.chip8
+            .poke_memory(addr as usize, &data)
+            .map_err(|e| e.to_string())?;
+        self.debugger.state_changed();
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let base = args["instructionOffset"]
+            .as_i64()
+            .unwrap_or(0)
+            .checked_mul(2)
+            .and_then(|offset| memory_reference(args).ok()?.checked_add(offset))
+            .ok_or("Invalid memory reference")?;
+        // there aren't more instructions in memory
+        let count = args["instructionCount"]
+            .as_i64()
+            .unwrap_or(0)
+            .clamp(0, MEMORY_SIZE as i64);
+        let instructions: Vec<Value> = (0..count)
+            .map(|n| base.saturating_add(n * 2))
+            .map(|addr| {
+                if addr < 0 || addr as usize + 1 >= MEMORY_SIZE {
+                    // editors expect exactly `instructionCount` entries
+                    return json!({ "address": format!("0x{:04x}", addr), "instruction": "??" });
+                }
+                let addr = addr as usize;
+                let (high, low) = (self.chip8.memory[addr], self.chip8.memory[addr + 1]);
+                let text = match Instruction::from_bytes(high, low) {
+                    Ok(ins) => ins.to_string().replace("\t\t", " ").replace('\t', " "),
+                    Err(_) => "UNKNOWN".to_string(),
+                };
+                let mut ins = json!({
+                    "address": format!("0x{:04x}", addr),
+                    "instructionBytes": format!("{:02x} {:02x}", high, low),
+                    "instruction": text,
+                });
+                if let Some(line) = self.source_map.line(addr) {
+                    ins["location"] = self.source_map.source();
+                    ins["line"] = json!(line);
+                }
+                ins
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+}
+
+/// Waits for an editor to connect to `addr` and serves it
+pub fn serve<T: Screen>(chip8: &mut Chip8<T>, addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!(
+        "Waiting for debug adapter client on {}",
+        listener.local_addr()?
+    );
+    let (stream, peer) = listener.accept()?;
+    println!("Debug adapter client connected from {}", peer);
+    stream.set_nodelay(true)?;
+    Session::new(chip8, stream).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+    use std::thread;
+
+    struct Client {
+        conn: Connection,
+        seq: u64,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let msg = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            self.conn.send(&msg).unwrap();
+            loop {
+                let msg = self.conn.read_message().unwrap().unwrap();
+                if msg["type"] == "response" {
+                    assert_eq!(msg["request_seq"], self.seq);
+                    assert_eq!(msg["success"], true, "{}", msg);
+                    return msg["body"].clone();
+                }
+            }
+        }
+
+        fn event(&mut self, name: &str) -> Value {
+            loop {
+                let msg = self.conn.read_message().unwrap().unwrap();
+                if msg["type"] == "event" && msg["event"] == name {
+                    return msg["body"].clone();
+                }
+            }
+        }
+    }
+
+    fn start(prog: &'static [u8]) -> (Client, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.load_from_slice(prog);
+            let (stream, _) = listener.accept().unwrap();
+            Session::new(&mut chip8, stream).run().unwrap();
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let client = Client {
+            conn: Connection {
+                stream,
+                buffer: Vec::new(),
+            },
+            seq: 0,
+        };
+        (client, server)
+    }
+
+    const PROG: &[u8] = &[
+        0x60, 0x05, // 200: mov V0, 05
+        0x22, 0x08, // 202: call 208
+        0xf0, 0x15, // 204: mov DT, V0
+        0x12, 0x06, // 206: jmp 206
+        0x71, 0x01, // 208: add V1, 01
+        0x00, 0xee, // 20a: ret
+    ];
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(unbase64("Zm8="), Some(b"fo".to_vec()));
+        assert_eq!(unbase64("Zm9vYmFy"), Some(b"foobar".to_vec()));
+        assert_eq!(unbase64("Zm9v!"), None);
+    }
+
+    #[test]
+    fn test_memory_reference() {
+        let args = json!({ "memoryReference": "0x0200", "offset": -2 });
+        assert_eq!(memory_reference(&args), Ok(0x1fe));
+        let args = json!({ "memoryReference": "0x7fffffffffffffff", "offset": 1 });
+        assert!(memory_reference(&args).is_err());
+    }
+
+    #[test]
+    fn test_source_map() {
+        let map = SourceMap::from_listing(None, "; header\n0200:\t60 05\n  0202 22 08\nlabel:\n");
+        assert_eq!(map.addr(2), Some(0x200));
+        assert_eq!(map.addr(3), Some(0x202));
+        assert_eq!(map.addr(4), None);
+        assert_eq!(map.line(0x203), Some(3));
+    }
+
+    #[test]
+    fn test_session() {
+        let (mut dap, server) = start(PROG);
+        let caps = dap.request("initialize", json!({ "adapterID": "chiper" }));
+        assert_eq!(caps["supportsDisassembleRequest"], true);
+        dap.event("initialized");
+        dap.request("attach", json!({ "stopOnEntry": true }));
+        // line 5 of the listing is 0208
+        let bps = dap.request(
+            "setBreakpoints",
+            json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 5 }] }),
+        );
+        assert_eq!(bps["breakpoints"][0]["verified"], true);
+        let bps = dap.request(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x7fffffffffffffff", "offset": 1 }] }),
+        );
+        assert_eq!(bps["breakpoints"][0]["verified"], false);
+        dap.request("configurationDone", json!({}));
+        assert_eq!(dap.event("stopped")["reason"], "entry");
+
+        dap.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(dap.event("stopped")["reason"], "breakpoint");
+        let trace = dap.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(trace["totalFrames"], 2);
+        assert_eq!(trace["stackFrames"][0]["line"], 5);
+        assert_eq!(
+            trace["stackFrames"][1]["instructionPointerReference"],
+            "0x0202"
+        );
+
+        let regs = dap.request(
+            "variables",
+            json!({ "variablesReference": REGISTERS_SCOPE }),
+        );
+        assert_eq!(regs["variables"][0]["value"], "0x05");
+        dap.request("stepOut", json!({ "threadId": 1 }));
+        dap.event("stopped");
+        dap.request("next", json!({ "threadId": 1 }));
+        dap.event("stopped");
+        let timers = dap.request("variables", json!({ "variablesReference": TIMERS_SCOPE }));
+        assert_eq!(timers["variables"][0]["value"], "0x05");
+
+        let mem = dap.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0200", "offset": 2, "count": 2 }),
+        );
+        assert_eq!(mem["address"], "0x0202");
+        assert_eq!(mem["data"], base64(&[0x22, 0x08]));
+        // the top left pixel
+        let written = dap.request(
+            "writeMemory",
+            json!({ "memoryReference": "0x0f00", "data": base64(&[0x80]) }),
+        );
+        assert_eq!(written["bytesWritten"], 1);
+        let dis = dap.request(
+            "disassemble",
+            json!({ "memoryReference": "0x0200", "instructionOffset": 1, "instructionCount": 2 }),
+        );
+        assert_eq!(dis["instructions"][0]["instruction"], "call 208");
+        assert_eq!(dis["instructions"][1]["address"], "0x0204");
+
+        let mem = dap.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0ffe", "count": u64::MAX }),
+        );
+        assert_eq!(mem["data"], base64(&[0, 0]));
+        let dis = dap.request(
+            "disassemble",
+            json!({ "memoryReference": "0x0200", "instructionCount": i64::MAX }),
+        );
+        assert_eq!(dis["instructions"].as_array().unwrap().len(), 0x1000);
+
+        dap.request("pause", json!({ "threadId": 1 }));
+        assert_eq!(dap.event("stopped")["reason"], "pause");
+        let eval = dap.request("evaluate", json!({ "expression": "V0 + V1" }));
+        assert_eq!(eval["result"], "0x6 (6)");
+        dap.request("disconnect", json!({}));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+//! Rom database
+//!
+//! Roms are recognized by their SHA-1 in a database following the schema of
+//! the community chip-8-database: `sha1-hashes.json` maps hashes to indices
+//! of `programs.json`, every program lists its roms with their platforms,
+//! quirks, tickrate, colors and keys, and `platforms.json` has quirks and
+//! tickrate of every platform. A small database with the bundled roms is
+//! built in, a full copy of the upstream one can be loaded from a directory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::{sha1_hex, Settings};
+use crate::platform::Quirks;
+use crate::screen::Palette;
+
+const LOG_TARGET: &str = "chiper::loader";
+
+const PLATFORMS: &str = include_str!("../db/platforms.json");
+const PROGRAMS: &str = include_str!("../db/programs.json");
+const HASHES: &str = include_str!("../db/sha1-hashes.json");
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformInfo {
+    id: String,
+    name: String,
+    default_tickrate: Option<u64>,
+    #[serde(default)]
+    quirks: BTreeMap<String, bool>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, RomInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomInfo {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<u64>,
+    /// Quirks differing from the ones of the platform
+    #[serde(default)]
+    quirky_platforms: HashMap<String, BTreeMap<String, bool>>,
+    colors: Option<Colors>,
+    /// Keypad keys of game actions
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    /// Unlit color first, then the lit one
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+pub struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+    platforms: Vec<PlatformInfo>,
+}
+
+/// Rom found in the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub title: String,
+    pub sha1: String,
+    /// Platform id, e.g. `originalChip8`
+    pub platform: String,
+    pub platform_name: String,
+    pub quirks: Quirks,
+    /// Instructions per frame
+    pub tickrate: Option<u64>,
+    pub palette: Option<Palette>,
+    pub keys: BTreeMap<String, u8>,
+}
+
+impl Detection {
+    /// Settings layer applied below the rom sections of the config file
+    pub fn settings(&self) -> Settings {
+        Settings {
+            ipf: self.tickrate,
+            palette: self.palette,
+            keys: self.keys.clone(),
+            quirks: self
+                .quirks
+                .iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            ..Settings::default()
+        }
+    }
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.title, self.platform_name)?;
+        if let Some(tickrate) = self.tickrate {
+            write!(f, ", {} ipf", tickrate)?;
+        }
+        write!(f, ", quirks: {}", self.quirks)
+    }
+}
+
+/// Tickrate of `of` in the database, zero is ignored as no frame would end
+fn valid_tickrate(tickrate: Option<u64>, of: &str) -> Option<u64> {
+    if tickrate == Some(0) {
+        log::warn!(target: LOG_TARGET, "Ignoring tickrate 0 of {}", of);
+        return None;
+    }
+    tickrate
+}
+
+fn invalid_data(file: &str, e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file, e))
+}
+
+impl Database {
+    /// Database of the bundled roms
+    pub fn bundled() -> Database {
+        Database::parse(PLATFORMS, PROGRAMS, HASHES).expect("Bundled database is invalid")
+    }
+
+    /// Loads the database from a directory with the files of the
+    /// chip-8-database
+    pub fn load(dir: &Path) -> io::Result<Database> {
+        let read = |name: &str| fs::read_to_string(dir.join(name));
+        Database::parse(
+            &read("platforms.json")?,
+            &read("programs.json")?,
+            &read("sha1-hashes.json")?,
+        )
+    }
+
+    fn parse(platforms: &str, programs: &str, hashes: &str) -> io::Result<Database> {
+        Ok(Database {
+            platforms: serde_json::from_str(platforms)
+                .map_err(|e| invalid_data("platforms.json", e))?,
+            programs: serde_json::from_str(programs)
+                .map_err(|e| invalid_data("programs.json", e))?,
+            hashes: serde_json::from_str(hashes)
+                .map_err(|e| invalid_data("sha1-hashes.json", e))?,
+        })
+    }
+
+    /// Looks the rom up by its hash, the first platform of the rom is used
+    pub fn detect(&self, rom: &[u8]) -> Option<Detection> {
+        let sha1 = sha1_hex(rom);
+        let program = self.programs.get(*self.hashes.get(&sha1)?)?;
+        let info = program.roms.get(&sha1)?;
+        let id = info.platforms.first()?;
+        let platform = self.platforms.iter().find(|p| &p.id == id)?;
+
+        let mut quirks = Quirks::default();
+        let overrides = info.quirky_platforms.get(id).into_iter().flatten();
+        for (name, &value) in platform.quirks.iter().chain(overrides) {
+            // quirks of other platforms, e.g. of SUPER-CHIP scrolling, aren't
+            // emulated
+            if quirks.set(name, value).is_err() {
+                log::warn!(target: LOG_TARGET, "Unsupported quirk '{}' of {}", name, id);
+            }
+        }
+        let palette = info
+            .colors
+            .as_ref()
+            .filter(|colors| colors.pixels.len() >= 2)
+            .and_then(|colors| {
+                format!("{},{}", colors.pixels[1], colors.pixels[0])
+                    .parse()
+                    .ok()
+            });
+        Some(Detection {
+            title: program.title.clone(),
+            sha1,
+            platform: id.clone(),
+            platform_name: platform.name.clone(),
+            quirks,
+            tickrate: valid_tickrate(info.tickrate, &program.title)
+                .or_else(|| valid_tickrate(platform.default_tickrate, id)),
+            palette,
+            keys: info.keys.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    #[test]
+    fn test_bundled() {
+        let db = Database::bundled();
+        let pong = db.detect(include_bytes!("../roms/pong.ch8")).unwrap();
+        assert_eq!(pong.title, "Pong");
+        assert_eq!(pong.platform, "originalChip8");
+        assert_eq!(pong.quirks, Platform::Chip8.quirks());
+        assert_eq!(pong.tickrate, Some(15));
+        assert_eq!(pong.keys["player2Down"], 0xd);
+        assert_eq!(
+            pong.to_string(),
+            "Pong (Cosmac VIP CHIP-8), 15 ipf, quirks: vblank, logic"
+        );
+        assert!(db.detect(&[0x12, 0x00]).is_none());
+
+        // every bundled entry refers to a known platform
+        for (sha1, &idx) in &db.hashes {
+            let info = &db.programs[idx].roms[sha1];
+            for id in &info.platforms {
+                assert!(db.platforms.iter().any(|p| &p.id == id), "{}", id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quirky_platform() {
+        let programs = r##"[{
+            "title": "Test",
+            "roms": {
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709": {
+                    "platforms": ["superchip", "xochip"],
+                    "tickrate": 20,
+                    "quirkyPlatforms": {"superchip": {"wrap": true, "vfOrder": true}},
+                    "colors": {"pixels": ["#101010", "#f0f0f0"]}
+                }
+            }
+        }]"##;
+        let hashes = r#"{"da39a3ee5e6b4b0d3255bfef95601890afd80709": 0}"#;
+        let db = Database::parse(PLATFORMS, programs, hashes).unwrap();
+        let detection = db.detect(&[]).unwrap();
+        assert_eq!(detection.platform, "superchip");
+        let mut quirks = Platform::SuperChip.quirks();
+        quirks.wrap = true;
+        assert_eq!(detection.quirks, quirks);
+        assert_eq!(detection.tickrate, Some(20));
+        assert_eq!(detection.palette, Some("#f0f0f0,#101010".parse().unwrap()));
+        let settings = detection.settings();
+        assert_eq!(settings.quirks().unwrap(), quirks);
+        assert_eq!(settings.ticks_per_frame(), 20);
+
+        // the tickrate of the platform is used instead of zero
+        let programs = programs.replace(r#""tickrate": 20"#, r#""tickrate": 0"#);
+        let db = Database::parse(PLATFORMS, &programs, hashes).unwrap();
+        assert_eq!(db.detect(&[]).unwrap().tickrate, Some(30));
+    }
+}
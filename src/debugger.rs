@@ -0,0 +1,986 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, stdin, Write};
+use std::str::FromStr;
+
+use crate::chip8::{Access, Chip8, ExecError, MemAccess, MEMORY_SIZE, STACK_MEMORY_END};
+use crate::instruction::Instruction;
+use crate::screen::Screen;
+
+pub mod expr;
+pub mod reverse;
+
+use expr::{Condition, Format};
+use reverse::History;
+
+const HELP: &str = "\
+Addresses and values are hexadecimal (0x prefix is optional), counts are decimal.
+
+  s, step [N]              execute N instructions (default 1)
+  n, next                  step over subroutine calls
+  fin, finish              run until the current subroutine returns
+  c, continue (r, run)     run until a breakpoint or watchpoint is hit
+  u, until draw|frame      run until the next draw or the next frame
+  back [N]                 undo last N instructions (default 1)
+  rc, reverse-continue     run backwards until a breakpoint or watchpoint
+  b, break ADDR [if COND]  set breakpoint at ADDR, optionally conditional
+  b, break if COND         stop as soon as COND becomes true
+  d, delete [ADDR|#N]      delete breakpoints at ADDR, number N or all
+  trace ADDR FORMAT        print FORMAT every time ADDR is reached
+  untrace [ADDR]           delete tracepoint at ADDR or all tracepoints
+  watch ADDR [LEN]         stop when memory is written
+  rwatch ADDR [LEN]        stop when memory is read
+  awatch ADDR [LEN]        stop when memory is read or written
+  unwatch [ADDR]           delete watchpoint at ADDR or all watchpoints
+  i, info                  list breakpoints, watchpoints and tracepoints
+  regs                     print registers
+  set REG VALUE            set register V0-VF, I, SP, PC, DT or ST
+  x ADDR [N]               examine N bytes of memory (default 1)
+  hd, hexdump ADDR [LEN]   hexdump LEN bytes of memory (default 100)
+  dis [ADDR [N]]           disassemble around PC or N instructions at ADDR
+  bt, backtrace            print call stack
+  dump ADDR LEN FILE       save memory range to FILE
+  load ADDR FILE           load FILE into memory at ADDR
+  h, help                  print this help
+  q, quit                  exit debugger
+
+Empty line repeats the last command.
+
+COND is an expression over V0-VF, I, PC, SP, DT, ST and memory bytes [ADDR]
+with C operators, e.g. 'V3 == 5 && I >= 300' or '[2f0] != 0'. Suffix
+'after MNEMONIC' checks it only after such instruction: 'VF == 1 after draw'.
+FORMAT is a text with {EXPR} placeholders, {EXPR:d} prints decimal value.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+    Sp,
+    Pc,
+    Dt,
+    St,
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Register, String> {
+        let reg = match s.to_ascii_uppercase().as_str() {
+            "I" => Register::I,
+            "SP" => Register::Sp,
+            "PC" => Register::Pc,
+            "DT" => Register::Dt,
+            "ST" => Register::St,
+            name if name.len() == 2 && name.starts_with('V') => {
+                let idx = usize::from_str_radix(&name[1..], 16)
+                    .map_err(|_| format!("Unknown register '{}'", s))?;
+                Register::V(idx)
+            }
+            _ => return Err(format!("Unknown register '{}'", s)),
+        };
+        Ok(reg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: usize,
+    pub len: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &MemAccess) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => access.kind == Access::Read,
+            WatchKind::Write => access.kind == Access::Write,
+            WatchKind::Access => true,
+        };
+        kind_matches && (self.addr..self.addr + self.len).contains(&access.addr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// Breakpoint without address is checked after every instruction
+    pub addr: Option<usize>,
+    pub condition: Option<Condition>,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.addr, &self.condition) {
+            (Some(addr), Some(cond)) => write!(f, "{:04x} if {}", addr, cond),
+            (Some(addr), None) => write!(f, "{:04x}", addr),
+            (None, Some(cond)) => write!(f, "if {}", cond),
+            (None, None) => write!(f, "never"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tracepoint {
+    pub addr: usize,
+    pub format: Format,
+}
+
+/// Breakpoints selected by `delete`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    All,
+    Addr(usize),
+    /// 1-based number as printed by `info`
+    Number(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Step(usize),
+    Next,
+    Finish,
+    Continue,
+    UntilDraw,
+    UntilFrame,
+    Back(u64),
+    ReverseContinue,
+    Break(Breakpoint),
+    Delete(Selection),
+    Trace(Tracepoint),
+    Untrace(Option<usize>),
+    Watch(Watchpoint),
+    Unwatch(Option<usize>),
+    Info,
+    Registers,
+    Set(Register, u16),
+    Examine(usize, usize),
+    Hexdump(usize, usize),
+    Disassemble(Option<(usize, usize)>),
+    Backtrace,
+    Dump(usize, usize, String),
+    Load(usize, String),
+    Quit,
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid hex number '{}'", s))
+}
+
+fn parse_addr(s: &str) -> Result<usize, String> {
+    let addr = parse_hex(s)? as usize;
+    if addr >= MEMORY_SIZE {
+        return Err(format!("Address {:x} is out of memory", addr));
+    }
+    Ok(addr)
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("Invalid count '{}'", s))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        // text after the n-th argument, used by free form arguments
+        let rest = |n: usize| -> &str {
+            let mut text = line.trim_start()[name.len()..].trim_start();
+            for _ in 0..n {
+                let word_end = text.find(char::is_whitespace).unwrap_or(text.len());
+                text = text[word_end..].trim_start();
+            }
+            text.trim_end()
+        };
+
+        let arg = |idx: usize| -> Result<&str, String> {
+            args.get(idx)
+                .copied()
+                .ok_or_else(|| format!("'{}' requires more arguments, see 'help'", name))
+        };
+        let opt_arg = |idx: usize| args.get(idx).copied();
+        let watch = |kind| -> Result<Command, String> {
+            let len = opt_arg(1).map(parse_hex).transpose()?.unwrap_or(1) as usize;
+            if len == 0 {
+                return Err("Watchpoint length can't be 0".to_string());
+            }
+            Ok(Command::Watch(Watchpoint {
+                addr: parse_addr(arg(0)?)?,
+                len,
+                kind,
+            }))
+        };
+
+        let cmd = match name {
+            "h" | "help" => Command::Help,
+            "s" | "step" => Command::Step(opt_arg(0).map(parse_count).transpose()?.unwrap_or(1)),
+            "n" | "next" => Command::Next,
+            "fin" | "finish" => Command::Finish,
+            "c" | "continue" | "r" | "run" => Command::Continue,
+            "u" | "until" => match arg(0)? {
+                "draw" => Command::UntilDraw,
+                "frame" => Command::UntilFrame,
+                other => return Err(format!("Unknown 'until' target '{}'", other)),
+            },
+            "back" => Command::Back(opt_arg(0).map(parse_count).transpose()?.unwrap_or(1) as u64),
+            "rc" | "reverse-continue" => Command::ReverseContinue,
+            "b" | "break" => {
+                let (addr, cond) = match arg(0)? {
+                    "if" => (None, Some(rest(1))),
+                    addr => match opt_arg(1) {
+                        Some("if") => (Some(parse_addr(addr)?), Some(rest(2))),
+                        Some(other) => return Err(format!("Expected 'if', got '{}'", other)),
+                        None => (Some(parse_addr(addr)?), None),
+                    },
+                };
+                Command::Break(Breakpoint {
+                    addr,
+                    condition: cond.map(str::parse).transpose()?,
+                })
+            }
+            "d" | "delete" => Command::Delete(match opt_arg(0) {
+                None => Selection::All,
+                Some(n) if n.starts_with('#') => Selection::Number(parse_count(&n[1..])?),
+                Some(addr) => Selection::Addr(parse_addr(addr)?),
+            }),
+            "trace" => {
+                let addr = parse_addr(arg(0)?)?;
+                arg(1)?;
+                Command::Trace(Tracepoint {
+                    addr,
+                    format: rest(1).parse()?,
+                })
+            }
+            "untrace" => Command::Untrace(opt_arg(0).map(parse_addr).transpose()?),
+            "watch" => watch(WatchKind::Write)?,
+            "rwatch" => watch(WatchKind::Read)?,
+            "awatch" => watch(WatchKind::Access)?,
+            "unwatch" => Command::Unwatch(opt_arg(0).map(parse_addr).transpose()?),
+            "i" | "info" => Command::Info,
+            "regs" => Command::Registers,
+            "set" => Command::Set(arg(0)?.parse()?, parse_hex(arg(1)?)?),
+            "x" => Command::Examine(
+                parse_addr(arg(0)?)?,
+                opt_arg(1).map(parse_count).transpose()?.unwrap_or(1),
+            ),
+            "hd" | "hexdump" => Command::Hexdump(
+                parse_addr(arg(0)?)?,
+                opt_arg(1).map(parse_hex).transpose()?.unwrap_or(0x100) as usize,
+            ),
+            "dis" => match opt_arg(0) {
+                Some(addr) => Command::Disassemble(Some((
+                    parse_addr(addr)?,
+                    opt_arg(1).map(parse_count).transpose()?.unwrap_or(10),
+                ))),
+                None => Command::Disassemble(None),
+            },
+            "bt" | "backtrace" => Command::Backtrace,
+            "dump" => Command::Dump(
+                parse_addr(arg(0)?)?,
+                parse_hex(arg(1)?)? as usize,
+                arg(2)?.to_string(),
+            ),
+            "load" => Command::Load(parse_addr(arg(0)?)?, arg(1)?.to_string()),
+            "q" | "quit" => Command::Quit,
+            unknown => return Err(format!("Unknown debug command '{}'", unknown)),
+        };
+        Ok(cmd)
+    }
+}
+
+/// Reason why execution was stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// Requested amount of instructions was executed
+    Done,
+    Breakpoint(usize),
+    Watchpoint(MemAccess),
+    /// Program jumps to itself forever
+    Halted,
+    /// Instruction at pc can't be executed
+    Fault(ExecError),
+    /// Breakpoint condition can't be evaluated
+    ConditionError(String),
+    /// Reverse execution reached the oldest known state
+    HistoryStart,
+    /// The window was closed
+    Quit,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Done => Ok(()),
+            StopReason::Breakpoint(addr) => write!(f, "Breakpoint at {:04x}", addr),
+            StopReason::Watchpoint(access) => write!(
+                f,
+                "Watchpoint: {} {:04x} = {:02x}",
+                match access.kind {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                },
+                access.addr,
+                access.value
+            ),
+            StopReason::Halted => write!(f, "Program halted"),
+            StopReason::Fault(e) => write!(f, "{}", e),
+            StopReason::ConditionError(e) => write!(f, "Breakpoint condition failed: {}", e),
+            StopReason::HistoryStart => write!(f, "Reached the beginning of the history"),
+            StopReason::Quit => write!(f, "Window closed"),
+        }
+    }
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    tracepoints: Vec<Tracepoint>,
+    watchpoints: Vec<Watchpoint>,
+    history: History,
+    /// The window was closed while the program ran
+    closed: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            tracepoints: Vec::new(),
+            watchpoints: Vec::new(),
+            history: History::new(),
+            closed: false,
+        }
+    }
+
+    /// Interactive command loop over stdin
+    pub fn run<T: Screen>(&mut self, chip8: &mut Chip8<T>) -> io::Result<()> {
+        println!("Enter debug mode, 'h' - for help");
+        print_location(chip8, chip8.pc);
+        let mut buffer = String::new();
+        let mut last_cmd = String::new();
+        // window is refreshed after every command
+        while !self.closed && chip8.update_frontend(&self.breakpoint_addrs()) {
+            print!("(chiper - db) ");
+            io::stdout().flush().expect("Could not flush stdout");
+            buffer.clear();
+            if stdin().read_line(&mut buffer)? == 0 {
+                // EOF
+                break;
+            }
+            let mut line = buffer.trim();
+            if line.is_empty() {
+                line = &last_cmd;
+            } else {
+                last_cmd = line.to_string();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(Command::Quit) => break,
+                Ok(cmd) => {
+                    if let Err(e) = self.execute(chip8, cmd) {
+                        eprintln!("{}", e);
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes single debugger command
+    pub fn execute<T: Screen>(&mut self, chip8: &mut Chip8<T>, cmd: Command) -> io::Result<()> {
+        match cmd {
+            Command::Help => println!("{}", HELP),
+            Command::Step(count) => {
+                let mut left = count;
+                let reason = self.run_until(chip8, |_, _| {
+                    left = left.saturating_sub(1);
+                    left == 0
+                });
+                self.report(chip8, reason);
+                chip8.dump_registers();
+            }
+            Command::Next => {
+                let reason = self.step_over(chip8);
+                self.report(chip8, reason);
+                chip8.dump_registers();
+            }
+            Command::Finish => {
+                let reason = self.step_out(chip8).map_err(io::Error::other)?;
+                self.report(chip8, reason);
+            }
+            Command::Continue => {
+                let reason = self.run_until(chip8, |_, _| false);
+                self.report(chip8, reason);
+            }
+            Command::UntilDraw => {
+                let reason = self.run_until(chip8, |_, ins| matches!(ins, Instruction::Draw(..)));
+                self.report(chip8, reason);
+            }
+            Command::UntilFrame => {
+                let frame = chip8.frames();
+                let reason = self.run_until(chip8, |c, _| c.frames() > frame);
+                self.report(chip8, reason);
+            }
+            Command::Back(count) => {
+                let reason = self.step_back(chip8, count);
+                self.report(chip8, reason);
+            }
+            Command::ReverseContinue => {
+                let reason = self.reverse_continue(chip8);
+                self.report(chip8, reason);
+            }
+            Command::Break(bp) => {
+                println!("Breakpoint {}: {}", self.breakpoints.len() + 1, bp);
+                self.breakpoints.push(bp);
+            }
+            Command::Delete(Selection::All) => self.breakpoints.clear(),
+            Command::Delete(Selection::Addr(addr)) => {
+                if !self.remove_breakpoint(addr) {
+                    return Err(io::Error::other(format!("No breakpoint at {:04x}", addr)));
+                }
+            }
+            Command::Delete(Selection::Number(n)) => {
+                if n == 0 || n > self.breakpoints.len() {
+                    return Err(io::Error::other(format!("No breakpoint number {}", n)));
+                }
+                self.breakpoints.remove(n - 1);
+            }
+            Command::Trace(tp) => self.tracepoints.push(tp),
+            Command::Untrace(Some(addr)) => self.tracepoints.retain(|tp| tp.addr != addr),
+            Command::Untrace(None) => self.tracepoints.clear(),
+            Command::Watch(watchpoint) => self.watchpoints.push(watchpoint),
+            Command::Unwatch(Some(addr)) => self.watchpoints.retain(|w| w.addr != addr),
+            Command::Unwatch(None) => self.watchpoints.clear(),
+            Command::Info => {
+                for (n, bp) in self.breakpoints.iter().enumerate() {
+                    println!("#{}\tbreak\t{}", n + 1, bp);
+                }
+                for tp in &self.tracepoints {
+                    println!("trace\t{:04x}\t{}", tp.addr, tp.format);
+                }
+                for w in &self.watchpoints {
+                    println!("{:?}\t{:04x}-{:04x}", w.kind, w.addr, w.addr + w.len - 1);
+                }
+            }
+            Command::Registers => chip8.dump_registers(),
+            Command::Set(reg, value) => {
+                set_register(chip8, reg, value)?;
+                // replaying from older snapshots would give a different state
+                self.history.clear();
+            }
+            Command::Examine(addr, count) => {
+                for a in addr..addr.saturating_add(count).min(MEMORY_SIZE) {
+                    let b = chip8.memory[a];
+                    println!("{:04x}:\t0x{:02x}\t{:3}\t0b{:08b}", a, b, b, b);
+                }
+            }
+            Command::Hexdump(addr, len) => hexdump(&chip8.memory, addr, len),
+            Command::Disassemble(Some((addr, count))) => {
+                for a in (addr..MEMORY_SIZE - 1).step_by(2).take(count) {
+                    self.print_listing_line(chip8, a);
+                }
+            }
+            Command::Disassemble(None) => {
+                let start = chip8.pc.saturating_sub(10);
+                for a in (start..(chip8.pc + 12).min(MEMORY_SIZE - 1)).step_by(2) {
+                    self.print_listing_line(chip8, a);
+                }
+            }
+            Command::Backtrace => {
+                println!("#0  {:04x}", chip8.pc);
+                for (frame, ret) in backtrace(chip8).into_iter().enumerate() {
+                    let call = ret.saturating_sub(2);
+                    print!("#{}  {:04x}  ", frame + 1, call);
+                    match chip8.instruction_at(call) {
+                        Some(ins) => println!("{}", ins),
+                        None => println!("?"),
+                    }
+                }
+            }
+            Command::Dump(addr, len, path) => {
+                let end = (addr + len).min(MEMORY_SIZE);
+                fs::write(&path, &chip8.memory[addr..end])?;
+                println!("Saved {:04x}-{:04x} to {}", addr, end, path);
+            }
+            Command::Load(addr, path) => {
+                let data = fs::read(&path)?;
+                if chip8.poke_memory(addr, &data).is_err() {
+                    return Err(io::Error::other("File doesn't fit into memory"));
+                }
+                self.history.clear();
+                println!("Loaded {} bytes to {:04x}", data.len(), addr);
+            }
+            Command::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// Executes instructions until `done` returns true for the executed
+    /// instruction, or one of breakpoints or watchpoints is hit
+    pub fn run_until<T, F>(&mut self, chip8: &mut Chip8<T>, mut done: F) -> StopReason
+    where
+        T: Screen,
+        F: FnMut(&Chip8<T>, Instruction) -> bool,
+    {
+        loop {
+            let instruction = match chip8.fetch() {
+                Ok(ins) => ins,
+                Err(e) => return StopReason::Fault(e),
+            };
+            if chip8.is_halted() {
+                return StopReason::Halted;
+            }
+            self.history.record(chip8);
+            if let Err(e) = chip8.emulate_op() {
+                return StopReason::Fault(e);
+            }
+            if chip8.cycles.is_multiple_of(chip8.ticks_per_frame)
+                && !chip8.update_frontend(&self.breakpoint_addrs())
+            {
+                self.closed = true;
+                return StopReason::Quit;
+            }
+            if let Some(access) = self.hit_watchpoint(chip8.last_accesses()) {
+                return StopReason::Watchpoint(access);
+            }
+            if done(chip8, instruction) {
+                return StopReason::Done;
+            }
+            self.fire_tracepoints(chip8);
+            if let Some(reason) = self.hit_breakpoint(chip8, instruction) {
+                return reason;
+            }
+        }
+    }
+
+    /// Checks whether `executed` instruction hit any of watchpoints or
+    /// breakpoints, tracepoints are ignored
+    fn check_stop<T: Screen>(&self, chip8: &Chip8<T>, executed: Instruction) -> Option<StopReason> {
+        if let Some(access) = self.hit_watchpoint(chip8.last_accesses()) {
+            return Some(StopReason::Watchpoint(access));
+        }
+        self.hit_breakpoint(chip8, executed)
+    }
+
+    /// Checks breakpoints once `executed` instruction has finished
+    fn hit_breakpoint<T: Screen>(
+        &self,
+        chip8: &Chip8<T>,
+        executed: Instruction,
+    ) -> Option<StopReason> {
+        for bp in &self.breakpoints {
+            if bp.addr.is_some() && bp.addr != Some(chip8.pc) {
+                continue;
+            }
+            let hit = match &bp.condition {
+                Some(cond) => cond.check(chip8, executed),
+                None => Ok(true),
+            };
+            match hit {
+                Ok(true) => return Some(StopReason::Breakpoint(chip8.pc)),
+                Ok(false) => {}
+                Err(e) => return Some(StopReason::ConditionError(e)),
+            }
+        }
+        None
+    }
+
+    fn fire_tracepoints<T: Screen>(&self, chip8: &Chip8<T>) {
+        for tp in self.tracepoints.iter().filter(|tp| tp.addr == chip8.pc) {
+            println!("{:04x}: {}", tp.addr, tp.format.render(chip8));
+        }
+    }
+
+    /// Executes next instruction, subroutine calls are executed entirely
+    pub fn step_over<T: Screen>(&mut self, chip8: &mut Chip8<T>) -> StopReason {
+        match chip8.current_instruction() {
+            Some(Instruction::Call(_)) => {
+                let (ret, sp) = (chip8.pc + 2, chip8.sp);
+                self.run_until(chip8, |c, _| c.pc == ret && c.sp == sp)
+            }
+            _ => self.run_until(chip8, |_, _| true),
+        }
+    }
+
+    /// Runs until the current subroutine returns to the caller
+    pub fn step_out<T: Screen>(&mut self, chip8: &mut Chip8<T>) -> Result<StopReason, String> {
+        let sp = chip8.sp;
+        if sp >= STACK_MEMORY_END {
+            return Err("Not in a subroutine".to_string());
+        }
+        Ok(self.run_until(chip8, |c, _| c.sp > sp))
+    }
+
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) {
+        self.breakpoints.push(bp);
+    }
+
+    /// Removes breakpoints at `addr`, returns false if there were none
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|bp| bp.addr != Some(addr));
+        self.breakpoints.len() != count
+    }
+
+    /// Addresses of all breakpoints, conditional ones included
+    pub fn breakpoint_addrs(&self) -> Vec<usize> {
+        self.breakpoints.iter().filter_map(|bp| bp.addr).collect()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes watchpoint, returns false if there was no such watchpoint
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    /// Drops reverse execution history, must be called when machine state
+    /// is changed outside of the debugger
+    pub fn state_changed(&mut self) {
+        self.history.clear();
+    }
+
+    fn hit_watchpoint(&self, accesses: &[MemAccess]) -> Option<MemAccess> {
+        accesses
+            .iter()
+            .find(|a| self.watchpoints.iter().any(|w| w.matches(a)))
+            .copied()
+    }
+
+    fn report<T: Screen>(&self, chip8: &Chip8<T>, reason: StopReason) {
+        if reason != StopReason::Done {
+            println!("{}", reason);
+        }
+        print_location(chip8, chip8.pc);
+    }
+
+    fn print_listing_line<T: Screen>(&self, chip8: &Chip8<T>, addr: usize) {
+        let has_breakpoint = self.breakpoints.iter().any(|bp| bp.addr == Some(addr));
+        let marker = match (addr == chip8.pc, has_breakpoint) {
+            (true, true) => "*>",
+            (true, false) => "=>",
+            (false, true) => "* ",
+            (false, false) => "  ",
+        };
+        print!("{} ", marker);
+        print_location(chip8, addr);
+    }
+}
+
+fn print_location<T: Screen>(chip8: &Chip8<T>, addr: usize) {
+    println!("{}", chip8.disassembly_line(addr));
+}
+
+/// Return addresses stored on the stack, the most recent call goes first
+pub(crate) fn backtrace<T: Screen>(chip8: &Chip8<T>) -> Vec<usize> {
+    (chip8.sp..STACK_MEMORY_END.min(MEMORY_SIZE - 1))
+        .step_by(2)
+        .map(|a| (chip8.memory[a] as usize) << 8 | chip8.memory[a + 1] as usize)
+        .collect()
+}
+
+pub(crate) fn register<T: Screen>(chip8: &Chip8<T>, reg: Register) -> u16 {
+    match reg {
+        Register::V(idx) => chip8.v[idx] as u16,
+        Register::I => chip8.i,
+        Register::Sp => chip8.sp as u16,
+        Register::Pc => chip8.pc as u16,
+        Register::Dt => chip8.dt as u16,
+        Register::St => chip8.st as u16,
+    }
+}
+
+pub(crate) fn set_register<T: Screen>(
+    chip8: &mut Chip8<T>,
+    reg: Register,
+    value: u16,
+) -> io::Result<()> {
+    let byte = || -> io::Result<u8> {
+        if value > 0xff {
+            return Err(io::Error::other(format!(
+                "{:x} doesn't fit into 8 bits",
+                value
+            )));
+        }
+        Ok(value as u8)
+    };
+    match reg {
+        Register::V(idx) => chip8.v[idx] = byte()?,
+        Register::I => chip8.i = value,
+        Register::Sp => chip8.sp = value as usize,
+        Register::Pc => chip8.pc = value as usize,
+        Register::Dt => chip8.dt = byte()?,
+        Register::St => chip8.st = byte()?,
+    }
+    Ok(())
+}
+
+fn hexdump(memory: &[u8], addr: usize, len: usize) {
+    let end = (addr + len).min(memory.len());
+    for (line, chunk) in memory[addr..end].chunks(16).enumerate() {
+        print!("{:04x}: ", addr + line * 16);
+        for b in chunk {
+            print!(" {:02x}", b);
+        }
+        print!("{}  |", "   ".repeat(16 - chunk.len()));
+        for &b in chunk {
+            let c = if b.is_ascii_graphic() { b as char } else { '.' };
+            print!("{}", c);
+        }
+        println!("|");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::{Input, NoScreen};
+
+    fn chip8_with(prog: &[u8]) -> Chip8<NoScreen> {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(prog);
+        chip8
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!("s".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 12".parse(), Ok(Command::Step(12)));
+        assert_eq!(
+            "b 0x20a".parse(),
+            Ok(Command::Break(Breakpoint {
+                addr: Some(0x20a),
+                condition: None,
+            }))
+        );
+        assert_eq!("d #2".parse(), Ok(Command::Delete(Selection::Number(2))));
+        assert_eq!("set vA ff".parse(), Ok(Command::Set(Register::V(10), 0xff)));
+        assert_eq!(
+            "rwatch 300 2".parse(),
+            Ok(Command::Watch(Watchpoint {
+                addr: 0x300,
+                len: 2,
+                kind: WatchKind::Read,
+            }))
+        );
+        assert_eq!("u frame".parse(), Ok(Command::UntilFrame));
+        assert!("b 1000".parse::<Command>().is_err());
+        assert!("set V10 1".parse::<Command>().is_err());
+        assert!("frobnicate".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut chip8 = chip8_with(&[
+            0x60, 0x01, // 200: mov V0, 01
+            0x61, 0x02, // 202: mov V1, 02
+            0x62, 0x03, // 204: mov V2, 03
+            0x12, 0x06, // 206: jmp 206
+        ]);
+        let mut db = Debugger::new();
+        db.execute(&mut chip8, "b 204".parse().unwrap()).unwrap();
+        let reason = db.run_until(&mut chip8, |_, _| false);
+        assert_eq!(reason, StopReason::Breakpoint(0x204));
+        assert_eq!(chip8.v[1], 2);
+        assert_eq!(chip8.v[2], 0);
+        // continue from the breakpoint doesn't hit it again
+        let reason = db.run_until(&mut chip8, |_, _| false);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(chip8.v[2], 3);
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut chip8 = chip8_with(&[
+            0x60, 0x01, // 200: mov V0, 01
+            0x00, 0xee, // 202: ret
+        ]);
+        let mut db = Debugger::new();
+        let reason = db.run_until(&mut chip8, |_, _| false);
+        assert_eq!(
+            reason.to_string(),
+            "Return with an empty stack at 0202 (00ee)"
+        );
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    /// Screen of a window closed right away
+    struct ClosedScreen {}
+
+    impl Screen for ClosedScreen {
+        fn clear(&mut self) {}
+
+        fn draw_px(&mut self, _x: i32, _y: i32) {}
+
+        fn clear_px(&mut self, _x: i32, _y: i32) {}
+
+        fn present(&mut self) {}
+
+        fn poll_events(&mut self, _keys: &mut [bool; 16]) -> Input {
+            Input {
+                quit: true,
+                redraw: false,
+            }
+        }
+    }
+
+    #[test]
+    fn test_window_closed() {
+        let mut chip8 = Chip8::new(ClosedScreen {});
+        chip8.load_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        let mut db = Debugger::new();
+        assert_eq!(db.run_until(&mut chip8, |_, _| false), StopReason::Quit);
+        assert_eq!(chip8.cycles(), chip8.ticks_per_frame);
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut chip8 = chip8_with(&[
+            0xa3, 0x00, // 200: mov I, 300
+            0xf1, 0x65, // 202: movm V0-V1, I
+            0xf1, 0x55, // 204: movm I, V0-V1
+            0x12, 0x06, // 206: jmp 206
+        ]);
+        let mut db = Debugger::new();
+        db.execute(&mut chip8, "watch 301".parse().unwrap())
+            .unwrap();
+        let reason = db.run_until(&mut chip8, |_, _| false);
+        assert_eq!(
+            reason,
+            StopReason::Watchpoint(MemAccess {
+                addr: 0x301,
+                kind: Access::Write,
+                value: 0,
+            })
+        );
+        assert_eq!(chip8.pc, 0x206);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut chip8 = chip8_with(&[
+            0x22, 0x06, // 200: call 206
+            0x60, 0x01, // 202: mov V0, 01
+            0x12, 0x04, // 204: jmp 204
+            0x61, 0x01, // 206: mov V1, 01
+            0x00, 0xee, // 208: ret
+        ]);
+        let mut db = Debugger::new();
+        assert_eq!(db.step_over(&mut chip8), StopReason::Done);
+        assert_eq!((chip8.pc, chip8.v[1]), (0x202, 1));
+
+        let mut chip8 = chip8_with(&[0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x01, 0x00, 0xee]);
+        assert!(db.step_out(&mut chip8).is_err());
+        db.run_until(&mut chip8, |_, _| true);
+        assert_eq!(backtrace(&chip8), vec![0x202]);
+        assert_eq!(db.step_out(&mut chip8), Ok(StopReason::Done));
+        assert_eq!(chip8.pc, 0x202);
+        assert!(backtrace(&chip8).is_empty());
+    }
+
+    #[test]
+    fn test_until_draw() {
+        let mut chip8 = chip8_with(&[
+            0x60, 0x01, // 200: mov V0, 01
+            0xd0, 0x01, // 202: draw V0, V0, 1
+            0x12, 0x04, // 204: jmp 204
+        ]);
+        let mut db = Debugger::new();
+        db.execute(&mut chip8, Command::UntilDraw).unwrap();
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn test_set_register() {
+        let mut chip8 = chip8_with(&[]);
+        let mut db = Debugger::new();
+        db.execute(&mut chip8, "set v3 12".parse().unwrap())
+            .unwrap();
+        db.execute(&mut chip8, "set I 0x345".parse().unwrap())
+            .unwrap();
+        assert!(db
+            .execute(&mut chip8, "set DT 100".parse().unwrap())
+            .is_err());
+        assert_eq!((chip8.v[3], chip8.i, chip8.dt), (0x12, 0x345, 0));
+    }
+
+    #[test]
+    fn test_examine() {
+        let mut chip8 = chip8_with(&[]);
+        let mut db = Debugger::new();
+        db.execute(&mut chip8, "x ffe 18446744073709551615".parse().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut chip8 = chip8_with(&[
+            0x70, 0x01, // 200: add V0, 01
+            0x12, 0x00, // 202: jmp 200
+        ]);
+        let mut db = Debugger::new();
+        db.execute(&mut chip8, "b 202 if V0 == 3".parse().unwrap())
+            .unwrap();
+        db.run_until(&mut chip8, |_, _| false);
+        assert_eq!((chip8.pc, chip8.v[0]), (0x202, 3));
+
+        db.execute(&mut chip8, "d".parse().unwrap()).unwrap();
+        db.execute(&mut chip8, "b if V0 >= 10 && [I] == f0".parse().unwrap())
+            .unwrap();
+        db.run_until(&mut chip8, |_, _| false);
+        assert_eq!(chip8.v[0], 0x10);
+
+        db.execute(&mut chip8, "d #1".parse().unwrap()).unwrap();
+        db.execute(&mut chip8, "b if [1000]".parse().unwrap())
+            .unwrap();
+        assert!(matches!(
+            db.run_until(&mut chip8, |_, _| false),
+            StopReason::ConditionError(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_tracepoint() {
+        let cmd: Command = "trace 204  V0={V0:d} I={I}".parse().unwrap();
+        let mut chip8 = chip8_with(&[]);
+        chip8.i = 0x2a;
+        match cmd {
+            Command::Trace(tp) => {
+                assert_eq!(tp.addr, 0x204);
+                assert_eq!(tp.format.render(&chip8), "V0=0 I=2a");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!("trace 204".parse::<Command>().is_err());
+    }
+}
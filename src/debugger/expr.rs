@@ -0,0 +1,458 @@
+//! Expressions over the machine state used by conditional breakpoints and
+//! tracepoints, e.g. `V3 == 5 && I >= 300` or `[2f0] != 0`.
+//!
+//! Numbers are hexadecimal like everywhere in the debugger, `[ADDR]` reads a
+//! byte of memory. Operators follow C precedence.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{register, Register};
+use crate::chip8::Chip8;
+use crate::instruction::Instruction;
+use crate::screen::Screen;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::BitOr => 3,
+            BinOp::BitXor => 4,
+            BinOp::BitAnd => 5,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 7,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    Reg(Register),
+    /// Byte of memory at the address
+    Mem(Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(i64),
+    Reg(Register),
+    Op(&'static str),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+}
+
+// longer operators go first, so `<=` isn't read as `<`
+const OPERATORS: [&str; 21] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "/",
+    "%", "!", "~", "=",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c.is_ascii_alphanumeric() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let token = match word.parse::<Register>() {
+                Ok(reg) => Token::Reg(reg),
+                Err(_) => {
+                    let digits = word.trim_start_matches("0x").trim_start_matches("0X");
+                    let num = i64::from_str_radix(digits, 16)
+                        .map_err(|_| format!("Unknown name '{}'", word))?;
+                    Token::Num(num)
+                }
+            };
+            tokens.push(token);
+            rest = &rest[len..];
+        } else {
+            let (token, len) = match c {
+                '[' => (Token::LBracket, 1),
+                ']' => (Token::RBracket, 1),
+                '(' => (Token::LParen, 1),
+                ')' => (Token::RParen, 1),
+                _ => match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    // single `=` is a common typo, treat it as comparison
+                    Some(&"=") => (Token::Op("=="), 1),
+                    Some(op) => (Token::Op(op), op.len()),
+                    None => return Err(format!("Unexpected '{}'", c)),
+                },
+            };
+            tokens.push(token);
+            rest = &rest[len..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            _ => Err(format!("Expected {:?}", expected)),
+        }
+    }
+
+    fn binary_op(&self) -> Option<BinOp> {
+        let op = match self.peek()? {
+            Token::Op(op) => *op,
+            _ => return None,
+        };
+        let op = match op {
+            "||" => BinOp::Or,
+            "&&" => BinOp::And,
+            "|" => BinOp::BitOr,
+            "^" => BinOp::BitXor,
+            "&" => BinOp::BitAnd,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            "<<" => BinOp::Shl,
+            ">>" => BinOp::Shr,
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "%" => BinOp::Rem,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Precedence climbing, parses operators with precedence >= `min`
+    fn expr(&mut self, min: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.binary_op() {
+            if op.precedence() < min {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Reg(reg)) => Ok(Expr::Reg(reg)),
+            Some(Token::Op("!")) => Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?))),
+            Some(Token::Op("-")) => Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?))),
+            Some(Token::Op("~")) => Ok(Expr::Unary(UnOp::BitNot, Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let expr = self.expr(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::LBracket) => {
+                let expr = self.expr(0)?;
+                self.expect(Token::RBracket)?;
+                Ok(Expr::Mem(Box::new(expr)))
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Expr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+}
+
+impl Expr {
+    pub fn eval<T: Screen>(&self, chip8: &Chip8<T>) -> Result<i64, String> {
+        let val = match self {
+            Expr::Num(n) => *n,
+            Expr::Reg(reg) => register(chip8, *reg) as i64,
+            Expr::Mem(addr) => {
+                let addr = addr.eval(chip8)?;
+                match chip8.memory.get(addr as usize) {
+                    Some(&b) if addr >= 0 => b as i64,
+                    _ => return Err(format!("Address {:x} is out of memory", addr)),
+                }
+            }
+            Expr::Unary(op, e) => {
+                let v = e.eval(chip8)?;
+                match op {
+                    UnOp::Not => (v == 0) as i64,
+                    UnOp::Neg => v.wrapping_neg(),
+                    UnOp::BitNot => !v,
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let l = lhs.eval(chip8)?;
+                // don't evaluate rhs if result is already known
+                match op {
+                    BinOp::Or if l != 0 => return Ok(1),
+                    BinOp::And if l == 0 => return Ok(0),
+                    _ => {}
+                }
+                let r = rhs.eval(chip8)?;
+                match op {
+                    BinOp::Or | BinOp::And => (r != 0) as i64,
+                    BinOp::BitOr => l | r,
+                    BinOp::BitXor => l ^ r,
+                    BinOp::BitAnd => l & r,
+                    BinOp::Eq => (l == r) as i64,
+                    BinOp::Ne => (l != r) as i64,
+                    BinOp::Lt => (l < r) as i64,
+                    BinOp::Le => (l <= r) as i64,
+                    BinOp::Gt => (l > r) as i64,
+                    BinOp::Ge => (l >= r) as i64,
+                    BinOp::Shl => l.wrapping_shl(r as u32),
+                    BinOp::Shr => l.wrapping_shr(r as u32),
+                    BinOp::Add => l.wrapping_add(r),
+                    BinOp::Sub => l.wrapping_sub(r),
+                    BinOp::Mul => l.wrapping_mul(r),
+                    BinOp::Div | BinOp::Rem if r == 0 => return Err("Division by zero".to_string()),
+                    BinOp::Div => l.wrapping_div(r),
+                    BinOp::Rem => l.wrapping_rem(r),
+                }
+            }
+        };
+        Ok(val)
+    }
+}
+
+/// Breakpoint condition: `EXPR [after MNEMONIC]`
+///
+/// With `after` the expression is checked only right after an instruction
+/// with the given mnemonic was executed, e.g. `VF == 1 after draw`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+    after: Option<String>,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Condition, String> {
+        let (expr, after) = match s.rfind(" after ") {
+            Some(idx) => (&s[..idx], Some(s[idx + 7..].trim().to_string())),
+            None => (s, None),
+        };
+        Ok(Condition {
+            source: s.trim().to_string(),
+            expr: expr.parse()?,
+            after,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Condition {
+    /// Checks condition once `executed` instruction has finished
+    pub fn check<T: Screen>(
+        &self,
+        chip8: &Chip8<T>,
+        executed: Instruction,
+    ) -> Result<bool, String> {
+        if let Some(after) = &self.after {
+            let text = executed.to_string();
+            let mnemonic = text.split_whitespace().next().unwrap_or("");
+            if mnemonic != after {
+                return Ok(false);
+            }
+        }
+        Ok(self.expr.eval(chip8)? != 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Expr(Expr, bool),
+}
+
+/// Tracepoint message: text with `{EXPR}` placeholders, `{EXPR:d}` prints
+/// the value as decimal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "Unclosed '{' in format".to_string())?
+                + start;
+            let inner = &rest[start + 1..end];
+            let (expr, decimal) = match inner.strip_suffix(":d") {
+                Some(expr) => (expr, true),
+                None => (inner.strip_suffix(":x").unwrap_or(inner), false),
+            };
+            segments.push(Segment::Expr(expr.parse()?, decimal));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Format {
+            source: s.to_string(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Format {
+    pub fn render<T: Screen>(&self, chip8: &Chip8<T>) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Expr(expr, decimal) => match expr.eval(chip8) {
+                    Ok(v) if *decimal => out.push_str(&v.to_string()),
+                    Ok(v) => out.push_str(&format!("{:x}", v)),
+                    Err(e) => out.push_str(&format!("<{}>", e)),
+                },
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+
+    fn eval(s: &str, chip8: &Chip8<NoScreen>) -> Result<i64, String> {
+        s.parse::<Expr>()?.eval(chip8)
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.v[3] = 5;
+        chip8.i = 0x300;
+        chip8.memory[0x2f0] = 0x7;
+
+        assert_eq!(eval("V3 == 5 && I >= 0x300", &chip8), Ok(1));
+        assert_eq!(eval("v3 == 5 && I > 300", &chip8), Ok(0));
+        assert_eq!(eval("[2F0] != 0", &chip8), Ok(1));
+        assert_eq!(eval("[I - 10] + 1", &chip8), Ok(8));
+        assert_eq!(eval("1 + 2 * 3", &chip8), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3", &chip8), Ok(9));
+        assert_eq!(eval("!V3 || -1 < 0", &chip8), Ok(1));
+        assert_eq!(eval("PC << 4 | ~0 & f", &chip8), Ok(0x200f));
+        assert!(eval("V3 / 0", &chip8).is_err());
+        assert!(eval("[1000]", &chip8).is_err());
+        assert!("V3 ==".parse::<Expr>().is_err());
+        assert!("(V3".parse::<Expr>().is_err());
+        assert!("V3 $ 1".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn test_condition_after() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.v[0xf] = 1;
+        let cond: Condition = "VF == 1 after draw".parse().unwrap();
+        assert_eq!(cond.check(&chip8, Instruction::Draw(0, 1, 5)), Ok(true));
+        assert_eq!(cond.check(&chip8, Instruction::AddReg(0, 1)), Ok(false));
+    }
+
+    #[test]
+    fn test_format() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.v[0] = 0x1f;
+        let format: Format = "V0={V0} dec={V0:d} [I]={[I]}".parse().unwrap();
+        assert_eq!(format.render(&chip8), "V0=1f dec=31 [I]=f0");
+        assert!("{V0".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! Reverse execution
+//!
+//! Emulation is deterministic, so instead of recording every instruction the
+//! debugger keeps periodic snapshots and gets to any earlier point by
+//! restoring the closest snapshot and executing forward from it. Once there
+//! are too many snapshots every second one is dropped and the interval is
+//! doubled, so memory stays bounded however long the session is. Input is
+//! the only outside influence, the snapshots contain the pressed keys and
+//! every change of them is recorded with its cycle to be replayed.
+
+use super::{Debugger, StopReason};
+use crate::chip8::{Chip8, ExecError, Snapshot};
+use crate::screen::Screen;
+
+const MAX_SNAPSHOTS: usize = 128;
+const INITIAL_INTERVAL: u64 = 64;
+
+pub struct History {
+    snapshots: Vec<Snapshot>,
+    /// Amount of instructions between two snapshots
+    interval: u64,
+    /// Cycles the pressed keys changed at and the new keys, in order
+    key_changes: Vec<(u64, [bool; 16])>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            snapshots: Vec::new(),
+            interval: INITIAL_INTERVAL,
+            key_changes: Vec::new(),
+        }
+    }
+
+    /// Takes snapshot if it's time for one, should be called before every
+    /// executed instruction
+    pub fn record<T: Screen>(&mut self, chip8: &Chip8<T>) {
+        let cycles = chip8.cycles;
+        if self.keys_at(cycles).is_some_and(|keys| keys != chip8.keys) {
+            // the recorded history from here on saw other input
+            self.snapshots.retain(|s| s.cycles() < cycles);
+            self.key_changes.retain(|&(c, _)| c < cycles);
+            self.key_changes.push((cycles, chip8.keys));
+        }
+        match self.snapshots.last() {
+            // history is already known up to here
+            Some(last) if last.cycles() >= cycles => return,
+            Some(_) if !cycles.is_multiple_of(self.interval) => return,
+            _ => {}
+        }
+        self.snapshots.push(chip8.snapshot());
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.interval *= 2;
+            let interval = self.interval;
+            let first = self.snapshots[0].cycles();
+            self.snapshots
+                .retain(|s| s.cycles() == first || s.cycles().is_multiple_of(interval));
+        }
+    }
+
+    /// Forgets everything, required once machine state is changed by hand
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.interval = INITIAL_INTERVAL;
+        self.key_changes.clear();
+    }
+
+    /// Keys pressed before the instruction at `cycles`, None before the
+    /// history starts
+    fn keys_at(&self, cycles: u64) -> Option<[bool; 16]> {
+        let first = self.snapshots.first().filter(|s| s.cycles() <= cycles)?;
+        let change = self.key_changes.iter().rev().find(|&&(c, _)| c <= cycles);
+        Some(change.map_or(first.keys(), |&(_, keys)| keys))
+    }
+
+    /// Executes the next instruction with the keys pressed when it was
+    /// recorded
+    fn replay_op<T: Screen>(&self, chip8: &mut Chip8<T>) -> Result<(), ExecError> {
+        let cycles = chip8.cycles;
+        if let Ok(idx) = self.key_changes.binary_search_by_key(&cycles, |&(c, _)| c) {
+            chip8.set_keys(self.key_changes[idx].1);
+        }
+        chip8.emulate_op()
+    }
+
+    /// Executes instructions until the machine reaches `cycle`
+    fn replay<T: Screen>(&self, chip8: &mut Chip8<T>, cycle: u64) {
+        while chip8.cycles < cycle {
+            // replayed instructions succeeded before
+            if self.replay_op(chip8).is_err() {
+                break;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+/// Runs `f` with tracing and profiling suspended, replayed instructions
+/// were already recorded when they were executed for the first time
+fn replaying<T, R, F>(chip8: &mut Chip8<T>, f: F) -> R
+where
+    F: FnOnce(&mut Chip8<T>) -> R,
+{
+    let tracer = chip8.tracer.take();
+    let profiler = chip8.profiler.take();
+    let result = f(chip8);
+    chip8.tracer = tracer;
+    chip8.profiler = profiler;
+    result
+}
+
+impl Debugger {
+    /// Undoes last `count` instructions
+    pub fn step_back<T: Screen>(&mut self, chip8: &mut Chip8<T>, count: u64) -> StopReason {
+        replaying(chip8, |chip8| self.replay_back(chip8, count))
+    }
+
+    /// Runs backwards until the previous breakpoint or watchpoint hit
+    pub fn reverse_continue<T: Screen>(&mut self, chip8: &mut Chip8<T>) -> StopReason {
+        replaying(chip8, |chip8| self.find_previous_stop(chip8))
+    }
+
+    fn replay_back<T: Screen>(&self, chip8: &mut Chip8<T>, count: u64) -> StopReason {
+        let snapshots = &self.history.snapshots;
+        let found = chip8
+            .cycles
+            .checked_sub(count)
+            .and_then(|target| Some((target, snapshots.iter().rfind(|s| s.cycles() <= target)?)));
+        match (found, snapshots.first()) {
+            (Some((target, snapshot)), _) => {
+                chip8.restore(snapshot);
+                self.history.replay(chip8, target);
+                StopReason::Done
+            }
+            (None, Some(first)) => {
+                chip8.restore(first);
+                StopReason::HistoryStart
+            }
+            (None, None) => StopReason::HistoryStart,
+        }
+    }
+
+    fn find_previous_stop<T: Screen>(&self, chip8: &mut Chip8<T>) -> StopReason {
+        let now = chip8.cycles;
+        let mut end = now;
+        // search segments between snapshots starting from the latest one
+        for idx in (0..self.history.snapshots.len()).rev() {
+            let snapshot = &self.history.snapshots[idx];
+            if snapshot.cycles() >= end {
+                continue;
+            }
+            chip8.restore(snapshot);
+            let mut last_hit = None;
+            while chip8.cycles < end {
+                let instruction = match chip8.fetch() {
+                    Ok(ins) => ins,
+                    Err(_) => break,
+                };
+                if self.history.replay_op(chip8).is_err() {
+                    break;
+                }
+                if chip8.cycles < now {
+                    if let Some(reason) = self.check_stop(chip8, instruction) {
+                        last_hit = Some((chip8.cycles, reason));
+                    }
+                }
+            }
+            if let Some((cycle, reason)) = last_hit {
+                chip8.restore(&self.history.snapshots[idx]);
+                self.history.replay(chip8, cycle);
+                return reason;
+            }
+            end = self.history.snapshots[idx].cycles();
+        }
+        if let Some(first) = self.history.snapshots.first() {
+            chip8.restore(first);
+        }
+        StopReason::HistoryStart
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+
+    fn counter() -> Chip8<NoScreen> {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[
+            0x70, 0x01, // 200: add V0, 01
+            0xc1, 0xff, // 202: rnd V1, ff
+            0x12, 0x00, // 204: jmp 200
+        ]);
+        chip8
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut chip8 = counter();
+        let mut db = Debugger::new();
+        db.run_until(&mut chip8, |c, _| c.cycles == 1000);
+        let before = (chip8.v, chip8.pc, chip8.dt);
+        db.run_until(&mut chip8, |_, _| true);
+        assert_eq!(db.step_back(&mut chip8, 1), StopReason::Done);
+        assert_eq!((chip8.v, chip8.pc, chip8.dt), before);
+        assert_eq!(chip8.cycles, 1000);
+
+        assert_eq!(db.step_back(&mut chip8, 2000), StopReason::HistoryStart);
+        assert_eq!((chip8.cycles, chip8.v[0]), (0, 0));
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        let mut chip8 = counter();
+        let mut db = Debugger::new();
+        db.run_until(&mut chip8, |c, _| c.cycles == 2000);
+        let v0 = chip8.v[0];
+        db.execute(&mut chip8, "b 202 if V0 % 10 == 0".parse().unwrap())
+            .unwrap();
+
+        let reason = db.reverse_continue(&mut chip8);
+        assert_eq!(reason, StopReason::Breakpoint(0x202));
+        assert_eq!(chip8.v[0], v0 / 0x10 * 0x10);
+        assert_eq!(chip8.pc, 0x202);
+
+        // doesn't stop at the current position again
+        db.reverse_continue(&mut chip8);
+        assert_eq!(chip8.v[0], v0 / 0x10 * 0x10 - 0x10);
+    }
+
+    #[test]
+    fn test_replayed_input() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[
+            0xe0, 0x9e, // 200: skp V0 key
+            0x71, 0x01, // 202: add V1, 01
+            0x12, 0x00, // 204: jmp 200
+        ]);
+        let mut db = Debugger::new();
+        db.run_until(&mut chip8, |c, _| c.cycles == 300);
+        let mut keys = [false; 16];
+        keys[0] = true;
+        chip8.set_keys(keys);
+        db.run_until(&mut chip8, |c, _| c.cycles == 310);
+        let v1 = chip8.v[1];
+        db.run_until(&mut chip8, |c, _| c.cycles == 600);
+
+        // replayed from the snapshot before the key was pressed
+        assert_eq!(db.step_back(&mut chip8, 290), StopReason::Done);
+        assert_eq!(chip8.v[1], v1);
+        assert!(chip8.key_pressed(0));
+        db.step_back(&mut chip8, 100);
+        assert!(!chip8.key_pressed(0));
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut chip8 = counter();
+        let mut db = Debugger::new();
+        db.run_until(&mut chip8, |c, _| c.cycles == 100_000);
+        assert!(db.history.len() <= MAX_SNAPSHOTS);
+        assert_eq!(db.step_back(&mut chip8, 99_999), StopReason::Done);
+        assert_eq!(chip8.cycles, 1);
+    }
+}
@@ -0,0 +1,718 @@
+//! Decompiler
+//!
+//! Lifts the control-flow graph to C-like pseudo-code. Loops are the natural
+//! loops of the back edges, a branch continues where its two ways meet, and
+//! gotos are left only where the code doesn't fit into if/else and while. A
+//! skip followed by a jump is the usual way to branch on CHIP-8, so the pair
+//! becomes a single condition.
+//!
+//! Registers are named after their most common use, e.g. the coordinates of
+//! draws, and draws at a known I show the sprite.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::cfg::{Cfg, Exit};
+use crate::chip8::MEMORY_START;
+use crate::instruction::{Extension, Instruction, Reg};
+
+const INDENT: &str = "    ";
+
+/// Names given to registers by their use, in the order of preference
+const ROLES: [&str; 8] = ["x", "y", "key", "timer", "sound", "score", "digit", "rnd"];
+
+/// Roles the instruction gives to its registers
+fn roles(instruction: Instruction) -> Vec<(Reg, usize)> {
+    use Instruction::*;
+
+    match instruction {
+        Draw(x, y, _) => vec![(x, 0), (y, 1)],
+        WaitKey(x) | SkipKey(x) | SkipNotKey(x) => vec![(x, 2)],
+        GetDelay(x) | SetDelay(x) => vec![(x, 3)],
+        SetSound(x) => vec![(x, 4)],
+        Bcd(x) => vec![(x, 5)],
+        Font(x) => vec![(x, 6)],
+        Rand(x, _) => vec![(x, 7)],
+        _ => vec![],
+    }
+}
+
+/// Names of the registers
+pub struct Names(Vec<String>);
+
+impl Names {
+    /// Registers get the role they have most often, registers sharing a role
+    /// are numbered. VF is the flag register everywhere
+    pub fn infer(cfg: &Cfg) -> Names {
+        let mut votes = [[0; ROLES.len()]; 16];
+        for block in cfg.blocks() {
+            for (_, word) in cfg.code(block) {
+                if let Ok(instruction) = Instruction::decode(word) {
+                    for (reg, role) in roles(instruction) {
+                        votes[reg][role] += 1;
+                    }
+                }
+            }
+        }
+        let mut best = [None; 16];
+        for (reg, votes) in votes.iter().enumerate().take(0xf) {
+            let max = votes.iter().copied().max().unwrap_or(0);
+            if max > 0 {
+                best[reg] = votes.iter().position(|&count| count == max);
+            }
+        }
+        let names = (0..16)
+            .map(|reg| match best[reg] {
+                _ if reg == 0xf => "vf".to_string(),
+                None => format!("v{:x}", reg),
+                Some(role) => {
+                    let shared: Vec<usize> = (0..16).filter(|&r| best[r] == Some(role)).collect();
+                    if shared.len() == 1 {
+                        ROLES[role].to_string()
+                    } else {
+                        let idx = shared.iter().position(|&r| r == reg).unwrap();
+                        format!("{}{}", ROLES[role], idx + 1)
+                    }
+                }
+            })
+            .collect();
+        Names(names)
+    }
+
+    pub fn name(&self, reg: Reg) -> &str {
+        &self.0[reg]
+    }
+}
+
+/// I while going through a block
+#[derive(Default)]
+struct Sprite {
+    /// Address loaded to I, if it's known
+    addr: Option<usize>,
+    /// Sprite at the address was already shown
+    shown: bool,
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == MEMORY_START {
+        "main".to_string()
+    } else {
+        format!("sub_{:03x}", entry)
+    }
+}
+
+/// Where a walk over the blocks stops and what jumps to the enclosing
+/// statements look like
+#[derive(Clone, Default)]
+struct Context {
+    /// Block the enclosing statement continues at
+    stop: Option<usize>,
+    /// Stops of the statements around the enclosing one, reached by goto
+    outer: Vec<usize>,
+    /// Header of the innermost loop, reached by continue
+    header: Option<usize>,
+    /// Exit of the innermost loop, reached by break
+    exit: Option<usize>,
+}
+
+impl Context {
+    fn nested(&self, stop: Option<usize>) -> Context {
+        let mut outer = self.outer.clone();
+        outer.extend(self.stop);
+        Context {
+            stop,
+            outer,
+            ..self.clone()
+        }
+    }
+
+    fn is_end(&self, n: usize) -> bool {
+        [self.stop, self.header, self.exit].contains(&Some(n)) || self.outer.contains(&n)
+    }
+}
+
+/// Decompiler of a single function
+struct Function<'a> {
+    cfg: &'a Cfg,
+    names: &'a Names,
+    /// Blocks reachable from the entry
+    nodes: BTreeSet<usize>,
+    /// Bodies of the loops by their headers
+    loops: BTreeMap<usize, BTreeSet<usize>>,
+    /// Blocks which need a label
+    labels: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+    emitted: BTreeSet<usize>,
+    lines: Vec<String>,
+    depth: usize,
+}
+
+impl<'a> Function<'a> {
+    fn new(cfg: &'a Cfg, names: &'a Names, entry: usize) -> Function<'a> {
+        let mut function = Function {
+            cfg,
+            names,
+            nodes: BTreeSet::new(),
+            loops: BTreeMap::new(),
+            labels: BTreeSet::new(),
+            gotos: BTreeSet::new(),
+            emitted: BTreeSet::new(),
+            lines: Vec::new(),
+            depth: 1,
+        };
+        let mut pending = vec![entry];
+        while let Some(n) = pending.pop() {
+            if function.nodes.insert(n) {
+                pending.extend(function.successors(n));
+            }
+        }
+        function.find_loops(entry);
+        function
+    }
+
+    fn successors(&self, n: usize) -> Vec<usize> {
+        self.cfg
+            .block(n)
+            .map_or(vec![], |block| block.exit.local_successors())
+    }
+
+    /// Block made of a jump to itself
+    fn is_halt(&self, n: usize) -> bool {
+        self.cfg
+            .block(n)
+            .is_some_and(|block| block.exit == Exit::Jump(n) && block.end == n + 2)
+    }
+
+    /// Block the control really continues at when it goes to `n`, the
+    /// target of a block made of a single jump
+    fn target(&self, n: usize) -> usize {
+        match self.cfg.block(n) {
+            Some(block) if block.end == n + 2 => match block.exit {
+                Exit::Jump(target) => target,
+                _ => n,
+            },
+            _ => n,
+        }
+    }
+
+    /// Natural loops of the back edges found by a depth-first search
+    fn find_loops(&mut self, entry: usize) {
+        let mut predecessors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &n in &self.nodes {
+            for s in self.successors(n) {
+                predecessors.entry(s).or_default().push(n);
+            }
+        }
+        let mut back_edges = Vec::new();
+        let mut on_stack = BTreeSet::new();
+        let mut done = BTreeSet::new();
+        let mut stack = vec![(entry, self.successors(entry), 0)];
+        on_stack.insert(entry);
+        while let Some((n, successors, idx)) = stack.last_mut() {
+            match successors.get(*idx).copied() {
+                Some(s) => {
+                    *idx += 1;
+                    if on_stack.contains(&s) {
+                        back_edges.push((*n, s));
+                    } else if !done.contains(&s) {
+                        on_stack.insert(s);
+                        let successors = self.successors(s);
+                        stack.push((s, successors, 0));
+                    }
+                }
+                None => {
+                    on_stack.remove(n);
+                    done.insert(*n);
+                    stack.pop();
+                }
+            }
+        }
+        for (latch, header) in back_edges {
+            if latch == header && self.is_halt(header) {
+                continue;
+            }
+            // entries into the middle of the loop aren't a part of it
+            let mut reachable = BTreeSet::new();
+            let mut pending = vec![header];
+            while let Some(n) = pending.pop() {
+                if reachable.insert(n) {
+                    pending.extend(self.successors(n));
+                }
+            }
+            let body = self.loops.entry(header).or_default();
+            body.insert(header);
+            let mut pending = vec![latch];
+            while let Some(n) = pending.pop() {
+                if reachable.contains(&n) && body.insert(n) {
+                    pending.extend(predecessors.get(&n).into_iter().flatten());
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.lines
+            .push(format!("{}{}", INDENT.repeat(self.depth), line.as_ref()));
+    }
+
+    fn label(&mut self, n: usize) {
+        if self.labels.contains(&n) {
+            self.lines
+                .push(format!("{}label_{:03x}:", INDENT.repeat(self.depth - 1), n));
+        }
+    }
+
+    fn goto(&mut self, n: usize) {
+        self.gotos.insert(n);
+        self.line(format!("goto label_{:03x};", n));
+    }
+
+    /// Jumps to the end of a statement
+    fn jump(&mut self, n: usize, ctx: &Context) {
+        if ctx.header == Some(n) {
+            self.line("continue;");
+        } else if ctx.exit == Some(n) {
+            self.line("break;");
+        } else {
+            self.goto(n);
+        }
+    }
+
+    /// Condition of skipping and its negation
+    fn condition(&self, word: u16) -> (String, String) {
+        use Instruction::*;
+
+        let n = |reg| self.names.name(reg);
+        let compare = |a: &str, b: String, equal: bool| {
+            let (eq, ne) = (format!("{} == {}", a, b), format!("{} != {}", a, b));
+            if equal {
+                (eq, ne)
+            } else {
+                (ne, eq)
+            }
+        };
+        match Instruction::decode(word) {
+            Ok(SkipEqImm(x, nn)) => compare(n(x), nn.to_string(), true),
+            Ok(SkipNeImm(x, nn)) => compare(n(x), nn.to_string(), false),
+            Ok(SkipEqReg(x, y)) => compare(n(x), n(y).to_string(), true),
+            Ok(SkipNeReg(x, y)) => compare(n(x), n(y).to_string(), false),
+            Ok(SkipKey(x)) => (
+                format!("key_pressed({})", n(x)),
+                format!("!key_pressed({})", n(x)),
+            ),
+            Ok(SkipNotKey(x)) => (
+                format!("!key_pressed({})", n(x)),
+                format!("key_pressed({})", n(x)),
+            ),
+            _ => unreachable!("{:04x} isn't a skip", word),
+        }
+    }
+
+    /// Rows of the sprite at `addr` as comments, none outside of the rom
+    fn sprite(&self, addr: usize, rows: u8) -> Vec<String> {
+        let rom = self.cfg.rom();
+        let range = addr
+            .checked_sub(MEMORY_START)
+            .and_then(|start| Some(start..start.checked_add(rows as usize)?));
+        let bytes = match range.and_then(|range| rom.get(range)) {
+            Some(bytes) => bytes,
+            None => return vec![],
+        };
+        let mut lines = vec![format!("// sprite at 0x{:03x}:", addr)];
+        for &row in bytes {
+            let art: String = (0..8)
+                .rev()
+                .map(|bit| if row >> bit & 1 == 1 { '#' } else { '.' })
+                .collect();
+            lines.push(format!("//   {}", art));
+        }
+        lines
+    }
+
+    fn statement(&self, word: u16, sprite: &mut Sprite) -> Vec<String> {
+        use Instruction::*;
+
+        let n = |reg| self.names.name(reg);
+        let instruction = match (Instruction::decode(word), Extension::decode(word)) {
+            (Ok(instruction), None) => instruction,
+            (_, Some(extension)) => {
+                return vec![format!("/* {:04x}: {} */", word, extension.description)]
+            }
+            (Err(_), None) => return vec![format!("invalid(0x{:04x});", word)],
+        };
+        let line = match instruction {
+            Sys(nnn) => format!("sys(0x{:03x});", nnn),
+            ClearScreen => "clear_screen();".to_string(),
+            MovImm(x, nn) => format!("{} = {};", n(x), nn),
+            AddImm(x, nn) => format!("{} += {};", n(x), nn),
+            MovReg(x, y) => format!("{} = {};", n(x), n(y)),
+            Or(x, y) => format!("{} |= {};", n(x), n(y)),
+            And(x, y) => format!("{} &= {};", n(x), n(y)),
+            Xor(x, y) => format!("{} ^= {};", n(x), n(y)),
+            AddReg(x, y) => format!("{} += {}; // vf = carry", n(x), n(y)),
+            SubReg(x, y) => format!("{} -= {}; // vf = !borrow", n(x), n(y)),
+            SubReverse(x, y) => format!("{} = {} - {}; // vf = !borrow", n(x), n(y), n(x)),
+            ShiftRight(x, y) if x == y => format!("{} >>= 1; // vf = shifted out bit", n(x)),
+            ShiftRight(x, y) => format!("{} = {} >> 1; // vf = shifted out bit", n(x), n(y)),
+            ShiftLeft(x, y) if x == y => format!("{} <<= 1; // vf = shifted out bit", n(x)),
+            ShiftLeft(x, y) => format!("{} = {} << 1; // vf = shifted out bit", n(x), n(y)),
+            MovI(nnn) => {
+                *sprite = Sprite {
+                    addr: Some(nnn as usize),
+                    shown: false,
+                };
+                format!("i = 0x{:03x};", nnn)
+            }
+            Rand(x, nn) => format!("{} = rand() & 0x{:02x};", n(x), nn),
+            Draw(x, y, rows) => {
+                let mut lines = match sprite.addr {
+                    Some(addr) if !sprite.shown => self.sprite(addr, rows),
+                    _ => vec![],
+                };
+                sprite.shown = true;
+                lines.push(format!("vf = draw({}, {}, {});", n(x), n(y), rows));
+                return lines;
+            }
+            GetDelay(x) => format!("{} = delay_timer;", n(x)),
+            WaitKey(x) => format!("{} = wait_key();", n(x)),
+            SetDelay(x) => format!("delay_timer = {};", n(x)),
+            SetSound(x) => format!("sound_timer = {};", n(x)),
+            AddI(x) => format!("i += {};", n(x)),
+            Font(x) => format!("i = font({});", n(x)),
+            Bcd(x) => format!("bcd(i, {});", n(x)),
+            Store(x) => format!("store(i, v0..v{:x});", x),
+            Load(x) => format!("load(i, v0..v{:x});", x),
+            _ => unreachable!("{} ends a block", instruction),
+        };
+        if matches!(instruction, AddI(_) | Font(_) | Store(_) | Load(_)) {
+            *sprite = Sprite::default();
+        }
+        vec![line]
+    }
+
+    /// Emits blocks starting with `n` until the end of the enclosing
+    /// statement
+    fn walk(&mut self, mut n: usize, ctx: &Context) {
+        loop {
+            if ctx.stop == Some(n) {
+                return;
+            }
+            if ctx.header == Some(n) {
+                self.line("continue;");
+                return;
+            }
+            if ctx.exit == Some(n) {
+                self.line("break;");
+                return;
+            }
+            if ctx.is_end(n) || self.emitted.contains(&n) || !self.nodes.contains(&n) {
+                self.goto(n);
+                return;
+            }
+            let next = if self.loops.contains_key(&n) {
+                self.emit_loop(n, ctx)
+            } else {
+                self.block(n, ctx)
+            };
+            match next {
+                Some(next) => n = next,
+                None => return,
+            }
+        }
+    }
+
+    /// Emits the block, returns the block the control continues at
+    fn block(&mut self, n: usize, ctx: &Context) -> Option<usize> {
+        self.emitted.insert(n);
+        if !self.loops.contains_key(&n) {
+            self.label(n);
+        }
+        let block = self.cfg.block(n).unwrap().clone();
+        let code = self.cfg.code(&block);
+        let body = match block.exit {
+            Exit::Next(_) | Exit::Invalid(_) => &code[..],
+            _ => &code[..code.len() - 1],
+        };
+        let mut sprite = Sprite::default();
+        for &(_, word) in body {
+            for line in self.statement(word, &mut sprite) {
+                self.line(line);
+            }
+        }
+        match block.exit {
+            Exit::Jump(target) if target == n && body.is_empty() => {
+                self.line("halt();");
+                None
+            }
+            Exit::Next(target) | Exit::Jump(target) => Some(target),
+            Exit::Skip { next, skip } => self.branch(code[code.len() - 1].1, next, skip, ctx),
+            Exit::Call { target, ret } => {
+                self.line(format!("{}();", function_name(target)));
+                Some(ret)
+            }
+            Exit::Return => {
+                self.line("return;");
+                None
+            }
+            Exit::Indirect(nnn) => {
+                let v0 = self.names.name(0);
+                self.line(format!("jump(0x{:03x} + {}); // unresolved", nnn, v0));
+                None
+            }
+            Exit::Halt => {
+                self.line("exit();");
+                None
+            }
+            Exit::Invalid(addr) => {
+                match self.cfg.word(addr) {
+                    Some(word) => self.line(format!("invalid(0x{:04x});", word)),
+                    None => self.line("invalid();"),
+                }
+                None
+            }
+        }
+    }
+
+    /// Blocks reachable from `n` before the end of the statement
+    fn reach(&self, n: usize, ctx: &Context) -> BTreeSet<usize> {
+        let mut reached = BTreeSet::new();
+        let mut pending = vec![n];
+        while let Some(n) = pending.pop() {
+            if !reached.insert(n) || ctx.is_end(n) || self.emitted.contains(&n) {
+                continue;
+            }
+            pending.extend(self.successors(n));
+        }
+        reached
+    }
+
+    /// First block reached from both `a` and `b`
+    fn merge(&self, a: usize, b: usize, ctx: &Context) -> Option<usize> {
+        let common: BTreeSet<usize> = self
+            .reach(a, ctx)
+            .intersection(&self.reach(b, ctx))
+            .copied()
+            .collect();
+        common
+            .iter()
+            .copied()
+            .find(|&m| self.reach(m, ctx).is_superset(&common))
+            .or_else(|| common.first().copied())
+    }
+
+    /// Emits the if statement of a skip, returns the block after it
+    fn branch(&mut self, word: u16, next: usize, skip: usize, ctx: &Context) -> Option<usize> {
+        let (skipped, not_skipped) = self.condition(word);
+        let (skip, other) = (self.target(skip), self.target(next));
+        if other == skip {
+            return Some(skip);
+        }
+        // leaving the statement early on one way continues with the other
+        let early = |n| ctx.is_end(n) && ctx.stop != Some(n);
+        for &(condition, target, rest) in &[(&skipped, skip, other), (&not_skipped, other, skip)] {
+            if early(target) && !early(rest) {
+                self.line(format!("if ({}) {{", condition));
+                self.depth += 1;
+                self.jump(target, ctx);
+                self.depth -= 1;
+                self.line("}");
+                return Some(rest);
+            }
+        }
+        if early(skip) && early(other) {
+            self.line(format!("if ({}) {{", skipped));
+            self.depth += 1;
+            self.jump(skip, ctx);
+            self.depth -= 1;
+            self.line("}");
+            self.jump(other, ctx);
+            return None;
+        }
+        let merge = self.merge(skip, other, ctx);
+        let inner = ctx.nested(merge);
+        if merge == Some(skip) {
+            self.line(format!("if ({}) {{", not_skipped));
+            self.nested(other, &inner);
+        } else if merge == Some(other) {
+            self.line(format!("if ({}) {{", skipped));
+            self.nested(skip, &inner);
+        } else {
+            self.line(format!("if ({}) {{", skipped));
+            self.nested(skip, &inner);
+            self.line("} else {");
+            self.nested(other, &inner);
+        }
+        self.line("}");
+        merge
+    }
+
+    fn nested(&mut self, n: usize, ctx: &Context) {
+        self.depth += 1;
+        self.walk(n, ctx);
+        self.depth -= 1;
+    }
+
+    /// Emits the loop with the header `h`, returns its exit
+    fn emit_loop(&mut self, h: usize, ctx: &Context) -> Option<usize> {
+        let body = self.loops[&h].clone();
+        let exit = body
+            .iter()
+            .flat_map(|&n| self.successors(n))
+            .filter(|s| !body.contains(s))
+            .map(|s| self.target(s))
+            .min();
+        let mut outer = ctx.outer.clone();
+        outer.extend([ctx.stop, ctx.header, ctx.exit].iter().flatten());
+        let inner = Context {
+            stop: Some(h),
+            outer,
+            header: Some(h),
+            exit,
+        };
+        self.label(h);
+
+        // a header made of a single skip is the condition of the loop
+        let block = self.cfg.block(h).unwrap().clone();
+        if let Exit::Skip { next, skip } = block.exit {
+            if block.end == h + 2 {
+                let (skipped, not_skipped) = self.condition(self.cfg.word(h).unwrap());
+                let (skip, other) = (self.target(skip), self.target(next));
+                let condition = if Some(other) == exit && body.contains(&skip) {
+                    Some((skipped, skip))
+                } else if Some(skip) == exit && body.contains(&other) {
+                    Some((not_skipped, other))
+                } else {
+                    None
+                };
+                if let Some((condition, first)) = condition {
+                    self.emitted.insert(h);
+                    self.line(format!("while ({}) {{", condition));
+                    self.nested(first, &inner);
+                    self.line("}");
+                    return exit;
+                }
+            }
+        }
+
+        self.line("while (true) {");
+        self.depth += 1;
+        if let Some(next) = self.block(h, &inner) {
+            self.walk(next, &inner);
+        }
+        self.depth -= 1;
+        self.line("}");
+        exit
+    }
+
+    /// Body of the function, labels are known after the first pass
+    fn emit(mut self, entry: usize) -> Vec<String> {
+        self.walk(entry, &Context::default());
+        self.labels = std::mem::take(&mut self.gotos);
+        self.emitted.clear();
+        self.lines.clear();
+        self.walk(entry, &Context::default());
+        if self.lines.last().map(|line| line.trim()) == Some("return;") {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// Pseudo-code of the functions of the graph
+pub fn decompile(cfg: &Cfg) -> String {
+    let names = Names::infer(cfg);
+    let renamed: Vec<String> = (0..16)
+        .filter(|&reg| names.name(reg) != format!("v{:x}", reg))
+        .map(|reg| format!("v{:x} = {}", reg, names.name(reg)))
+        .collect();
+    let mut out = String::new();
+    if !renamed.is_empty() {
+        out.push_str(&format!("// registers: {}\n", renamed.join(", ")));
+    }
+    for entry in cfg.functions() {
+        out.push_str(&format!("\nvoid {}() {{\n", function_name(entry)));
+        for line in Function::new(cfg, &names, entry).emit(entry) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_structure() {
+        let cfg = Cfg::new(&[
+            0x60, 0x00, // 200: mov V0, 00
+            0xa2, 0x16, // 202: mov I, 216
+            0xd0, 0x12, // 204: draw V0, V1, 2
+            0x70, 0x08, // 206: add V0, 08
+            0x40, 0x40, // 208: skipifne V0, 40
+            0x60, 0x00, // 20a: mov V0, 00
+            0x30, 0x10, // 20c: skipifeq V0, 10
+            0x12, 0x02, // 20e: jmp 202
+            0x22, 0x14, // 210: call 214
+            0x12, 0x12, // 212: jmp 212
+            0x00, 0xee, // 214: ret
+            0x81, 0x18, // 216: sprite
+        ]);
+        assert_eq!(
+            decompile(&cfg),
+            "// registers: v0 = x, v1 = y
+
+void main() {
+    x = 0;
+    while (true) {
+        i = 0x216;
+        // sprite at 0x216:
+        //   #......#
+        //   ...##...
+        vf = draw(x, y, 2);
+        x += 8;
+        if (x == 64) {
+            x = 0;
+        }
+        if (x == 16) {
+            break;
+        }
+    }
+    sub_214();
+    halt();
+}
+
+void sub_214() {
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_goto() {
+        // enters the loop at 206 in the middle of its body
+        let cfg = Cfg::new(&[
+            0x30, 0x00, // 200: skipifeq V0, 00
+            0x12, 0x06, // 202: jmp 206
+            0x71, 0x01, // 204: add V1, 01
+            0x72, 0x01, // 206: add V2, 01
+            0x31, 0x05, // 208: skipifeq V1, 05
+            0x12, 0x04, // 20a: jmp 204
+            0x12, 0x0c, // 20c: jmp 20c
+        ]);
+        let text = decompile(&cfg);
+        assert!(text.contains("goto label_"), "{}", text);
+    }
+
+    #[test]
+    fn test_sprite_outside_rom() {
+        let cfg = Cfg::new(&[
+            0xa1, 0xf6, // 200: mov I, 1f6
+            0xd0, 0x1a, // 202: draw V0, V1, a
+            0x12, 0x04, // 204: jmp 204
+        ]);
+        let text = decompile(&cfg);
+        assert!(!text.contains("// sprite at"), "{}", text);
+    }
+}
@@ -0,0 +1,288 @@
+//! Bit-parallel framebuffer
+//!
+//! Every screen row is a single integer with the leftmost pixel in the most
+//! significant bit: u64 for the 64x32 lores screen and u128 for the 128x64
+//! hires one. A sprite row is drawn with one shift and XOR, it collides when
+//! it shares a set bit with the screen row before the XOR.
+
+use std::fmt::Debug;
+use std::io;
+use std::ops::{BitAnd, BitXor, Shr};
+
+/// Screen row of a resolution
+pub trait Row:
+    Copy
+    + Default
+    + Eq
+    + Debug
+    + BitAnd<Output = Self>
+    + BitXor<Output = Self>
+    + Shr<u32, Output = Self>
+{
+    /// Pixels per row
+    const WIDTH: u32;
+    /// Rows of the screen
+    const HEIGHT: u32;
+
+    /// Row with `bits` at the left edge
+    fn from_left(bits: u8) -> Self;
+    fn rotate_right(self, n: u32) -> Self;
+    fn is_zero(self) -> bool;
+    /// Index of the lowest set bit
+    fn trailing_zeros(self) -> u32;
+    /// Writes the pixels to `bytes`, leftmost first
+    fn write_to(self, bytes: &mut [u8]);
+    fn from_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! row {
+    ($type:ty, $height:expr) => {
+        impl Row for $type {
+            const WIDTH: u32 = <$type>::BITS;
+            const HEIGHT: u32 = $height;
+
+            fn from_left(bits: u8) -> $type {
+                (bits as $type) << (<$type>::BITS - 8)
+            }
+
+            fn rotate_right(self, n: u32) -> $type {
+                <$type>::rotate_right(self, n)
+            }
+
+            fn is_zero(self) -> bool {
+                self == 0
+            }
+
+            fn trailing_zeros(self) -> u32 {
+                <$type>::trailing_zeros(self)
+            }
+
+            fn write_to(self, bytes: &mut [u8]) {
+                bytes.copy_from_slice(&self.to_be_bytes());
+            }
+
+            fn from_bytes(bytes: &[u8]) -> $type {
+                let mut row = [0; <$type>::BITS as usize / 8];
+                row.copy_from_slice(bytes);
+                <$type>::from_be_bytes(row)
+            }
+        }
+    };
+}
+
+row!(u64, 32);
+row!(u128, 64);
+
+/// Pixels of the 64x32 screen
+pub type Lores = Framebuffer<u64>;
+/// Pixels of the 128x64 SUPER-CHIP screen
+pub type Hires = Framebuffer<u128>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer<R> {
+    rows: Vec<R>,
+}
+
+impl<R: Row> Default for Framebuffer<R> {
+    fn default() -> Framebuffer<R> {
+        Framebuffer {
+            rows: vec![R::default(); R::HEIGHT as usize],
+        }
+    }
+}
+
+impl<R: Row> Framebuffer<R> {
+    pub fn rows(&self) -> &[R] {
+        &self.rows
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.fill(R::default());
+    }
+
+    pub fn set_row(&mut self, y: u32, row: R) {
+        self.rows[y as usize] = row;
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        !(self.rows[y as usize] & (R::from_left(0x80) >> x)).is_zero()
+    }
+
+    /// Draws the sprite with its top left corner at (`x`, `y`), parts off
+    /// the screen wrap around to the other side or are clipped. Calls
+    /// `flipped` with the index of every changed row, its changed pixels and
+    /// the row after the draw. Returns whether a set pixel was cleared
+    pub fn draw(
+        &mut self,
+        x: u32,
+        y: u32,
+        sprite: &[u8],
+        wrap: bool,
+        mut flipped: impl FnMut(u32, R, R),
+    ) -> bool {
+        // the starting position always wraps
+        let (x, y) = (x % R::WIDTH, y % R::HEIGHT);
+        let mut collision = false;
+        for (cy, &bits) in (y..).zip(sprite) {
+            let cy = match cy {
+                cy if cy < R::HEIGHT => cy,
+                cy if wrap => cy % R::HEIGHT,
+                _ => break,
+            };
+            let bits = R::from_left(bits);
+            let bits = if wrap {
+                bits.rotate_right(x)
+            } else {
+                bits >> x
+            };
+            let row = &mut self.rows[cy as usize];
+            collision |= !(*row & bits).is_zero();
+            *row = *row ^ bits;
+            if !bits.is_zero() {
+                flipped(cy, bits, *row);
+            }
+        }
+        collision
+    }
+
+    /// Rows as bytes, leftmost pixels first
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; (R::WIDTH * R::HEIGHT / 8) as usize];
+        for (row, chunk) in self
+            .rows
+            .iter()
+            .zip(bytes.chunks_mut(R::WIDTH as usize / 8))
+        {
+            row.write_to(chunk);
+        }
+        bytes
+    }
+
+    /// One line of `#` for set and `.` for clear pixels per row
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+        for y in 0..R::HEIGHT {
+            text.extend((0..R::WIDTH).map(|x| if self.pixel(x, y) { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Binary PBM image, set pixels are black
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut image = format!("P4\n{} {}\n", R::WIDTH, R::HEIGHT).into_bytes();
+        image.extend(self.to_bytes());
+        image
+    }
+
+    /// Black and white PNG image, set pixels are white
+    pub fn to_png(&self) -> io::Result<Vec<u8>> {
+        let mut image = Vec::new();
+        let mut encoder = png::Encoder::new(&mut image, R::WIDTH, R::HEIGHT);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_bytes())?;
+        writer.finish()?;
+        Ok(image)
+    }
+
+    /// Framebuffer of an image written by `to_png`
+    pub fn from_png(image: &[u8]) -> io::Result<Framebuffer<R>> {
+        let mut reader = png::Decoder::new(image).read_info()?;
+        let info = reader.info();
+        if (info.width, info.height, info.color_type, info.bit_depth)
+            != (
+                R::WIDTH,
+                R::HEIGHT,
+                png::ColorType::Grayscale,
+                png::BitDepth::One,
+            )
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Expected a black and white {}x{} image",
+                    R::WIDTH,
+                    R::HEIGHT
+                ),
+            ));
+        }
+        let mut bytes = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut bytes)?;
+        Ok(Framebuffer::from_bytes(&bytes))
+    }
+
+    /// Framebuffer of the bytes of `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Framebuffer<R> {
+        Framebuffer {
+            rows: bytes
+                .chunks(R::WIDTH as usize / 8)
+                .map(R::from_bytes)
+                .collect(),
+        }
+    }
+}
+
+/// Columns of the pixels set in `bits`
+pub fn columns<R: Row>(mut bits: R) -> impl Iterator<Item = u32> {
+    std::iter::from_fn(move || {
+        if bits.is_zero() {
+            return None;
+        }
+        let bit = bits.trailing_zeros();
+        bits = bits ^ (R::from_left(0x80) >> (R::WIDTH - 1 - bit));
+        Some(R::WIDTH - 1 - bit)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw() {
+        let mut fb = Lores::default();
+        let mut flipped = Vec::new();
+        let collision = fb.draw(60, 31, &[0xff, 0x81], false, |y, bits, row| {
+            assert_eq!(bits, row);
+            flipped.push((y, columns(bits).collect::<Vec<_>>()))
+        });
+        assert!(!collision);
+        // clipped at the right and bottom edges
+        assert_eq!(fb.rows()[31], 0xf);
+        assert_eq!(fb.rows()[0], 0);
+        assert_eq!(flipped, vec![(31, vec![63, 62, 61, 60])]);
+
+        assert!(fb.draw(124, 95, &[0xff, 0x81], true, |_, _, _| {}));
+        // wrapped to the left and top edges, colliding in the last row
+        assert_eq!(fb.rows()[31], 0xf0 << 56);
+        assert_eq!(fb.rows()[0], 1 << 60 | 1 << 3);
+        assert!(fb.pixel(3, 0) && fb.pixel(60, 0) && !fb.pixel(0, 0));
+
+        let bytes = fb.to_bytes();
+        assert_eq!(bytes.len(), 256);
+        assert_eq!(Lores::from_bytes(&bytes), fb);
+        let ascii = fb.to_ascii();
+        assert_eq!(ascii.lines().count(), 32);
+        assert!(
+            ascii.starts_with("...#........................................................#...\n")
+        );
+        assert_eq!(&fb.to_pbm()[..9], b"P4\n64 32\n");
+        assert_eq!(Lores::from_png(&fb.to_png().unwrap()).unwrap(), fb);
+        assert!(Hires::from_png(&fb.to_png().unwrap()).is_err());
+        fb.clear();
+        assert_eq!(fb, Lores::default());
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut fb = Hires::default();
+        assert_eq!(fb.rows().len(), 64);
+        assert!(!fb.draw(124, 63, &[0xff, 0xff], false, |_, _, _| {}));
+        assert_eq!((fb.rows()[63], fb.rows()[0]), (0xf, 0));
+        assert!(fb.draw(120, 63, &[0x0f], false, |_, _, _| {}));
+        assert_eq!(fb.rows()[63], 0);
+        assert_eq!(fb.to_bytes().len(), 1024);
+    }
+}
@@ -0,0 +1,582 @@
+//! GDB remote serial protocol stub
+//!
+//! Exposes the machine to gdb and IDE front-ends over TCP. Registers are
+//! numbered V0-VF (0-15), I (16), SP (17), PC (18), DT (19) and ST (20),
+//! 16-bit registers are sent in little endian. The layout is described by
+//! the target description returned for `qXfer:features:read:target.xml`.
+//!
+//! https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::chip8::{Chip8, Fault, MEMORY_SIZE};
+use crate::debugger::{
+    register, set_register, Breakpoint, Debugger, Register, StopReason, WatchKind, Watchpoint,
+};
+use crate::screen::Screen;
+
+/// Registers in the order they are sent by `g`, with size in bits
+const REGISTERS: [(&str, Register, u32); 21] = [
+    ("v0", Register::V(0x0), 8),
+    ("v1", Register::V(0x1), 8),
+    ("v2", Register::V(0x2), 8),
+    ("v3", Register::V(0x3), 8),
+    ("v4", Register::V(0x4), 8),
+    ("v5", Register::V(0x5), 8),
+    ("v6", Register::V(0x6), 8),
+    ("v7", Register::V(0x7), 8),
+    ("v8", Register::V(0x8), 8),
+    ("v9", Register::V(0x9), 8),
+    ("va", Register::V(0xa), 8),
+    ("vb", Register::V(0xb), 8),
+    ("vc", Register::V(0xc), 8),
+    ("vd", Register::V(0xd), 8),
+    ("ve", Register::V(0xe), 8),
+    ("vf", Register::V(0xf), 8),
+    ("i", Register::I, 16),
+    ("sp", Register::Sp, 16),
+    ("pc", Register::Pc, 16),
+    ("dt", Register::Dt, 8),
+    ("st", Register::St, 8),
+];
+
+/// How often running program checks for the interrupt request from gdb
+const INTERRUPT_POLL_INTERVAL: u64 = 0x1000;
+
+/// SIGTRAP, reported for breakpoints and finished steps
+const SIGTRAP: u8 = 5;
+/// SIGILL, reported when instruction can't be decoded
+const SIGILL: u8 = 4;
+/// SIGSEGV, reported for memory accesses past the end and stack errors
+const SIGSEGV: u8 = 11;
+/// SIGINT, reported when gdb interrupts running program
+const SIGINT: u8 = 2;
+
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chiper.chip8\">\n",
+    );
+    for (n, (name, reg, bits)) in REGISTERS.iter().enumerate() {
+        let ty = match reg {
+            Register::Pc => "code_ptr",
+            Register::I | Register::Sp => "data_ptr",
+            _ => "uint8",
+        };
+        xml.push_str(&format!(
+            "  <reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+            name, bits, ty, n
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_num(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// Parses `ADDR,LEN` pair used by memory packets
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((parse_num(addr)?, parse_num(len)?))
+}
+
+/// Connection to a single gdb client
+pub struct Session<'a, T> {
+    chip8: &'a mut Chip8<T>,
+    stream: TcpStream,
+    debugger: Debugger,
+    no_ack: bool,
+    /// Acks are disabled once reply to `QStartNoAckMode` is sent
+    start_no_ack: bool,
+}
+
+impl<'a, T: Screen> Session<'a, T> {
+    pub fn new(chip8: &'a mut Chip8<T>, stream: TcpStream) -> Session<'a, T> {
+        Session {
+            chip8,
+            stream,
+            debugger: Debugger::new(),
+            no_ack: false,
+            start_no_ack: false,
+        }
+    }
+
+    /// Serves requests until gdb detaches or closes the connection
+    pub fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Some(reply) => self.send_packet(&reply)?,
+                None => break,
+            }
+            if self.start_no_ack {
+                self.no_ack = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Reads next packet payload, returns None once connection is closed
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // skip acks and interrupt requests received while stopped
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                    None => return Ok(None),
+                }
+            }
+            let mut sum = [0; 2];
+            self.stream.read_exact(&mut sum)?;
+            let expected = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            if self.no_ack {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            if expected == Some(checksum(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let mut escaped = Vec::with_capacity(data.len());
+        for &b in data.as_bytes() {
+            if matches!(b, b'$' | b'#' | b'}' | b'*') {
+                escaped.push(b'}');
+                escaped.push(b ^ 0x20);
+            } else {
+                escaped.push(b);
+            }
+        }
+        let mut packet = vec![b'$'];
+        packet.extend(&escaped);
+        packet.extend(format!("#{:02x}", checksum(&escaped)).as_bytes());
+        loop {
+            self.stream.write_all(&packet)?;
+            if self.no_ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Handles single packet, returns reply or None when session is over
+    fn handle(&mut self, packet: &str) -> io::Result<Option<String>> {
+        let (cmd, args) = packet.split_at(packet.len().min(1));
+        let reply = match cmd {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => match parse_num(args).and_then(|n| REGISTERS.get(n)) {
+                Some(&(_, reg, bits)) => self.encode_register(reg, bits),
+                None => "E01".to_string(),
+            },
+            "P" => self.write_register(args),
+            "m" => match parse_range(args) {
+                Some((addr, len)) if addr < MEMORY_SIZE => {
+                    let end = addr.saturating_add(len).min(MEMORY_SIZE);
+                    encode_hex(&self.chip8.memory[addr..end])
+                }
+                _ => "E01".to_string(),
+            },
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.update_point(cmd == "Z", args),
+            "s" => match self.debugger.run_until(self.chip8, |_, _| true) {
+                StopReason::Quit => return self.exited(),
+                reason => self.stop_reply(reason),
+            },
+            "c" => {
+                let stream = &mut self.stream;
+                let mut interrupted = false;
+                let reason = self.debugger.run_until(self.chip8, |c, _| {
+                    if c.cycles.is_multiple_of(INTERRUPT_POLL_INTERVAL) {
+                        interrupted = poll_interrupt(stream);
+                    }
+                    interrupted
+                });
+                match reason {
+                    _ if interrupted => format!("S{:02x}", SIGINT),
+                    StopReason::Quit => return self.exited(),
+                    reason => self.stop_reply(reason),
+                }
+            }
+            "H" | "T" => "OK".to_string(),
+            "D" => {
+                self.send_packet("OK")?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    /// Reports the program exited once the window is closed, ends the
+    /// session
+    fn exited(&mut self) -> io::Result<Option<String>> {
+        self.send_packet("W00")?;
+        Ok(None)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml();
+            return match parse_range(range) {
+                Some((offset, _)) if offset >= xml.len() => "l".to_string(),
+                Some((offset, len)) if offset.saturating_add(len) >= xml.len() => {
+                    format!("l{}", &xml[offset..])
+                }
+                Some((offset, len)) => format!("m{}", &xml[offset..offset + len]),
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.start_no_ack = true;
+                "OK"
+            }
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            _ => "",
+        }
+        .to_string()
+    }
+
+    fn encode_register(&self, reg: Register, bits: u32) -> String {
+        let value = register(self.chip8, reg);
+        let bytes = value.to_le_bytes();
+        encode_hex(&bytes[..bits as usize / 8])
+    }
+
+    fn read_registers(&self) -> String {
+        REGISTERS
+            .iter()
+            .map(|&(_, reg, bits)| self.encode_register(reg, bits))
+            .collect()
+    }
+
+    fn set(&mut self, reg: Register, bytes: &[u8]) -> bool {
+        let value = match *bytes {
+            [b] => b as u16,
+            [lo, hi] => u16::from_le_bytes([lo, hi]),
+            _ => return false,
+        };
+        self.debugger.state_changed();
+        set_register(self.chip8, reg, value).is_ok()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let bytes = match decode_hex(args) {
+            Some(bytes) => bytes,
+            None => return "E01".to_string(),
+        };
+        let mut offset = 0;
+        for &(_, reg, bits) in REGISTERS.iter() {
+            let size = bits as usize / 8;
+            match bytes.get(offset..offset + size) {
+                Some(value) if self.set(reg, value) => offset += size,
+                _ => return "E01".to_string(),
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(n, value)| {
+            let &(_, reg, bits) = REGISTERS.get(parse_num(n)?)?;
+            let bytes = decode_hex(value)?;
+            Some((reg, bits, bytes))
+        });
+        match parsed {
+            Some((reg, bits, bytes)) if bytes.len() == bits as usize / 8 => {
+                if self.set(reg, &bytes) {
+                    "OK".to_string()
+                } else {
+                    "E01".to_string()
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_range(range)?;
+            let data = decode_hex(data)?;
+            Some((addr, len, data))
+        });
+        match parsed {
+            Some((_, len, data)) if data.len() != len => "E01".to_string(),
+            Some((addr, _, data)) if self.chip8.poke_memory(addr, &data).is_ok() => {
+                self.debugger.state_changed();
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// Handles `Z`/`z` packets: `TYPE,ADDR,KIND`
+    fn update_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let ty = fields.next();
+        let addr = fields.next().and_then(parse_num);
+        let len = fields.next().and_then(parse_num).unwrap_or(1);
+        let addr = match addr {
+            Some(addr) if addr < MEMORY_SIZE => addr,
+            _ => return "E01".to_string(),
+        };
+        // watched bytes past the end of memory are never accessed
+        let len = len.clamp(1, MEMORY_SIZE - addr);
+        let kind = match ty {
+            // software and hardware breakpoints are the same thing here
+            Some("0") | Some("1") => {
+                if insert {
+                    self.debugger.add_breakpoint(Breakpoint {
+                        addr: Some(addr),
+                        condition: None,
+                    });
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return "OK".to_string();
+            }
+            Some("2") => WatchKind::Write,
+            Some("3") => WatchKind::Read,
+            Some("4") => WatchKind::Access,
+            _ => return String::new(),
+        };
+        let watchpoint = Watchpoint { addr, len, kind };
+        if insert {
+            self.debugger.add_watchpoint(watchpoint);
+        } else {
+            self.debugger.remove_watchpoint(&watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Watchpoint(access) => {
+                let kind = self
+                    .debugger
+                    .watchpoints()
+                    .iter()
+                    .find(|w| w.addr <= access.addr && access.addr < w.addr + w.len)
+                    .map(|w| w.kind);
+                let name = match kind {
+                    Some(WatchKind::Read) => "rwatch",
+                    Some(WatchKind::Access) => "awatch",
+                    _ => "watch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, access.addr)
+            }
+            StopReason::Fault(e) if e.fault == Fault::UnknownOpcode => format!("S{:02x}", SIGILL),
+            StopReason::Fault(_) => format!("S{:02x}", SIGSEGV),
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+}
+
+/// Checks whether gdb asked to interrupt running program
+fn poll_interrupt(stream: &mut TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0];
+    let interrupted = match stream.read(&mut byte) {
+        Ok(1) => byte[0] == 0x03,
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => false,
+        _ => false,
+    };
+    let _ = stream.set_nonblocking(false);
+    interrupted
+}
+
+/// Waits for gdb to connect to `addr` and serves it
+pub fn serve<T: Screen>(chip8: &mut Chip8<T>, addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Waiting for gdb connection on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("gdb connected from {}", peer);
+    stream.set_nodelay(true)?;
+    Session::new(chip8, stream).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+    use std::thread;
+
+    /// Scripted gdb side of the connection
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], b'+');
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.read_reply()
+        }
+
+        fn read_reply(&mut self) -> String {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], b'$');
+            let mut data = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut sum = [0; 2];
+            self.stream.read_exact(&mut sum).unwrap();
+            assert_eq!(
+                u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap(),
+                checksum(&data)
+            );
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(data).unwrap()
+        }
+    }
+
+    fn start(prog: &'static [u8]) -> (Client, thread::JoinHandle<Chip8<NoScreen>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut chip8 = Chip8::new(NoScreen {});
+            chip8.load_from_slice(prog);
+            let (stream, _) = listener.accept().unwrap();
+            Session::new(&mut chip8, stream).run().unwrap();
+            chip8
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        (Client { stream }, server)
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let (mut gdb, server) = start(&[
+            0x60, 0x12, // 200: mov V0, 12
+            0xa3, 0x45, // 202: mov I, 345
+            0x12, 0x04, // 204: jmp 204
+        ]);
+        assert!(gdb
+            .request("qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert_eq!(gdb.request("?"), "S05");
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("s"), "S05");
+        let regs = gdb.request("g");
+        // V0..VF, I, SP, PC, DT, ST
+        assert_eq!(regs.len(), (16 + 2 * 3 + 2) * 2);
+        assert_eq!(&regs[..2], "12");
+        assert_eq!(&regs[32..], "4503000f04020000");
+        assert_eq!(gdb.request("p12"), "0402");
+        assert_eq!(gdb.request("P3=7f"), "OK");
+        assert_eq!(gdb.request("p3"), "7f");
+        assert_eq!(gdb.request("m200,4"), "6012a345");
+        assert_eq!(gdb.request("M300,2:beef"), "OK");
+        assert_eq!(gdb.request("m300,2"), "beef");
+        assert_eq!(gdb.request("mffe,ffffffffffffffff"), "0000");
+        assert_eq!(gdb.request("Mffe,ffffffffffffffff:00"), "E01");
+        assert_eq!(gdb.request("Mffffffffffffffff,1:00"), "E01");
+        assert_eq!(gdb.request("Z2,ffe,ffffffffffffffff"), "OK");
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+        assert!(gdb
+            .request("qXfer:features:read:target.xml:0,fff")
+            .starts_with("l<?xml"));
+        assert!(gdb
+            .request("qXfer:features:read:target.xml:1,ffffffffffffffff")
+            .starts_with("l?xml"));
+        assert_eq!(gdb.request("D"), "OK");
+
+        let chip8 = server.join().unwrap();
+        assert_eq!(chip8.v[3], 0x7f);
+        assert_eq!(chip8.memory[0x301], 0xef);
+    }
+
+    #[test]
+    fn test_breakpoints_and_continue() {
+        let (mut gdb, server) = start(&[
+            0x70, 0x01, // 200: add V0, 01
+            0x30, 0x05, // 202: skipifeq V0, 05
+            0x12, 0x00, // 204: jmp 200
+            0xa3, 0x00, // 206: mov I, 300
+            0xf0, 0x55, // 208: movm I, V0-V0
+            0x12, 0x0a, // 20a: jmp 20a
+        ]);
+        assert_eq!(gdb.request("Z0,206,2"), "OK");
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.request("p12"), "0602");
+        assert_eq!(gdb.request("p0"), "05");
+        assert_eq!(gdb.request("z0,206,2"), "OK");
+        assert_eq!(gdb.request("Z2,300,1"), "OK");
+        assert_eq!(gdb.request("c"), "T05watch:300;");
+        assert_eq!(gdb.request("c"), "S05");
+        assert_eq!(gdb.request("p12"), "0a02");
+        gdb.send("k");
+        server.join().unwrap();
+    }
+}
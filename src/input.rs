@@ -0,0 +1,120 @@
+//! Scripted input
+//!
+//! A script sets the pressed keypad keys at given frames, counted from the
+//! start of the run, so headless runs get the same input every time. Every
+//! line has a frame and the keys held from that frame on as hex digits, or
+//! `-` for none:
+//!
+//! ```text
+//! # start the game, then hold 4 and 6 for a second
+//! 30 5
+//! 32 -
+//! 100 46
+//! 160 -
+//! ```
+
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::chip8::{Chip8, ExecError};
+use crate::screen::Screen;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Script {
+    /// Frames and the keys pressed from them on, ordered by frame
+    changes: Vec<(u64, [bool; 16])>,
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Script, String> {
+        let mut changes: Vec<(u64, [bool; 16])> = Vec::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |e: &str| format!("Line {}: {}", n + 1, e);
+            let (frame, digits) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error("expected a frame and keys"))?;
+            let frame: u64 = frame.parse().map_err(|_| error("invalid frame"))?;
+            if changes.last().is_some_and(|&(last, _)| last >= frame) {
+                return Err(error("frames have to increase"));
+            }
+            let mut keys = [false; 16];
+            match digits.trim() {
+                "-" => {}
+                digits => {
+                    for digit in digits.chars() {
+                        let key = digit
+                            .to_digit(16)
+                            .ok_or_else(|| error(&format!("invalid key '{}'", digit)))?;
+                        keys[key as usize] = true;
+                    }
+                }
+            }
+            changes.push((frame, keys));
+        }
+        Ok(Script { changes })
+    }
+}
+
+impl Script {
+    pub fn load(path: &str) -> io::Result<Script> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Runs `frames` frames like `Chip8::run_frames`, pressing the keys of
+    /// the script
+    pub fn run<T: Screen>(&self, chip8: &mut Chip8<T>, frames: u64) -> Result<(), ExecError> {
+        let mut frame = 0;
+        for &(start, keys) in self.changes.iter().take_while(|&&(f, _)| f < frames) {
+            chip8.run_frames(start - frame)?;
+            chip8.set_keys(keys);
+            frame = start;
+        }
+        chip8.run_frames(frames - frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+
+    #[test]
+    fn test_parse() {
+        let script: Script = "# comment\n\n2 5\n4 a0 # both\n6 -\n".parse().unwrap();
+        let mut keys = [false; 16];
+        keys[5] = true;
+        assert_eq!(script.changes[0], (2, keys));
+        assert!(script.changes[1].1[0] && script.changes[1].1[0xa]);
+        assert_eq!(script.changes[2], (6, [false; 16]));
+
+        assert_eq!(
+            "1 5\n1 6".parse::<Script>(),
+            Err("Line 2: frames have to increase".to_string())
+        );
+        assert!("1 g".parse::<Script>().is_err());
+        assert!("1".parse::<Script>().is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_rom("roms/pong.ch8").unwrap();
+        let script: Script = "3 1\n10 -".parse().unwrap();
+        script.run(&mut chip8, 5).unwrap();
+        assert_eq!(chip8.frames(), 5);
+        assert!(chip8.key_pressed(1));
+        // frames are counted from the start of every run
+        script.run(&mut chip8, 20).unwrap();
+        assert_eq!(chip8.frames(), 25);
+        assert!(!chip8.key_pressed(1));
+    }
+}
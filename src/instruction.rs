@@ -0,0 +1,422 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::platform::Platform;
+
+/// Register index, returned as usize since it's used only as index for V[]
+/// registers
+pub type Reg = usize;
+
+/// Decoded CHIP-8 instruction
+///
+/// Every 16-bit word either decodes into exactly one `Instruction` or is
+/// rejected with `UnknownOpcode`, and `encode` gives back the original word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// 0NNN: Calls machine code routine at address NNN
+    Sys(u16),
+    /// 00E0: Clears the screen
+    ClearScreen,
+    /// 00EE: Returns from a subroutine
+    Return,
+    /// 1NNN: Jumps to address NNN
+    Jump(u16),
+    /// 2NNN: Calls subroutine at NNN
+    Call(u16),
+    /// 3XNN: Skips the next instruction if VX equals NN
+    SkipEqImm(Reg, u8),
+    /// 4XNN: Skips the next instruction if VX doesn't equal NN
+    SkipNeImm(Reg, u8),
+    /// 5XY0: Skips the next instruction if VX equals VY
+    SkipEqReg(Reg, Reg),
+    /// 6XNN: Sets VX to NN
+    MovImm(Reg, u8),
+    /// 7XNN: Adds NN to VX. (Carry flag is not changed)
+    AddImm(Reg, u8),
+    /// 8XY0: Sets VX to the value of VY
+    MovReg(Reg, Reg),
+    /// 8XY1: Sets VX to VX or VY
+    Or(Reg, Reg),
+    /// 8XY2: Sets VX to VX and VY
+    And(Reg, Reg),
+    /// 8XY3: Sets VX to VX xor VY
+    Xor(Reg, Reg),
+    /// 8XY4: Adds VY to VX. VF is set to 1 when there's a carry
+    AddReg(Reg, Reg),
+    /// 8XY5: VY is subtracted from VX. VF is set to 0 when there's a borrow
+    SubReg(Reg, Reg),
+    /// 8XY6: Shifts VX to the right by 1, VF is set to the shifted out bit
+    ShiftRight(Reg, Reg),
+    /// 8XY7: Sets VX to VY minus VX. VF is set to 0 when there's a borrow
+    SubReverse(Reg, Reg),
+    /// 8XYE: Shifts VX to the left by 1, VF is set to the shifted out bit
+    ShiftLeft(Reg, Reg),
+    /// 9XY0: Skips the next instruction if VX doesn't equal VY
+    SkipNeReg(Reg, Reg),
+    /// ANNN: Sets I to the address NNN
+    MovI(u16),
+    /// BNNN: Jumps to the address NNN plus V0
+    JumpV0(u16),
+    /// CXNN: Sets VX to random number and NN
+    Rand(Reg, u8),
+    /// DXYN: Draws N lines of sprite at I to coordinate (VX, VY)
+    Draw(Reg, Reg, u8),
+    /// EX9E: Skips the next instruction if the key stored in VX is pressed
+    SkipKey(Reg),
+    /// EXA1: Skips the next instruction if the key stored in VX isn't pressed
+    SkipNotKey(Reg),
+    /// FX07: Sets VX to the value of the delay timer
+    GetDelay(Reg),
+    /// FX0A: Waits for a key press and stores it in VX
+    WaitKey(Reg),
+    /// FX15: Sets the delay timer to VX
+    SetDelay(Reg),
+    /// FX18: Sets the sound timer to VX
+    SetSound(Reg),
+    /// FX1E: Adds VX to I. VF is not affected
+    AddI(Reg),
+    /// FX29: Sets I to the location of the font sprite for digit VX
+    Font(Reg),
+    /// FX33: Stores BCD representation of VX at I, I+1 and I+2
+    Bcd(Reg),
+    /// FX55: Stores V0 to VX (including VX) in memory starting at address I
+    Store(Reg),
+    /// FX65: Fills V0 to VX (including VX) with values from memory at I
+    Load(Reg),
+}
+
+/// Returned for the words which are not valid CHIP-8 instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownOpcode(pub u16);
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode {:04x}", self.0)
+    }
+}
+
+impl Error for UnknownOpcode {}
+
+/// Opcode of the SUPER-CHIP or XO-CHIP extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extension {
+    /// Opcode pattern, e.g. "00FF"
+    pub pattern: &'static str,
+    pub description: &'static str,
+    /// Oldest platform with the opcode
+    pub platform: Platform,
+}
+
+impl Extension {
+    /// Recognizes opcodes chiper doesn't emulate, 00CN, 00FB-00FF and DXY0
+    /// still decode as CHIP-8 instructions
+    pub fn decode(word: u16) -> Option<Extension> {
+        let (pattern, description, platform) = match word {
+            0x00c0..=0x00cf => ("00CN", "scroll down N lines", Platform::SuperChip),
+            0x00d0..=0x00df => ("00DN", "scroll up N lines", Platform::XoChip),
+            0x00fb => ("00FB", "scroll right", Platform::SuperChip),
+            0x00fc => ("00FC", "scroll left", Platform::SuperChip),
+            0x00fd => ("00FD", "exit", Platform::SuperChip),
+            0x00fe => ("00FE", "low resolution", Platform::SuperChip),
+            0x00ff => ("00FF", "high resolution", Platform::SuperChip),
+            0xf000 => ("F000", "long I load", Platform::XoChip),
+            0xf002 => ("F002", "audio pattern", Platform::XoChip),
+            _ => match (word >> 12, word & 0xf, word & 0xff) {
+                (0x5, 0x2, _) => ("5XY2", "store VX-VY", Platform::XoChip),
+                (0x5, 0x3, _) => ("5XY3", "load VX-VY", Platform::XoChip),
+                (0xd, 0x0, _) => ("DXY0", "16x16 sprite", Platform::SuperChip),
+                (0xf, _, 0x01) => ("FN01", "select planes", Platform::XoChip),
+                (0xf, _, 0x30) => ("FX30", "big font", Platform::SuperChip),
+                (0xf, _, 0x3a) => ("FX3A", "audio pitch", Platform::XoChip),
+                (0xf, _, 0x75) => ("FX75", "store flags", Platform::SuperChip),
+                (0xf, _, 0x85) => ("FX85", "load flags", Platform::SuperChip),
+                _ => return None,
+            },
+        };
+        Some(Extension {
+            pattern,
+            description,
+            platform,
+        })
+    }
+
+    /// Size of the instruction in bytes, F000 NNNN is the only one taking 4
+    pub fn size(word: u16) -> usize {
+        if word == 0xf000 {
+            4
+        } else {
+            2
+        }
+    }
+}
+
+impl Instruction {
+    pub fn decode(word: u16) -> Result<Instruction, UnknownOpcode> {
+        use Instruction::*;
+
+        let x = ((word >> 8) & 0xf) as Reg;
+        let y = ((word >> 4) & 0xf) as Reg;
+        let n = (word & 0xf) as u8;
+        let nn = (word & 0xff) as u8;
+        let nnn = word & 0xfff;
+
+        let instruction = match word >> 12 {
+            0x0 => match word {
+                0x00e0 => ClearScreen,
+                0x00ee => Return,
+                _ => Sys(nnn),
+            },
+            0x1 => Jump(nnn),
+            0x2 => Call(nnn),
+            0x3 => SkipEqImm(x, nn),
+            0x4 => SkipNeImm(x, nn),
+            0x5 if n == 0 => SkipEqReg(x, y),
+            0x6 => MovImm(x, nn),
+            0x7 => AddImm(x, nn),
+            0x8 => match n {
+                0x0 => MovReg(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddReg(x, y),
+                0x5 => SubReg(x, y),
+                0x6 => ShiftRight(x, y),
+                0x7 => SubReverse(x, y),
+                0xe => ShiftLeft(x, y),
+                _ => return Err(UnknownOpcode(word)),
+            },
+            0x9 if n == 0 => SkipNeReg(x, y),
+            0xa => MovI(nnn),
+            0xb => JumpV0(nnn),
+            0xc => Rand(x, nn),
+            0xd => Draw(x, y, n),
+            0xe => match nn {
+                0x9e => SkipKey(x),
+                0xa1 => SkipNotKey(x),
+                _ => return Err(UnknownOpcode(word)),
+            },
+            0xf => match nn {
+                0x07 => GetDelay(x),
+                0x0a => WaitKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1e => AddI(x),
+                0x29 => Font(x),
+                0x33 => Bcd(x),
+                0x55 => Store(x),
+                0x65 => Load(x),
+                _ => return Err(UnknownOpcode(word)),
+            },
+            _ => return Err(UnknownOpcode(word)),
+        };
+        Ok(instruction)
+    }
+
+    /// Decodes instruction from two bytes as they are stored in memory
+    pub fn from_bytes(high: u8, low: u8) -> Result<Instruction, UnknownOpcode> {
+        Instruction::decode((high as u16) << 8 | low as u16)
+    }
+
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        fn xnn(op: u16, x: Reg, nn: u8) -> u16 {
+            op << 12 | (x as u16) << 8 | nn as u16
+        }
+        fn xyn(op: u16, x: Reg, y: Reg, n: u8) -> u16 {
+            op << 12 | (x as u16) << 8 | (y as u16) << 4 | n as u16
+        }
+
+        match *self {
+            Sys(nnn) => nnn,
+            ClearScreen => 0x00e0,
+            Return => 0x00ee,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipEqImm(x, nn) => xnn(0x3, x, nn),
+            SkipNeImm(x, nn) => xnn(0x4, x, nn),
+            SkipEqReg(x, y) => xyn(0x5, x, y, 0x0),
+            MovImm(x, nn) => xnn(0x6, x, nn),
+            AddImm(x, nn) => xnn(0x7, x, nn),
+            MovReg(x, y) => xyn(0x8, x, y, 0x0),
+            Or(x, y) => xyn(0x8, x, y, 0x1),
+            And(x, y) => xyn(0x8, x, y, 0x2),
+            Xor(x, y) => xyn(0x8, x, y, 0x3),
+            AddReg(x, y) => xyn(0x8, x, y, 0x4),
+            SubReg(x, y) => xyn(0x8, x, y, 0x5),
+            ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            SubReverse(x, y) => xyn(0x8, x, y, 0x7),
+            ShiftLeft(x, y) => xyn(0x8, x, y, 0xe),
+            SkipNeReg(x, y) => xyn(0x9, x, y, 0x0),
+            MovI(nnn) => 0xa000 | nnn,
+            JumpV0(nnn) => 0xb000 | nnn,
+            Rand(x, nn) => xnn(0xc, x, nn),
+            Draw(x, y, n) => xyn(0xd, x, y, n),
+            SkipKey(x) => xnn(0xe, x, 0x9e),
+            SkipNotKey(x) => xnn(0xe, x, 0xa1),
+            GetDelay(x) => xnn(0xf, x, 0x07),
+            WaitKey(x) => xnn(0xf, x, 0x0a),
+            SetDelay(x) => xnn(0xf, x, 0x15),
+            SetSound(x) => xnn(0xf, x, 0x18),
+            AddI(x) => xnn(0xf, x, 0x1e),
+            Font(x) => xnn(0xf, x, 0x29),
+            Bcd(x) => xnn(0xf, x, 0x33),
+            Store(x) => xnn(0xf, x, 0x55),
+            Load(x) => xnn(0xf, x, 0x65),
+        }
+    }
+
+    /// Opcode pattern the instruction belongs to, e.g. "8XY4"
+    pub fn pattern(&self) -> &'static str {
+        use Instruction::*;
+
+        match *self {
+            Sys(_) => "0NNN",
+            ClearScreen => "00E0",
+            Return => "00EE",
+            Jump(_) => "1NNN",
+            Call(_) => "2NNN",
+            SkipEqImm(..) => "3XNN",
+            SkipNeImm(..) => "4XNN",
+            SkipEqReg(..) => "5XY0",
+            MovImm(..) => "6XNN",
+            AddImm(..) => "7XNN",
+            MovReg(..) => "8XY0",
+            Or(..) => "8XY1",
+            And(..) => "8XY2",
+            Xor(..) => "8XY3",
+            AddReg(..) => "8XY4",
+            SubReg(..) => "8XY5",
+            ShiftRight(..) => "8XY6",
+            SubReverse(..) => "8XY7",
+            ShiftLeft(..) => "8XYE",
+            SkipNeReg(..) => "9XY0",
+            MovI(_) => "ANNN",
+            JumpV0(_) => "BNNN",
+            Rand(..) => "CXNN",
+            Draw(..) => "DXYN",
+            SkipKey(_) => "EX9E",
+            SkipNotKey(_) => "EXA1",
+            GetDelay(_) => "FX07",
+            WaitKey(_) => "FX0A",
+            SetDelay(_) => "FX15",
+            SetSound(_) => "FX18",
+            AddI(_) => "FX1E",
+            Font(_) => "FX29",
+            Bcd(_) => "FX33",
+            Store(_) => "FX55",
+            Load(_) => "FX65",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Sys(nnn) => write!(f, "sys\t\t{:03x}", nnn),
+            ClearScreen => write!(f, "dclr"),
+            Return => write!(f, "ret"),
+            Jump(nnn) => write!(f, "jmp\t\t{:03x}", nnn),
+            Call(nnn) => write!(f, "call\t\t{:03x}", nnn),
+            SkipEqImm(x, nn) => write!(f, "skipifeq\tV{:01x}, {:02x}", x, nn),
+            SkipNeImm(x, nn) => write!(f, "skipifne\tV{:01x}, {:02x}", x, nn),
+            SkipEqReg(x, y) => write!(f, "skipifeq\tV{:01x}, V{:01x}", x, y),
+            MovImm(x, nn) => write!(f, "mov\t\tV{:01x}, {:02x}", x, nn),
+            AddImm(x, nn) => write!(f, "add\t\tV{:01x}, {:02x}", x, nn),
+            MovReg(x, y) => write!(f, "mov\t\tV{:01x}, V{:01x}", x, y),
+            Or(x, y) => write!(f, "or\t\tV{:01x}, V{:01x}", x, y),
+            And(x, y) => write!(f, "and\t\tV{:01x}, V{:01x}", x, y),
+            Xor(x, y) => write!(f, "xor\t\tV{:01x}, V{:01x}", x, y),
+            AddReg(x, y) => write!(f, "addwc\t\tV{:01x}, V{:01x}", x, y),
+            SubReg(x, y) => write!(f, "subwc\t\tV{:01x}, V{:01x}", x, y),
+            ShiftRight(x, y) => write!(f, "shr\t\tV{:01x}, V{:01x}", x, y),
+            SubReverse(x, y) => write!(f, "subwc\t\tV{:01x}, V{:01x}, V{:01x}", x, y, x),
+            ShiftLeft(x, y) => write!(f, "shl\t\tV{:01x}, V{:01x}", x, y),
+            SkipNeReg(x, y) => write!(f, "skipifne\tV{:01x}, V{:01x}", x, y),
+            MovI(nnn) => write!(f, "mov\t\tI, {:03x}", nnn),
+            JumpV0(nnn) => write!(f, "jmp\t\tV0, {:03x}", nnn),
+            Rand(x, nn) => write!(f, "rnd\t\tV{:01x}, {:02x}", x, nn),
+            Draw(x, y, n) => write!(f, "draw\t\tV{:01x}, V{:01x}, {:01x}", x, y, n),
+            SkipKey(x) => write!(f, "skipifkey\tV{:01x}", x),
+            SkipNotKey(x) => write!(f, "skipifnkey\tV{:01x}", x),
+            GetDelay(x) => write!(f, "mov\t\tV{:01x}, DT", x),
+            WaitKey(x) => write!(f, "waitkey\t\tV{:01x}", x),
+            SetDelay(x) => write!(f, "mov\t\tDT, V{:01x}", x),
+            SetSound(x) => write!(f, "mov\t\tST, V{:01x}", x),
+            AddI(x) => write!(f, "add\t\tI, V{:01x}", x),
+            Font(x) => write!(f, "font\t\tI, V{:01x}", x),
+            Bcd(x) => write!(f, "bcd\t\tI, V{:01x}", x),
+            Store(x) => write!(f, "movm\t\tI, V0-V{:01x}", x),
+            Load(x) => write!(f, "movm\t\tV0-V{:01x}, I", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_encode_roundtrip() {
+        let mut known = 0;
+        for word in 0..=0xffff_u16 {
+            if let Ok(instruction) = Instruction::decode(word) {
+                assert_eq!(instruction.encode(), word, "{}", instruction);
+                known += 1;
+            }
+        }
+        // 0-4, 6-7 and A-D are fully populated, 5 and 9 take 1/16 of
+        // their words, 8 - 9/16, E - 2/256 and F - 9/256
+        assert_eq!(
+            known,
+            0x1000 * 11 + 0x100 * 2 + 0x100 * 9 + 0x10 * 2 + 0x10 * 9
+        );
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        assert_eq!(Instruction::decode(0x5121), Err(UnknownOpcode(0x5121)));
+        assert_eq!(Instruction::decode(0x812f), Err(UnknownOpcode(0x812f)));
+        assert_eq!(Instruction::decode(0xe1ff), Err(UnknownOpcode(0xe1ff)));
+        assert_eq!(Instruction::decode(0xf1ff), Err(UnknownOpcode(0xf1ff)));
+    }
+
+    #[test]
+    fn test_display() {
+        let ins = Instruction::from_bytes(0xd2, 0x36).unwrap();
+        assert_eq!(ins, Instruction::Draw(2, 3, 6));
+        assert_eq!(ins.to_string(), "draw\t\tV2, V3, 6");
+        assert_eq!(Instruction::MovI(0x220).to_string(), "mov\t\tI, 220");
+    }
+
+    #[test]
+    fn test_extension() {
+        let scroll = Extension::decode(0x00c4).unwrap();
+        assert_eq!(scroll.pattern, "00CN");
+        assert_eq!(scroll.platform, Platform::SuperChip);
+        assert_eq!(
+            Extension::decode(0xf201).unwrap().platform,
+            Platform::XoChip
+        );
+        assert_eq!(Extension::decode(0xd120).unwrap().pattern, "DXY0");
+        assert_eq!(Extension::decode(0xd121), None);
+        assert_eq!(Extension::decode(0x00e0), None);
+        assert_eq!(Extension::size(0xf000), 4);
+    }
+
+    #[test]
+    fn test_pattern() {
+        // pattern is the opcode with operands replaced by letters
+        for word in 0..=0xffff_u16 {
+            if let Ok(instruction) = Instruction::decode(word) {
+                let pattern = instruction.pattern();
+                let hex = format!("{:04X}", word);
+                for (p, h) in pattern.chars().zip(hex.chars()) {
+                    assert!(p == h || "XYN".contains(p), "{} {}", pattern, hex);
+                }
+            }
+        }
+    }
+}
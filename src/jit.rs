@@ -0,0 +1,364 @@
+//! x86-64 JIT
+//!
+//! Blocks starting often enough are compiled to native code working on V0-VF
+//! and I. A native block is a straight run of register and I instructions,
+//! optionally ended by a jump or a skip, and returns the next pc. Draws, key
+//! waits, timer reads and memory accesses aren't compiled, the block exits
+//! before them and the interpreter executes them. Quirks are compiled in.
+//!
+//! Writes to memory a block was compiled from drop it like in the cached
+//! engine, so self-modifying code is compiled again.
+
+#[cfg(not(target_arch = "x86_64"))]
+compile_error!("the jit feature needs x86-64");
+
+use std::mem;
+
+use dynasmrt::x64::Assembler;
+use dynasmrt::{dynasm, AssemblyOffset, DynasmApi, DynasmLabelApi, ExecutableBuffer};
+
+use crate::cache::{BlockCache, MAX_BLOCK_LEN};
+use crate::chip8::MEMORY_SIZE;
+use crate::instruction::Instruction;
+use crate::platform::Quirks;
+
+/// Times a block starts before it's compiled
+const HOT: u8 = 4;
+
+/// Hits of addresses whose instruction isn't compiled
+const COLD: u8 = u8::MAX;
+
+/// Native code of a block, takes pointers to V0-VF, I and the amount of
+/// instructions it may execute, returns the next pc
+type Code = extern "sysv64" fn(*mut u8, *mut u16, *mut u64) -> u64;
+
+pub struct Native {
+    buffer: ExecutableBuffer,
+    entry: AssemblyOffset,
+}
+
+impl Native {
+    /// Executes the block, at most `budget` instructions of it, which are
+    /// subtracted from the budget. Returns the next pc
+    pub fn run(&self, v: &mut [u8; 16], i: &mut u16, budget: &mut u64) -> usize {
+        // the buffer holds a function of the type assembled by `compile`
+        let code: Code = unsafe { mem::transmute(self.buffer.ptr(self.entry)) };
+        code(v.as_mut_ptr(), i, budget) as usize
+    }
+}
+
+/// Native blocks and how often the addresses without one started a block
+pub struct Jit {
+    blocks: BlockCache<Native>,
+    hits: Vec<u8>,
+}
+
+impl Default for Jit {
+    fn default() -> Jit {
+        Jit {
+            blocks: BlockCache::default(),
+            hits: vec![0; MEMORY_SIZE],
+        }
+    }
+}
+
+impl Jit {
+    /// Native block starting at `addr`, compiled once the address is hot.
+    /// None while it isn't, when the instruction at `addr` isn't compiled
+    /// or when it's past the end of memory
+    pub fn block(&mut self, memory: &[u8], addr: usize, quirks: Quirks) -> Option<&Native> {
+        if addr + 1 >= MEMORY_SIZE {
+            return None;
+        }
+        if self.blocks.get(addr).is_some() {
+            return self.blocks.get(addr);
+        }
+        match self.hits[addr] {
+            COLD => return None,
+            hits if hits < HOT => {
+                self.hits[addr] += 1;
+                return None;
+            }
+            _ => {}
+        }
+        let (native, size) = match compile(memory, addr, quirks) {
+            Some(compiled) => compiled,
+            None => {
+                self.hits[addr] = COLD;
+                return None;
+            }
+        };
+        self.blocks.insert(addr, size, native);
+        self.blocks.get(addr)
+    }
+
+    /// Drops the native blocks compiled from `addr` after it's written
+    pub fn invalidate(&mut self, addr: usize) {
+        self.blocks.invalidate(addr);
+        // the instruction may be compiled now
+        for start in addr.saturating_sub(1)..=addr {
+            if self.hits[start] == COLD {
+                self.hits[start] = 0;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Jit::default();
+    }
+}
+
+/// How a compiled instruction continues
+enum Flow {
+    Next,
+    /// The instruction returned the next pc
+    Exit,
+}
+
+/// Compiles the block at `addr`, returns it with the amount of bytes it's
+/// made from
+fn compile(memory: &[u8], addr: usize, quirks: Quirks) -> Option<(Native, usize)> {
+    let mut ops = Assembler::new().ok()?;
+    let entry = ops.offset();
+    let (mut pc, mut len) = (addr, 0);
+    loop {
+        let instruction = match memory.get(pc..pc + 2) {
+            Some(bytes) if len < MAX_BLOCK_LEN => Instruction::from_bytes(bytes[0], bytes[1]).ok(),
+            _ => None,
+        };
+        // programs halt by jumping to themselves, the runners stop before
+        // the jump
+        let flow = match instruction {
+            Some(Instruction::Jump(target)) if target as usize == pc => None,
+            Some(instruction) => emit(&mut ops, instruction, pc, quirks),
+            None => None,
+        };
+        match flow {
+            Some(Flow::Next) => {
+                // exits once the budget is used up
+                dynasm!(ops
+                    ; .arch x64
+                    ; dec QWORD [rdx]
+                    ; jnz >next
+                    ; mov eax, pc as i32 + 2
+                    ; ret
+                    ; next:
+                );
+            }
+            Some(Flow::Exit) => {
+                pc += 2;
+                break;
+            }
+            None if len == 0 => return None,
+            None => {
+                dynasm!(ops
+                    ; .arch x64
+                    ; mov eax, pc as i32
+                    ; ret
+                );
+                break;
+            }
+        }
+        len += 1;
+        pc += 2;
+    }
+    let buffer = ops.finalize().ok()?;
+    Some((Native { buffer, entry }, pc - addr))
+}
+
+/// Assembles the instruction at `pc`, None when it isn't compiled. V0-VF
+/// are at rdi, I is at rsi and the budget at rdx
+fn emit(ops: &mut Assembler, instruction: Instruction, pc: usize, quirks: Quirks) -> Option<Flow> {
+    use Instruction::*;
+    let (next, skip) = (pc as i32 + 2, pc as i32 + 4);
+    match instruction {
+        MovImm(x, nn) => dynasm!(ops
+            ; .arch x64
+            ; mov BYTE [rdi + x as i32], nn as i8
+        ),
+        AddImm(x, nn) => dynasm!(ops
+            ; .arch x64
+            ; add BYTE [rdi + x as i32], nn as i8
+        ),
+        MovReg(x, y) => dynasm!(ops
+            ; .arch x64
+            ; mov al, [rdi + y as i32]
+            ; mov [rdi + x as i32], al
+        ),
+        Or(x, y) | And(x, y) | Xor(x, y) => {
+            dynasm!(ops
+                ; .arch x64
+                ; mov al, [rdi + x as i32]
+            );
+            match instruction {
+                Or(..) => dynasm!(ops ; .arch x64 ; or al, [rdi + y as i32]),
+                And(..) => dynasm!(ops ; .arch x64 ; and al, [rdi + y as i32]),
+                _ => dynasm!(ops ; .arch x64 ; xor al, [rdi + y as i32]),
+            }
+            dynasm!(ops
+                ; .arch x64
+                ; mov [rdi + x as i32], al
+            );
+            if quirks.logic {
+                dynasm!(ops
+                    ; .arch x64
+                    ; mov BYTE [rdi + 0xf], 0
+                );
+            }
+        }
+        AddReg(x, y) => dynasm!(ops
+            ; .arch x64
+            ; mov al, [rdi + x as i32]
+            ; add al, [rdi + y as i32]
+            ; setc cl
+            ; mov [rdi + x as i32], al
+            ; mov [rdi + 0xf], cl
+        ),
+        SubReg(x, y) | SubReverse(x, y) => {
+            let (a, b) = if let SubReg(..) = instruction {
+                (x, y)
+            } else {
+                (y, x)
+            };
+            dynasm!(ops
+                ; .arch x64
+                ; mov al, [rdi + a as i32]
+                ; sub al, [rdi + b as i32]
+                ; setnc cl
+                ; mov [rdi + x as i32], al
+                ; mov [rdi + 0xf], cl
+            )
+        }
+        ShiftRight(x, y) | ShiftLeft(x, y) => {
+            let source = if quirks.shift { x } else { y };
+            dynasm!(ops
+                ; .arch x64
+                ; mov al, [rdi + source as i32]
+                ; mov cl, al
+            );
+            if let ShiftRight(..) = instruction {
+                dynasm!(ops
+                    ; .arch x64
+                    ; and cl, 1
+                    ; shr al, 1
+                );
+            } else {
+                dynasm!(ops
+                    ; .arch x64
+                    ; shr cl, 7
+                    ; shl al, 1
+                );
+            }
+            dynasm!(ops
+                ; .arch x64
+                ; mov [rdi + x as i32], al
+                ; mov [rdi + 0xf], cl
+            )
+        }
+        MovI(nnn) => dynasm!(ops
+            ; .arch x64
+            ; mov WORD [rsi], nnn as i16
+        ),
+        AddI(x) => dynasm!(ops
+            ; .arch x64
+            ; movzx eax, BYTE [rdi + x as i32]
+            ; add [rsi], ax
+        ),
+        Jump(target) => {
+            dynasm!(ops
+                ; .arch x64
+                ; dec QWORD [rdx]
+                ; mov eax, target as i32
+                ; ret
+            );
+            return Some(Flow::Exit);
+        }
+        JumpV0(nnn) => {
+            // BXNN is a jump to XNN + VX on SUPER-CHIP
+            let x = if quirks.jump { nnn as i32 >> 8 } else { 0 };
+            dynasm!(ops
+                ; .arch x64
+                ; dec QWORD [rdx]
+                ; movzx eax, BYTE [rdi + x]
+                ; add eax, nnn as i32
+                ; ret
+            );
+            return Some(Flow::Exit);
+        }
+        SkipEqImm(x, nn) | SkipNeImm(x, nn) => {
+            // dec sets the flags, it goes before the comparison
+            dynasm!(ops
+                ; .arch x64
+                ; dec QWORD [rdx]
+                ; cmp BYTE [rdi + x as i32], nn as i8
+            );
+            skip_exit(ops, matches!(instruction, SkipEqImm(..)), next, skip);
+            return Some(Flow::Exit);
+        }
+        SkipEqReg(x, y) | SkipNeReg(x, y) => {
+            dynasm!(ops
+                ; .arch x64
+                ; dec QWORD [rdx]
+                ; mov al, [rdi + x as i32]
+                ; cmp al, [rdi + y as i32]
+            );
+            skip_exit(ops, matches!(instruction, SkipEqReg(..)), next, skip);
+            return Some(Flow::Exit);
+        }
+        _ => return None,
+    }
+    Some(Flow::Next)
+}
+
+/// Returns `skip` if the last comparison was equal, or unequal when `equal`
+/// is false, and `next` otherwise
+fn skip_exit(ops: &mut Assembler, equal: bool, next: i32, skip: i32) {
+    dynasm!(ops
+        ; .arch x64
+        ; mov eax, next
+        ; mov ecx, skip
+    );
+    if equal {
+        dynasm!(ops ; .arch x64 ; cmove eax, ecx);
+    } else {
+        dynasm!(ops ; .arch x64 ; cmovne eax, ecx);
+    }
+    dynasm!(ops ; .arch x64 ; ret);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let mut memory = [0; MEMORY_SIZE];
+        // 200: mov V0, fe; 202: add V0, 3; 204: mov V1, V0; 206: shr V1;
+        // 208: add I, V0; 20a: skp V1 != 0; 20c: jmp 200
+        memory[0x200..0x20e].copy_from_slice(&[
+            0x60, 0xfe, 0x70, 0x03, 0x81, 0x00, 0x81, 0x16, 0xf0, 0x1e, 0x41, 0x00, 0x12, 0x00,
+        ]);
+        let (native, size) = compile(&memory, 0x200, Quirks::default()).unwrap();
+        assert_eq!(size, 0x0c);
+        let (mut v, mut i, mut budget) = ([0; 16], 0xfff0, 10);
+        assert_eq!(native.run(&mut v, &mut i, &mut budget), 0x20c);
+        assert_eq!(
+            (v[0], v[1], v[0xf], i, budget),
+            (0x01, 0x00, 0x01, 0xfff1, 4)
+        );
+
+        // stops once the budget is used up
+        let mut budget = 2;
+        assert_eq!(native.run(&mut v, &mut i, &mut budget), 0x204);
+        assert_eq!((v[0], budget), (0x01, 0));
+
+        // the block exits before the draw at 202
+        memory[0x202..0x204].copy_from_slice(&[0xd0, 0x11]);
+        let (native, size) = compile(&memory, 0x200, Quirks::default()).unwrap();
+        assert_eq!(size, 2);
+        let mut budget = 10;
+        assert_eq!(native.run(&mut v, &mut i, &mut budget), 0x202);
+        assert_eq!(budget, 9);
+        assert!(compile(&memory, 0x202, Quirks::default()).is_none());
+    }
+}
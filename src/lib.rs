@@ -0,0 +1,23 @@
+pub mod analysis;
+pub mod cache;
+pub mod cfg;
+#[allow(clippy::print_with_newline)]
+pub mod chip8;
+pub mod config;
+pub mod coverage;
+pub mod dap;
+pub mod database;
+pub mod debugger;
+pub mod decompile;
+pub mod framebuffer;
+pub mod gdb;
+pub mod input;
+pub mod instruction;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod overlay;
+pub mod platform;
+pub mod profile;
+pub mod recompile;
+pub mod screen;
+pub mod trace;
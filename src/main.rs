@@ -1,27 +1,789 @@
-#[allow(clippy::print_with_newline)]
-mod chip8;
-mod screen;
-
-use chip8::Chip8;
+use chiper::analysis;
+use chiper::cache::Engine;
+use chiper::cfg::Cfg;
+use chiper::chip8::{Chip8, DrawTiming};
+use chiper::config::{self, Config, Settings};
+use chiper::coverage::Coverage;
+use chiper::dap;
+use chiper::database::{Database, Detection};
+use chiper::debugger::expr::{BinOp, Expr};
+use chiper::debugger::Register;
+use chiper::decompile;
+use chiper::framebuffer::Lores;
+use chiper::gdb;
+use chiper::input::Script;
+use chiper::instruction::Instruction;
+use chiper::platform::{Platform, Quirks};
+use chiper::profile::Profiler;
+use chiper::recompile;
+use chiper::screen::{self, Effects, Keymap, NoScreen, Palette, Screen};
+use chiper::trace::{self, Format, Tracer};
+use clap::{Args, Parser, Subcommand};
+use std::collections::BTreeMap;
 use std::env;
-use std::io::{self, Error, ErrorKind};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::str::FromStr;
+use std::time::Instant;
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// Environment variable with log filters, e.g. `chiper::cpu=trace`
+const LOG_ENV: &str = "CHIPER_LOG";
 
-    if args.len() != 2 {
-        eprintln!("usage: chiper <path to rom>");
-        process::exit(1);
+/// Seed of headless runs without --seed, so they are repeatable
+const HEADLESS_SEED: u64 = 1;
+
+/// CHIP-8 emulator and debugger
+#[derive(Parser)]
+#[command(name = "chiper", version)]
+struct Cli {
+    /// Log filters, e.g. `chiper::draw=debug`, overrides CHIPER_LOG
+    #[arg(long, global = true, value_name = "FILTERS")]
+    log: Option<String>,
+    /// Config file, by default CHIPER_CONFIG or ~/.config/chiper/config.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<String>,
+    /// Directory with a copy of the chip-8-database, a database of the
+    /// bundled roms is used by default
+    #[arg(long, global = true, value_name = "DIR")]
+    db: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a rom in a window
+    Run(RunArgs),
+    /// Run a rom under the debugger or a debug server
+    Debug(DebugArgs),
+    /// Print disassembly of a rom
+    Disasm {
+        /// Path to the rom
+        rom: String,
+        /// Show bytes used only as data in the coverage file as bytes
+        #[arg(long, value_name = "FILE")]
+        coverage: Option<String>,
+    },
+    /// Print information about a rom
+    Info {
+        /// Path to the rom
+        rom: String,
+    },
+    /// Find code depending on quirks and recommend a platform
+    Analyze {
+        /// Path to the rom
+        rom: String,
+    },
+    /// Print the control-flow graph of a rom as Graphviz DOT
+    Cfg {
+        /// Path to the rom
+        rom: String,
+    },
+    /// Print pseudo-code of a rom
+    Decompile {
+        /// Path to the rom
+        rom: String,
+    },
+    /// Translate the code of a rom to a Rust module
+    Recompile {
+        /// Path to the rom
+        rom: String,
+    },
+    /// Run a rom headless as fast as possible and report the speed
+    Bench {
+        #[command(flatten)]
+        machine: MachineArgs,
+        /// Amount of frames to emulate
+        #[arg(long, default_value_t = 600)]
+        frames: u64,
+        /// Press keys as scripted in the file, see src/input.rs
+        #[arg(long, value_name = "FILE")]
+        input: Option<String>,
+        /// Path to the rom
+        rom: String,
+    },
+    /// Show settings merged from the config file and options
+    Config {
+        /// Print the effective settings as TOML
+        #[arg(long, required = true)]
+        dump: bool,
+        #[command(flatten)]
+        settings: SettingsArgs,
+        #[command(flatten)]
+        screen: ScreenArgs,
+        /// Rom to apply the settings of, only defaults are shown without it
+        rom: Option<String>,
+    },
+    /// Find the first instruction where two traces diverge
+    Tracediff {
+        /// Lines of context shown around the divergence
+        #[arg(long, default_value_t = 5, value_name = "LINES")]
+        context: usize,
+        a: String,
+        b: String,
+    },
+}
+
+/// Emulation settings, which can be set in the config file too
+#[derive(Args)]
+struct SettingsArgs {
+    /// Quirk preset: chip8, schip or xochip
+    #[arg(long)]
+    platform: Option<Platform>,
+    /// Enable or disable a single quirk, e.g. `--quirk wrap` or
+    /// `--quirk shift=off`, applied after the platform preset
+    #[arg(long, value_name = "NAME[=on|off]", value_parser = parse_quirk)]
+    quirk: Vec<(String, bool)>,
+    /// Instructions executed per frame, i.e. 60 times a second
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    ipf: Option<u64>,
+    /// Don't apply settings of the rom found in the rom database
+    #[arg(long)]
+    no_detect: bool,
+}
+
+/// Emulated machine settings
+#[derive(Args)]
+struct MachineArgs {
+    #[command(flatten)]
+    settings: SettingsArgs,
+    /// Seed of the random number generator
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Restore the machine state from the file if it exists, save it on exit
+    #[arg(long, value_name = "FILE")]
+    state: Option<String>,
+    /// Execution engine: interpreter, cached (predecoded basic blocks) or
+    /// jit (needs the jit feature)
+    #[arg(long, default_value = "interpreter")]
+    engine: Engine,
+}
+
+/// Window settings
+#[derive(Args)]
+struct ScreenArgs {
+    /// Size of a CHIP-8 pixel in window pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: Option<u32>,
+    /// Pixel colors, e.g. `#ffcc00,#202020`
+    #[arg(long, value_name = "FG,BG")]
+    palette: Option<Palette>,
+    /// Keyboard keys for the keypad keys 0-F, e.g. `X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V`
+    #[arg(long, value_name = "KEYS")]
+    keymap: Option<Keymap>,
+    /// Display effects: grid, scanlines or none
+    #[arg(long, value_name = "LIST")]
+    effects: Option<Effects>,
+}
+
+/// Recorders of the emulation
+#[derive(Args)]
+struct RecordArgs {
+    /// Write a line per executed instruction
+    #[arg(long, value_name = "FILE")]
+    trace: Option<String>,
+    /// Trace line format, fields are listed in src/trace.rs
+    #[arg(long, value_name = "FORMAT", requires = "trace")]
+    trace_format: Option<Format>,
+    /// Write an execution profile report
+    #[arg(long, value_name = "FILE")]
+    profile: Option<String>,
+    /// Write collapsed call stacks for flamegraph tools
+    #[arg(long, value_name = "FILE")]
+    profile_stacks: Option<String>,
+    /// Accumulate memory coverage in the file
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<String>,
+    /// Write disassembly annotated with coverage
+    #[arg(long, value_name = "FILE", requires = "coverage")]
+    coverage_listing: Option<String>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+    #[command(flatten)]
+    screen: ScreenArgs,
+    #[command(flatten)]
+    record: RecordArgs,
+    /// Print disassembly of the rom before running it
+    #[arg(long)]
+    disasm: bool,
+    /// Run without a window for a given amount of frames, e.g. in CI
+    #[arg(long, requires = "frames")]
+    headless: bool,
+    /// Amount of frames to emulate headless
+    #[arg(long, requires = "headless")]
+    frames: Option<u64>,
+    /// Press keys as scripted in the file, see src/input.rs
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    input: Option<String>,
+    /// Write the final framebuffer as PNG or PBM, by the extension, or as
+    /// ASCII art, which is printed without the option
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    output: Option<String>,
+    /// Fail unless the expression holds at the end, e.g. `V0=0x12` or
+    /// `[300]=ff`, numbers are hex like in the debugger
+    #[arg(long, value_name = "EXPR", requires = "headless")]
+    expect: Vec<Expectation>,
+    /// Path to the rom
+    rom: String,
+}
+
+#[derive(Args)]
+struct DebugArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+    #[command(flatten)]
+    screen: ScreenArgs,
+    #[command(flatten)]
+    record: RecordArgs,
+    /// Serve the GDB remote protocol instead of the interactive debugger
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "dap")]
+    gdb: Option<String>,
+    /// Serve the Debug Adapter Protocol instead of the interactive debugger
+    #[arg(long, value_name = "HOST:PORT")]
+    dap: Option<String>,
+    /// Path to the rom
+    rom: String,
+}
+
+/// Expression checked at the end of a headless run
+#[derive(Clone)]
+struct Expectation {
+    source: String,
+    expr: Expr,
+}
+
+impl FromStr for Expectation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Expectation, String> {
+        let expr: Expr = s.parse()?;
+        // a value the target can't hold would only fail after the run
+        if let Expr::Binary(BinOp::Eq | BinOp::Ne, lhs, rhs) = &expr {
+            for (target, value) in [(lhs, rhs), (rhs, lhs)] {
+                if let (Some(bits), Expr::Num(n)) = (width(target), value.as_ref()) {
+                    if *n >> bits != 0 {
+                        return Err(format!("Value {:x} doesn't fit in {} bits", n, bits));
+                    }
+                }
+            }
+        }
+        Ok(Expectation {
+            source: s.to_string(),
+            expr,
+        })
     }
-    let screen = screen::sdl_init().map_err(|e| Error::new(ErrorKind::Other, e))?;
-    let mut chip8 = Chip8::new(screen);
-    chip8.load_rom(&args[1])?;
-    chip8.dump_memory();
-    if env::var("DEBUGGER").is_err() {
-        chip8.emulate();
-    } else {
-        chip8.debugger()?;
+}
+
+/// Bits of the register or memory byte read by `expr`
+fn width(expr: &Expr) -> Option<u32> {
+    match expr {
+        Expr::Reg(Register::I | Register::Pc | Register::Sp) => Some(16),
+        Expr::Reg(_) | Expr::Mem(_) => Some(8),
+        _ => None,
     }
+}
+
+impl Expectation {
+    /// Describes the failure unless the expression holds
+    fn check<T: Screen>(&self, chip8: &Chip8<T>) -> Result<(), String> {
+        if self.expr.eval(chip8)? != 0 {
+            return Ok(());
+        }
+        match &self.expr {
+            // comparisons show the value they compared
+            Expr::Binary(
+                BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge,
+                lhs,
+                _,
+            ) => Err(format!(
+                "Expected {}, got {:x}",
+                self.source,
+                lhs.eval(chip8)?
+            )),
+            _ => Err(format!("Expected {}", self.source)),
+        }
+    }
+}
+
+fn parse_quirk(s: &str) -> Result<(String, bool), String> {
+    let (name, value) = match s.split_once('=') {
+        Some((name, "on" | "true" | "1")) => (name, true),
+        Some((name, "off" | "false" | "0")) => (name, false),
+        Some((_, value)) => return Err(format!("Invalid quirk value '{}'", value)),
+        None => (s, true),
+    };
+    // report unknown names while parsing arguments
+    Quirks::default().set(name, value)?;
+    Ok((name.to_string(), value))
+}
+
+/// Adds the file name to errors like "No such file or directory"
+fn with_path(e: io::Error, path: &str) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path, e))
+}
+
+/// Sets up logging, `filters` given on the command line take precedence
+/// over the environment
+fn init_logger(filters: Option<&str>) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(log::LevelFilter::Warn);
+    if let Ok(env_filters) = env::var(LOG_ENV) {
+        builder.parse_filters(&env_filters);
+    }
+    if let Some(filters) = filters {
+        builder.parse_filters(filters);
+    }
+    builder.init();
+}
+
+/// Files given by the global options
+struct Files<'a> {
+    config: Option<&'a str>,
+    db: Option<&'a str>,
+}
+
+impl Files<'_> {
+    /// Loads the config file or the default one if it exists
+    fn config(&self) -> io::Result<(Option<PathBuf>, Config)> {
+        let path = match self.config {
+            Some(path) => PathBuf::from(path),
+            None => match Config::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok((None, Config::default())),
+            },
+        };
+        let config = Config::load(&path).map_err(|e| with_path(e, &path.to_string_lossy()))?;
+        Ok((Some(path), config))
+    }
+
+    fn detect(&self, rom: &[u8]) -> io::Result<Option<Detection>> {
+        let db = match self.db {
+            Some(dir) => Database::load(Path::new(dir)).map_err(|e| with_path(e, dir))?,
+            None => Database::bundled(),
+        };
+        Ok(db.detect(rom))
+    }
+
+    /// Settings of the rom from the database and the config file overridden
+    /// by `layers`
+    fn rom_settings(
+        &self,
+        rom: &str,
+        args: &SettingsArgs,
+        layers: &[Settings],
+    ) -> io::Result<Settings> {
+        let (_, config) = self.config()?;
+        let bytes = fs::read(rom).map_err(|e| with_path(e, rom))?;
+        let detection = match args.no_detect {
+            false => self.detect(&bytes)?,
+            true => None,
+        };
+        if let Some(detection) = &detection {
+            // stdout is left to the output of the command
+            eprintln!("Detected {}, --no-detect to ignore", detection);
+        }
+        let detected = detection.as_ref().map(Detection::settings);
+        let mut settings = config.settings(rom, &bytes, detected.as_ref());
+        settings.merge(&args.settings());
+        for layer in layers {
+            settings.merge(layer);
+        }
+        Ok(settings)
+    }
+}
+
+impl SettingsArgs {
+    fn settings(&self) -> Settings {
+        Settings {
+            platform: self.platform,
+            ipf: self.ipf,
+            quirks: self.quirk.iter().cloned().collect(),
+            ..Settings::default()
+        }
+    }
+}
+
+impl MachineArgs {
+    /// Creates the machine and loads the rom, or the saved state if there
+    /// is one
+    fn load<T: Screen>(&self, screen: T, rom: &str, settings: &Settings) -> io::Result<Chip8<T>> {
+        let mut chip8 = Chip8::new(screen);
+        chip8.set_engine(self.engine);
+        if let Some(seed) = self.seed {
+            chip8.set_seed(seed);
+        }
+        chip8.load_rom(rom).map_err(|e| with_path(e, rom))?;
+        // settings include the detected ones unless they are overridden
+        chip8.set_quirks(settings.quirks().map_err(Error::other)?);
+        chip8.set_ticks_per_frame(settings.ticks_per_frame());
+        if let Some(path) = self.state.as_deref().filter(|p| Path::new(p).exists()) {
+            chip8.load_state(path).map_err(|e| with_path(e, path))?;
+        }
+        Ok(chip8)
+    }
+
+    /// Loads the machine without a window, seeded with `HEADLESS_SEED`
+    /// unless the seed is given or restored
+    fn load_headless(&self, rom: &str, settings: &Settings) -> io::Result<Chip8<NoScreen>> {
+        let mut chip8 = self.load(NoScreen {}, rom, settings)?;
+        if self.seed.is_none() && self.state.is_none() {
+            chip8.set_seed(HEADLESS_SEED);
+        }
+        Ok(chip8)
+    }
+
+    fn save<T: Screen>(&self, chip8: &Chip8<T>) -> io::Result<()> {
+        match &self.state {
+            Some(path) => chip8.save_state(path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ScreenArgs {
+    fn settings(&self) -> Settings {
+        Settings {
+            scale: self.scale,
+            palette: self.palette,
+            keymap: self.keymap,
+            effects: self.effects,
+            ..Settings::default()
+        }
+    }
+}
+
+impl RecordArgs {
+    fn start<T: Screen>(&self, chip8: &mut Chip8<T>) -> io::Result<()> {
+        if let Some(path) = &self.trace {
+            let out = BufWriter::new(File::create(path)?);
+            let format = self.trace_format.clone().unwrap_or_default();
+            chip8.set_tracer(Tracer::new(Box::new(out), format));
+        }
+        if self.profile.is_some() || self.profile_stacks.is_some() {
+            chip8.set_profiler(Profiler::new());
+        }
+        if let Some(path) = &self.coverage {
+            // sessions are accumulated in the same file
+            chip8.set_coverage(Coverage::load(path)?);
+        }
+        Ok(())
+    }
+
+    fn finish<T: Screen>(&self, chip8: &mut Chip8<T>) -> io::Result<()> {
+        chip8.flush_trace()?;
+        if let Some(profiler) = chip8.take_profiler() {
+            if let Some(path) = &self.profile {
+                profiler.write_report(chip8, &mut BufWriter::new(File::create(path)?))?;
+            }
+            if let Some(path) = &self.profile_stacks {
+                profiler.write_stacks(&mut BufWriter::new(File::create(path)?))?;
+            }
+        }
+        if let (Some(coverage), Some(path)) = (chip8.take_coverage(), &self.coverage) {
+            coverage.save(path)?;
+            eprint!("{}", coverage.summary(chip8.rom_size()));
+            if let Some(path) = &self.coverage_listing {
+                fs::write(path, coverage.listing(chip8))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Script of the file or none pressing no keys
+fn load_script(path: Option<&str>) -> io::Result<Script> {
+    match path {
+        Some(path) => Script::load(path).map_err(|e| with_path(e, path)),
+        None => Ok(Script::default()),
+    }
+}
+
+/// Writes PNG or PBM by the extension of `path`, ASCII art otherwise
+fn write_framebuffer(path: &str, pixels: &Lores) -> io::Result<()> {
+    let image = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("png") => pixels.to_png()?,
+        Some("pbm") => pixels.to_pbm(),
+        _ => pixels.to_ascii().into_bytes(),
+    };
+    fs::write(path, image).map_err(|e| with_path(e, path))
+}
+
+fn run_headless(files: &Files, args: &RunArgs) -> io::Result<()> {
+    let settings = files.rom_settings(&args.rom, &args.machine.settings, &[])?;
+    let script = load_script(args.input.as_deref())?;
+    let mut chip8 = args.machine.load_headless(&args.rom, &settings)?;
+    if args.disasm {
+        chip8.dump_memory();
+    }
+    args.record.start(&mut chip8)?;
+    script
+        .run(&mut chip8, args.frames.unwrap_or_default())
+        .map_err(Error::other)?;
+    args.record.finish(&mut chip8)?;
+    args.machine.save(&chip8)?;
+
+    let pixels = Lores::from_bytes(chip8.framebuffer());
+    match &args.output {
+        Some(path) => write_framebuffer(path, &pixels)?,
+        None => print!("{}", pixels.to_ascii()),
+    }
+    let failures: Vec<String> = args
+        .expect
+        .iter()
+        .filter_map(|expectation| expectation.check(&chip8).err())
+        .collect();
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    match failures.len() {
+        0 => Ok(()),
+        n => Err(Error::other(format!(
+            "{} of {} expectations failed",
+            n,
+            args.expect.len()
+        ))),
+    }
+}
+
+fn run(files: &Files, args: &RunArgs) -> io::Result<()> {
+    if args.headless {
+        return run_headless(files, args);
+    }
+    let settings =
+        files.rom_settings(&args.rom, &args.machine.settings, &[args.screen.settings()])?;
+    let options = settings.screen_options().map_err(Error::other)?;
+    let screen = screen::sdl_init(options).map_err(Error::other)?;
+    let mut chip8 = args.machine.load(screen, &args.rom, &settings)?;
+    if args.disasm {
+        chip8.dump_memory();
+    }
+    args.record.start(&mut chip8)?;
+    chip8.emulate().map_err(Error::other)?;
+    args.record.finish(&mut chip8)?;
+    args.machine.save(&chip8)
+}
+
+fn debug(files: &Files, args: &DebugArgs) -> io::Result<()> {
+    let settings =
+        files.rom_settings(&args.rom, &args.machine.settings, &[args.screen.settings()])?;
+    let options = settings.screen_options().map_err(Error::other)?;
+    let screen = screen::sdl_init(options).map_err(Error::other)?;
+    let mut chip8 = args.machine.load(screen, &args.rom, &settings)?;
+    args.record.start(&mut chip8)?;
+    match (&args.gdb, &args.dap) {
+        (Some(addr), _) => gdb::serve(&mut chip8, addr)?,
+        (_, Some(addr)) => dap::serve(&mut chip8, addr)?,
+        _ => chip8.debugger()?,
+    }
+    args.record.finish(&mut chip8)?;
+    args.machine.save(&chip8)
+}
+
+fn disasm(rom: &str, coverage: Option<&str>) -> io::Result<()> {
+    let mut chip8 = Chip8::new(NoScreen {});
+    chip8.load_rom(rom).map_err(|e| with_path(e, rom))?;
+    match coverage {
+        Some(path) => {
+            // unlike --coverage of run, a missing file is an error here
+            let text = fs::read_to_string(path).map_err(|e| with_path(e, path))?;
+            let coverage: Coverage = text.parse().map_err(Error::other)?;
+            print!("{}", coverage.listing(&chip8));
+        }
+        None => chip8.dump_memory(),
+    }
+    Ok(())
+}
+
+fn info(files: &Files, rom: &str) -> io::Result<()> {
+    let bytes = fs::read(rom).map_err(|e| with_path(e, rom))?;
+    println!("File:         {}", rom);
+    println!("Size:         {} bytes", bytes.len());
+    println!("SHA-1:        {}", config::sha1_hex(&bytes));
+    match files.detect(&bytes)? {
+        Some(detection) => {
+            println!("Title:        {}", detection.title);
+            println!(
+                "Platform:     {} ({})",
+                detection.platform_name, detection.platform
+            );
+            println!("Quirks:       {}", detection.quirks);
+            if let Some(tickrate) = detection.tickrate {
+                println!("Tickrate:     {} ipf", tickrate);
+            }
+        }
+        None => println!("Title:        unknown, not found in the rom database"),
+    }
+    // every aligned word is decoded, so data is counted as well
+    let mut classes = BTreeMap::new();
+    let mut unknown = 0;
+    for word in bytes.chunks_exact(2) {
+        match Instruction::from_bytes(word[0], word[1]) {
+            Ok(ins) => *classes.entry(ins.pattern()).or_insert(0) += 1,
+            Err(_) => unknown += 1,
+        }
+    }
+    println!(
+        "Words:        {} decodable, {} unknown",
+        classes.values().sum::<usize>(),
+        unknown
+    );
+    let classes: Vec<String> = classes
+        .iter()
+        .map(|(class, count)| format!("{} x{}", class, count))
+        .collect();
+    println!("Opcodes:      {}", classes.join(", "));
+    Ok(())
+}
+
+fn analyze(files: &Files, rom: &str) -> io::Result<()> {
+    let bytes = fs::read(rom).map_err(|e| with_path(e, rom))?;
+    if let Some(detection) = files.detect(&bytes)? {
+        println!("Detected {}, the rom database has its settings", detection);
+    }
+    print!("{}", analysis::analyze(&Cfg::new(&bytes)));
     Ok(())
 }
+
+fn cfg(rom: &str) -> io::Result<()> {
+    let bytes = fs::read(rom).map_err(|e| with_path(e, rom))?;
+    print!("{}", Cfg::new(&bytes).dot());
+    Ok(())
+}
+
+fn recompile(rom: &str) -> io::Result<()> {
+    let bytes = fs::read(rom).map_err(|e| with_path(e, rom))?;
+    let name = Path::new(rom)
+        .file_name()
+        .map_or(rom.into(), |name| name.to_string_lossy());
+    print!("{}", recompile::recompile(&Cfg::new(&bytes), &name));
+    Ok(())
+}
+
+fn bench(
+    files: &Files,
+    machine: &MachineArgs,
+    frames: u64,
+    input: Option<&str>,
+    rom: &str,
+) -> io::Result<()> {
+    let settings = files.rom_settings(rom, &machine.settings, &[])?;
+    let script = load_script(input)?;
+    let mut chip8 = machine.load_headless(rom, &settings)?;
+    chip8.set_draw_timing(DrawTiming::default());
+    let (start_cycles, start_frames) = (chip8.cycles(), chip8.frames());
+    let start = Instant::now();
+    script.run(&mut chip8, frames).map_err(Error::other)?;
+    let elapsed = start.elapsed().as_secs_f64();
+    let instructions = chip8.cycles() - start_cycles;
+    let frames = chip8.frames() - start_frames;
+    println!(
+        "{} instructions in {} frames, {:.3}s",
+        instructions, frames, elapsed
+    );
+    println!(
+        "{:.0} instructions/s, {:.0} frames/s",
+        instructions as f64 / elapsed,
+        frames as f64 / elapsed
+    );
+    let timing = chip8.take_draw_timing().unwrap_or_default();
+    if timing.draws > 0 {
+        println!(
+            "{} draws, {:.0}ns per draw",
+            timing.draws,
+            timing.time.as_nanos() as f64 / timing.draws as f64
+        );
+    }
+    if chip8.is_halted() {
+        println!("Program halted at {:04x}", chip8.pc());
+    }
+    Ok(())
+}
+
+fn dump_config(
+    files: &Files,
+    settings: &SettingsArgs,
+    screen: &ScreenArgs,
+    rom: Option<&str>,
+) -> io::Result<()> {
+    let (path, config) = files.config()?;
+    let mut effective = match rom {
+        Some(rom) => {
+            let bytes = fs::read(rom).map_err(|e| with_path(e, rom))?;
+            println!("# rom: {} (sha1 {})", rom, config::sha1_hex(&bytes));
+            let detection = match settings.no_detect {
+                false => files.detect(&bytes)?,
+                true => None,
+            };
+            if let Some(detection) = &detection {
+                println!("# detected: {}", detection);
+            }
+            let detected = detection.as_ref().map(Detection::settings);
+            config.settings(rom, &bytes, detected.as_ref())
+        }
+        None => config.defaults.clone(),
+    };
+    match path {
+        Some(path) => println!("# config: {}", path.display()),
+        None => println!("# config: none"),
+    }
+    effective.merge(&settings.settings());
+    effective.merge(&screen.settings());
+    print!("{}", effective.effective().map_err(Error::other)?);
+    Ok(())
+}
+
+fn tracediff(context: usize, a: &str, b: &str) -> io::Result<()> {
+    match trace::diff(&fs::read_to_string(a)?, &fs::read_to_string(b)?, context) {
+        Some(divergence) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        None => println!("Traces are identical"),
+    }
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    init_logger(cli.log.as_deref());
+    let files = Files {
+        config: cli.config.as_deref(),
+        db: cli.db.as_deref(),
+    };
+    let result = match &cli.command {
+        Command::Run(args) => run(&files, args),
+        Command::Debug(args) => debug(&files, args),
+        Command::Disasm { rom, coverage } => disasm(rom, coverage.as_deref()),
+        Command::Info { rom } => info(&files, rom),
+        Command::Analyze { rom } => analyze(&files, rom),
+        Command::Cfg { rom } => cfg(rom),
+        Command::Decompile { rom } => fs::read(rom)
+            .map(|bytes| print!("{}", decompile::decompile(&Cfg::new(&bytes))))
+            .map_err(|e| with_path(e, rom)),
+        Command::Recompile { rom } => recompile(rom),
+        Command::Bench {
+            machine,
+            frames,
+            input,
+            rom,
+        } => bench(&files, machine, *frames, input.as_deref(), rom),
+        Command::Config {
+            settings,
+            screen,
+            rom,
+            ..
+        } => dump_config(&files, settings, screen, rom.as_deref()),
+        Command::Tracediff { context, a, b } => tracediff(*context, a, b),
+    };
+    if let Err(e) = result {
+        eprintln!("chiper: {}", e);
+        process::exit(1);
+    }
+}
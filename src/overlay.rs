@@ -0,0 +1,141 @@
+//! Contents of the debug panes shown next to the game screen
+//!
+//! The overlay is rebuilt from the machine state every time it's drawn, so
+//! the frontend only has to lay out the text.
+
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::debugger::backtrace;
+use crate::screen::Screen;
+
+/// Instructions shown before and after the current one
+const DISASSEMBLY_CONTEXT: usize = 10;
+const MEMORY_ROWS: usize = 8;
+const MEMORY_ROW_SIZE: usize = 8;
+const STACK_DEPTH: usize = 8;
+
+/// Keys in the order they are laid out on the COSMAC VIP keypad
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+pub struct DisassemblyLine {
+    pub addr: usize,
+    pub text: String,
+    pub breakpoint: bool,
+}
+
+pub struct Overlay {
+    /// Instructions around pc
+    pub disassembly: Vec<DisassemblyLine>,
+    pub pc: usize,
+    pub registers: Vec<String>,
+    /// Memory starting at the row containing I
+    pub memory: Vec<String>,
+    /// Return addresses, innermost call first
+    pub stack: Vec<String>,
+    pub keys: [bool; 16],
+}
+
+impl Overlay {
+    pub fn new<T: Screen>(chip8: &Chip8<T>, breakpoints: &[usize]) -> Overlay {
+        let first = chip8.pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+        let disassembly = (first..MEMORY_SIZE - 1)
+            .step_by(2)
+            .take(DISASSEMBLY_CONTEXT * 2 + 1)
+            .map(|addr| DisassemblyLine {
+                addr,
+                // squeeze tabs used to align the terminal output
+                text: chip8
+                    .disassembly_line(addr)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                breakpoint: breakpoints.contains(&addr),
+            })
+            .collect();
+
+        let mut registers: Vec<String> = chip8
+            .v
+            .chunks(4)
+            .enumerate()
+            .map(|(row, regs)| {
+                let regs: Vec<String> = regs
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, val)| format!("V{:X} {:02x}", row * 4 + idx, val))
+                    .collect();
+                regs.join("  ")
+            })
+            .collect();
+        registers.push(format!(
+            "I  {:03x}  SP {:03x}  PC {:03x}",
+            chip8.i, chip8.sp, chip8.pc
+        ));
+        registers.push(format!("DT {:02x}   ST {:02x}", chip8.dt, chip8.st));
+
+        let start = (chip8.i as usize / MEMORY_ROW_SIZE * MEMORY_ROW_SIZE)
+            .min(MEMORY_SIZE - MEMORY_ROWS * MEMORY_ROW_SIZE);
+        let memory = chip8.memory[start..start + MEMORY_ROWS * MEMORY_ROW_SIZE]
+            .chunks(MEMORY_ROW_SIZE)
+            .enumerate()
+            .map(|(row, bytes)| {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("{:04x}: {}", start + row * MEMORY_ROW_SIZE, bytes.join(" "))
+            })
+            .collect();
+
+        let stack = backtrace(chip8)
+            .into_iter()
+            .take(STACK_DEPTH)
+            .enumerate()
+            .map(|(idx, ret)| format!("#{} {:04x}", idx, ret))
+            .collect();
+
+        Overlay {
+            disassembly,
+            pc: chip8.pc,
+            registers,
+            memory,
+            stack,
+            keys: chip8.keys,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+
+    #[test]
+    fn test_overlay() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[
+            0xa2, 0x08, // 200: mov I, 208
+            0x22, 0x06, // 202: call 206
+            0x12, 0x04, // 204: jmp 204
+            0x60, 0x12, // 206: mov V0, 12
+        ]);
+        for _ in 0..3 {
+            chip8.emulate_op().unwrap();
+        }
+        chip8.keys[0xf] = true;
+
+        let overlay = Overlay::new(&chip8, &[0x204]);
+        let lines: Vec<_> = overlay.disassembly.iter().map(|l| l.addr).collect();
+        assert_eq!(lines[0], 0x1f4);
+        assert_eq!(lines[DISASSEMBLY_CONTEXT], 0x208);
+        let bp = &overlay.disassembly[DISASSEMBLY_CONTEXT - 2];
+        assert_eq!(bp.text, "0204: 12 04 jmp 204");
+        assert!(bp.breakpoint);
+
+        assert_eq!(overlay.registers[0], "V0 12  V1 00  V2 00  V3 00");
+        assert_eq!(overlay.registers[4], "I  208  SP efe  PC 208");
+        assert_eq!(overlay.memory[0], "0208: 00 00 00 00 00 00 00 00");
+        assert_eq!(overlay.stack, vec!["#0 0204"]);
+        assert!(overlay.keys[0xf]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Platform presets and quirks
+//!
+//! Interpreters disagree on the behaviour of a few instructions, every such
+//! difference is a quirk. Quirks are named after the ones of the community
+//! chip-8-database, a platform preset enables the quirks of the interpreter
+//! most roms for that platform were written for.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VX in place instead of shifting VY into VX
+    pub shift: bool,
+    /// FX55 and FX65 increment I by X instead of X + 1
+    pub memory_increment_by_x: bool,
+    /// FX55 and FX65 leave I unchanged
+    pub memory_leave_i_unchanged: bool,
+    /// Sprites wrap around the screen edges instead of being clipped
+    pub wrap: bool,
+    /// BXNN jumps to XNN + VX instead of BNNN jumping to NNN + V0
+    pub jump: bool,
+    /// DXYN waits for the start of a frame
+    pub vblank: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF
+    pub logic: bool,
+}
+
+/// Names accepted by `Quirks::set`
+pub const QUIRK_NAMES: [&str; 7] = [
+    "shift",
+    "memoryIncrementByX",
+    "memoryLeaveIUnchanged",
+    "wrap",
+    "jump",
+    "vblank",
+    "logic",
+];
+
+impl Default for Quirks {
+    /// Behaviour of chiper before quirks were configurable
+    fn default() -> Self {
+        Quirks {
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+        }
+    }
+}
+
+impl Quirks {
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "shift" => &mut self.shift,
+            "memoryIncrementByX" => &mut self.memory_increment_by_x,
+            "memoryLeaveIUnchanged" => &mut self.memory_leave_i_unchanged,
+            "wrap" => &mut self.wrap,
+            "jump" => &mut self.jump,
+            "vblank" => &mut self.vblank,
+            "logic" => &mut self.logic,
+            _ => return None,
+        })
+    }
+
+    /// Enables or disables the quirk called `name`
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        let flag = self.flag(name).ok_or_else(|| {
+            format!(
+                "Unknown quirk '{}', expected one of: {}",
+                name,
+                QUIRK_NAMES.join(", ")
+            )
+        })?;
+        *flag = value;
+        Ok(())
+    }
+
+    /// Quirks in the order of `QUIRK_NAMES`
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        let mut quirks = *self;
+        QUIRK_NAMES
+            .iter()
+            .map(move |&name| (name, *quirks.flag(name).unwrap()))
+    }
+}
+
+impl fmt::Display for Quirks {
+    /// Names of the enabled quirks
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let enabled: Vec<&str> = self.iter().filter(|q| q.1).map(|q| q.0).collect();
+        if enabled.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", enabled.join(", "))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// Original interpreter of the COSMAC VIP
+    Chip8,
+    /// SUPER-CHIP 1.1 as it's emulated by modern interpreters
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: false,
+                jump: false,
+                vblank: true,
+                logic: true,
+            },
+            Platform::SuperChip => Quirks {
+                shift: true,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: true,
+                wrap: false,
+                jump: true,
+                vblank: false,
+                logic: false,
+            },
+            Platform::XoChip => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: true,
+                jump: false,
+                vblank: false,
+                logic: false,
+            },
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Platform, String> {
+        match s {
+            "chip8" | "vip" => Ok(Platform::Chip8),
+            "schip" | "superchip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Unknown platform '{}', expected chip8, schip or xochip",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quirks() {
+        let mut quirks: Quirks = "chip8".parse::<Platform>().unwrap().quirks();
+        assert_eq!(quirks.to_string(), "vblank, logic");
+        quirks.set("wrap", true).unwrap();
+        quirks.set("vblank", false).unwrap();
+        assert_eq!(quirks.to_string(), "wrap, logic");
+        assert!(quirks.set("wrapp", true).is_err());
+        assert!("schip8".parse::<Platform>().is_err());
+        assert_eq!(quirks.iter().count(), QUIRK_NAMES.len());
+    }
+}
@@ -0,0 +1,373 @@
+//! Execution profiler
+//!
+//! Counts executed instructions per address and per opcode class, calls of
+//! every subroutine and draws per frame. Subroutine costs come from a call
+//! tree: exclusive cost is the amount of instructions executed in the
+//! subroutine itself, inclusive one also counts everything it calls.
+//! The call tree is also written as collapsed stacks, one line per stack
+//! with its exclusive cost, which is the input format of flamegraph tools:
+//!
+//! ```text
+//! main;sub_0300;sub_0320 1234
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use crate::chip8::{Chip8, MEMORY_SIZE, MEMORY_START};
+use crate::instruction::Instruction;
+use crate::screen::Screen;
+
+/// Call tree node, one for every distinct call stack
+struct Node {
+    parent: usize,
+    /// Subroutine address, ignored for the root
+    addr: u16,
+    /// Instructions executed with exactly this stack
+    count: u64,
+}
+
+const ROOT: usize = 0;
+
+pub struct Profiler {
+    /// Executions per address
+    addr_counts: Vec<u64>,
+    /// Executions per instruction word, grouped into classes by the report
+    opcode_counts: Vec<u64>,
+    /// Nodes are only appended, so parent always precedes its children
+    nodes: Vec<Node>,
+    children: HashMap<(usize, u16), usize>,
+    current: usize,
+    calls: BTreeMap<u16, u64>,
+    /// Draws made in the current frame
+    draws: u32,
+    /// Amount of frames per number of draws made in them
+    draws_per_frame: BTreeMap<u32, u64>,
+    total: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            addr_counts: vec![0; MEMORY_SIZE],
+            opcode_counts: vec![0; 0x10000],
+            nodes: vec![Node {
+                parent: ROOT,
+                addr: 0,
+                count: 0,
+            }],
+            children: HashMap::new(),
+            current: ROOT,
+            calls: BTreeMap::new(),
+            draws: 0,
+            draws_per_frame: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Accounts `instruction` executed at `pc`, `frame_end` is set when it
+    /// was the last instruction of a frame
+    pub fn record(&mut self, pc: usize, instruction: Instruction, frame_end: bool) {
+        self.total += 1;
+        self.addr_counts[pc] += 1;
+        self.opcode_counts[instruction.encode() as usize] += 1;
+        self.nodes[self.current].count += 1;
+        match instruction {
+            Instruction::Call(addr) => {
+                *self.calls.entry(addr).or_insert(0) += 1;
+                let next = self.nodes.len();
+                let parent = self.current;
+                self.current = *self.children.entry((parent, addr)).or_insert(next);
+                if self.current == next {
+                    self.nodes.push(Node {
+                        parent,
+                        addr,
+                        count: 0,
+                    });
+                }
+            }
+            // return without a call, e.g. the stack was set up by hand
+            Instruction::Return if self.current != ROOT => {
+                self.current = self.nodes[self.current].parent
+            }
+            Instruction::Draw(..) => self.draws += 1,
+            _ => {}
+        }
+        if frame_end {
+            *self.draws_per_frame.entry(self.draws).or_insert(0) += 1;
+            self.draws = 0;
+        }
+    }
+
+    /// Amount of profiled instructions
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Executions of the instruction at `addr`
+    pub fn count(&self, addr: usize) -> u64 {
+        self.addr_counts[addr]
+    }
+
+    /// Executions per opcode class, e.g. "DXYN"
+    pub fn opcode_classes(&self) -> BTreeMap<&'static str, u64> {
+        let mut classes = BTreeMap::new();
+        for (word, &count) in self.opcode_counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            if let Ok(ins) = Instruction::decode(word as u16) {
+                *classes.entry(ins.pattern()).or_insert(0) += count;
+            }
+        }
+        classes
+    }
+
+    /// Calls, inclusive and exclusive cost of every called subroutine
+    pub fn subroutines(&self) -> BTreeMap<u16, (u64, u64, u64)> {
+        // subtree totals, children always follow their parents
+        let mut totals: Vec<u64> = self.nodes.iter().map(|n| n.count).collect();
+        for idx in (1..self.nodes.len()).rev() {
+            totals[self.nodes[idx].parent] += totals[idx];
+        }
+        let mut subs: BTreeMap<u16, (u64, u64, u64)> = self
+            .calls
+            .iter()
+            .map(|(&addr, &calls)| (addr, (calls, 0, 0)))
+            .collect();
+        for (idx, node) in self.nodes.iter().enumerate().skip(1) {
+            let sub = subs.entry(node.addr).or_insert((0, 0, 0));
+            sub.2 += node.count;
+            // recursive calls are already included in the outer one
+            if !self.ancestors(idx).any(|a| self.nodes[a].addr == node.addr) {
+                sub.1 += totals[idx];
+            }
+        }
+        subs
+    }
+
+    /// Proper ancestors of the node, the root excluded
+    fn ancestors(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut idx = idx;
+        std::iter::from_fn(move || {
+            idx = self.nodes[idx].parent;
+            (idx != ROOT).then_some(idx)
+        })
+    }
+
+    fn stack_name(&self, idx: usize) -> String {
+        let mut frames: Vec<String> = (idx != ROOT)
+            .then_some(idx)
+            .into_iter()
+            .chain(self.ancestors(idx))
+            .map(|a| format!("sub_{:04x}", self.nodes[a].addr))
+            .collect();
+        frames.push("main".to_string());
+        frames.reverse();
+        frames.join(";")
+    }
+
+    /// Writes collapsed stacks for flamegraph tools
+    pub fn write_stacks(&self, out: &mut dyn Write) -> io::Result<()> {
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.count > 0 {
+                writeln!(out, "{} {}", self.stack_name(idx), node.count)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    /// Writes summary tables and disassembly annotated with execution counts
+    pub fn write_report<T: Screen>(&self, chip8: &Chip8<T>, out: &mut dyn Write) -> io::Result<()> {
+        let frames: u64 = self.draws_per_frame.values().sum();
+        writeln!(
+            out,
+            "Profile of {} instructions in {} frames",
+            self.total, frames
+        )?;
+
+        writeln!(out, "\nOpcode classes:")?;
+        let mut classes: Vec<_> = self.opcode_classes().into_iter().collect();
+        classes.sort_by_key(|&(_, count)| Reverse(count));
+        for (class, count) in classes {
+            writeln!(
+                out,
+                "  {}  {:>12}  {:6.2}%",
+                class,
+                count,
+                self.percent(count)
+            )?;
+        }
+
+        writeln!(out, "\nSubroutines:")?;
+        writeln!(
+            out,
+            "  addr  {:>10}  {:>20}  {:>20}",
+            "calls", "inclusive", "exclusive"
+        )?;
+        let subs = self.subroutines();
+        for (addr, (calls, inclusive, exclusive)) in &subs {
+            writeln!(
+                out,
+                "  {:04x}  {:>10}  {:>12} {:6.2}%  {:>12} {:6.2}%",
+                addr,
+                calls,
+                inclusive,
+                self.percent(*inclusive),
+                exclusive,
+                self.percent(*exclusive)
+            )?;
+        }
+
+        writeln!(out, "\nDraws per frame:")?;
+        let draws: u64 = self
+            .draws_per_frame
+            .iter()
+            .map(|(&d, &f)| d as u64 * f)
+            .sum();
+        for (draws, count) in &self.draws_per_frame {
+            writeln!(out, "  {:>5} draws  {:>10} frames", draws, count)?;
+        }
+        if frames > 0 {
+            writeln!(out, "  average {:.2}", draws as f64 / frames as f64)?;
+        }
+
+        writeln!(out, "\nAnnotated disassembly:")?;
+        let rom = (MEMORY_START..chip8.rom_end()).step_by(2);
+        // code executed outside of the rom or at odd addresses is shown too
+        let mut addrs: Vec<usize> = rom
+            .chain((0..MEMORY_SIZE).filter(|&a| self.addr_counts[a] > 0))
+            .collect();
+        addrs.sort_unstable();
+        addrs.dedup();
+        let mut last = None;
+        for addr in addrs {
+            if last.is_some_and(|l| l + 2 < addr) {
+                writeln!(out, "  ...")?;
+            }
+            last = Some(addr);
+            if let Some((calls, inclusive, exclusive)) = subs.get(&(addr as u16)) {
+                writeln!(
+                    out,
+                    "sub_{:04x}: calls {}, inclusive {}, exclusive {}",
+                    addr, calls, inclusive, exclusive
+                )?;
+            }
+            let count = self.addr_counts[addr];
+            if count == 0 {
+                writeln!(out, "{:>12}          {}", "", chip8.disassembly_line(addr))?;
+            } else {
+                writeln!(
+                    out,
+                    "{:>12} {:6.2}%  {}",
+                    count,
+                    self.percent(count),
+                    chip8.disassembly_line(addr)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::TICKS_PER_FRAME;
+    use crate::screen::NoScreen;
+
+    fn profile(prog: &[u8], cycles: u64) -> (Chip8<NoScreen>, Profiler) {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(prog);
+        chip8.set_profiler(Profiler::new());
+        for _ in 0..cycles {
+            chip8.emulate_op().unwrap();
+        }
+        let profiler = chip8.take_profiler().unwrap();
+        (chip8, profiler)
+    }
+
+    const PROG: &[u8] = &[
+        0x22, 0x06, // 200: call 206
+        0xd0, 0x01, // 202: draw V0, V0, 1
+        0x12, 0x00, // 204: jmp 200
+        0x22, 0x0c, // 206: call 20c
+        0x70, 0x01, // 208: add V0, 01
+        0x00, 0xee, // 20a: ret
+        0x71, 0x01, // 20c: add V1, 01
+        0x00, 0xee, // 20e: ret
+    ];
+
+    #[test]
+    fn test_counts() {
+        // 8 instructions per loop iteration
+        let (_, profiler) = profile(PROG, 80);
+        assert_eq!(profiler.total(), 80);
+        assert_eq!(profiler.count(0x200), 10);
+        assert_eq!(profiler.count(0x20c), 10);
+        let classes = profiler.opcode_classes();
+        assert_eq!(classes["2NNN"], 20);
+        assert_eq!(classes["00EE"], 20);
+        assert_eq!(classes["DXYN"], 10);
+
+        let subs = profiler.subroutines();
+        // calls, inclusive, exclusive
+        assert_eq!(subs[&0x206], (10, 50, 30));
+        assert_eq!(subs[&0x20c], (10, 20, 20));
+
+        // every frame has at least one of 10 draws
+        assert_eq!(TICKS_PER_FRAME, 10);
+        let frames: u64 = profiler.draws_per_frame.values().sum();
+        assert_eq!(frames, 8);
+        assert_eq!(profiler.draws_per_frame.get(&0), None);
+    }
+
+    #[test]
+    fn test_recursion() {
+        let (_, profiler) = profile(
+            &[
+                0x22, 0x04, // 200: call 204
+                0x12, 0x02, // 202: jmp 202
+                0x70, 0x01, // 204: add V0, 01
+                0x30, 0x03, // 206: skipifeq V0, 03
+                0x22, 0x04, // 208: call 204
+                0x00, 0xee, // 20a: ret
+            ],
+            13,
+        );
+        // three nested calls, the outermost one includes the other two
+        assert_eq!(profiler.subroutines()[&0x204], (3, 11, 11));
+    }
+
+    #[test]
+    fn test_reports() {
+        let (chip8, profiler) = profile(PROG, 9);
+        let mut stacks = Vec::new();
+        profiler.write_stacks(&mut stacks).unwrap();
+        assert_eq!(
+            String::from_utf8(stacks).unwrap(),
+            "main 4\nmain;sub_0206 3\nmain;sub_0206;sub_020c 2\n"
+        );
+
+        let mut report = Vec::new();
+        profiler.write_report(&chip8, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("sub_020c: calls 1, inclusive 2, exclusive 2\n"));
+        assert!(report.contains("           1  11.11%  0202:\td0 01\tdraw\t\tV0, V0, 1\n"));
+    }
+}
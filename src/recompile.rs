@@ -0,0 +1,363 @@
+//! Static recompiler
+//!
+//! Translates the blocks of the control-flow graph to a Rust module with a
+//! function per block. The functions work on the machine through the
+//! framebuffer, timer and keypad methods of `Chip8`, quirk dependent
+//! instructions go through `Chip8::execute`. A block returns at the end of
+//! every frame and wherever the interpreter would repeat an instruction, i.e.
+//! draws waiting for the vertical blank and FX0A waiting for a key, so the
+//! caller sees exactly the machine states the interpreter goes through.
+//!
+//! Code the graph can't resolve, reached only through BNNN, and blocks the
+//! rom overwrites at a known I are left to the interpreter. Every other block
+//! checks its code is unchanged before it runs, self-modifying code at a
+//! computed address falls back to the interpreter too.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::cfg::{Block, Cfg};
+use crate::chip8::MEMORY_START;
+use crate::instruction::Instruction;
+
+const INDENT: &str = "    ";
+
+/// Generates the module running the code of the rom `name`
+pub fn recompile(cfg: &Cfg, name: &str) -> String {
+    let overwritten = overwritten(cfg);
+    let blocks: Vec<&Block> = cfg
+        .blocks()
+        .filter(|b| b.end <= cfg.rom_end() && !overwritten.contains(&b.start))
+        .filter(|b| supported(cfg, b.start))
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "//! {} recompiled by `chiper recompile`", name).unwrap();
+    out.push_str("//!\n");
+    out.push_str("//! Pass `run` to `Chip8::run_compiled`, code without a block and blocks\n");
+    out.push_str("//! overwritten since run on the interpreter.\n");
+    if !overwritten.is_empty() {
+        let starts: Vec<String> = overwritten.iter().map(|a| format!("{:03x}", a)).collect();
+        writeln!(
+            out,
+            "//! Self-modifying, interpreted: {}",
+            starts.join(", ")
+        )
+        .unwrap();
+    }
+    out.push_str("\n#![allow(clippy::all, unreachable_code, unused_imports)]\n\n");
+    out.push_str("use chiper::chip8::{Chip8, ExecError};\n");
+    out.push_str("use chiper::instruction::Instruction;\n");
+    out.push_str("use chiper::screen::Screen;\n\n");
+
+    let rom = cfg.rom();
+    writeln!(out, "/// The rom, loaded at {:#x}", MEMORY_START).unwrap();
+    writeln!(out, "const ROM: [u8; {}] = [", rom.len()).unwrap();
+    for row in rom.chunks(16) {
+        let bytes: Vec<String> = row.iter().map(|b| format!("{:#04x},", b)).collect();
+        writeln!(out, "{}{}", INDENT, bytes.join(" ")).unwrap();
+    }
+    out.push_str("];\n\n");
+
+    out.push_str("/// Runs the block at pc, returns false when there's none\n");
+    out.push_str("pub fn run<T: Screen>(m: &mut Chip8<T>) -> Result<bool, ExecError> {\n");
+    out.push_str("    match m.pc() {\n");
+    for block in &blocks {
+        writeln!(
+            out,
+            "        {:#05x} if unchanged(m, {:#05x}, {:#05x}) => block_{:03x}(m)?,",
+            block.start, block.start, block.end, block.start
+        )
+        .unwrap();
+    }
+    out.push_str("        _ => return Ok(false),\n");
+    out.push_str("    }\n");
+    out.push_str("    Ok(true)\n");
+    out.push_str("}\n\n");
+
+    out.push_str("/// The code from `start` to `end` is still the compiled one\n");
+    out.push_str("fn unchanged<T: Screen>(m: &Chip8<T>, start: usize, end: usize) -> bool {\n");
+    writeln!(
+        out,
+        "    m.memory()[start..end] == ROM[start - {:#x}..end - {:#x}]",
+        MEMORY_START, MEMORY_START
+    )
+    .unwrap();
+    out.push_str("}\n");
+
+    for block in blocks {
+        out.push('\n');
+        out.push_str(&function(cfg, block));
+    }
+    out
+}
+
+/// Starts of the blocks written by FX33 or FX55 at a constant I
+fn overwritten(cfg: &Cfg) -> BTreeSet<usize> {
+    let mut writes = Vec::new();
+    for block in cfg.blocks() {
+        let mut i = None;
+        for (_, word) in cfg.code(block) {
+            match Instruction::decode(word) {
+                Ok(Instruction::MovI(nnn)) => i = Some(nnn as usize),
+                Ok(Instruction::Bcd(_)) => writes.extend(i.map(|i| (i, i + 3))),
+                Ok(Instruction::Store(x)) => {
+                    writes.extend(i.map(|i| (i, i + x + 1)));
+                    // I may be advanced by the memory quirk
+                    i = None;
+                }
+                Ok(Instruction::AddI(_) | Instruction::Font(_) | Instruction::Load(_)) => i = None,
+                _ => {}
+            }
+        }
+    }
+    cfg.blocks()
+        .filter(|b| {
+            writes
+                .iter()
+                .any(|&(start, end)| start < b.end && b.start < end)
+        })
+        .map(|b| b.start)
+        .collect()
+}
+
+/// The instruction at `addr` is compiled, blocks starting with one the
+/// interpreter runs would never advance
+fn supported(cfg: &Cfg, addr: usize) -> bool {
+    cfg.word(addr)
+        .and_then(|word| Instruction::decode(word).ok())
+        .is_some_and(|instruction| statement(addr, instruction).is_some())
+}
+
+/// Function running the block
+fn function(cfg: &Cfg, block: &Block) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "fn block_{:03x}<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {{",
+        block.start
+    )
+    .unwrap();
+    let code = cfg.code(block);
+    for (n, &(addr, word)) in code.iter().enumerate() {
+        let instruction = Instruction::decode(word).ok();
+        match instruction {
+            Some(instruction) => {
+                let text = instruction.to_string();
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                writeln!(out, "{}// {:03x}: {}", INDENT, addr, text).unwrap();
+            }
+            None => writeln!(out, "{}// {:03x}: {:04x}", INDENT, addr, word).unwrap(),
+        }
+        let lines = match instruction.and_then(|i| statement(addr, i)) {
+            Some(Statement::Exit(lines)) => {
+                push_lines(&mut out, &lines);
+                break;
+            }
+            Some(Statement::Effect(lines)) => lines,
+            // unsupported instructions are left to the interpreter
+            None => {
+                push_lines(&mut out, &[format!("m.set_pc({:#05x});", addr)]);
+                break;
+            }
+        };
+        push_lines(&mut out, &lines);
+        let next = addr + 2;
+        if n + 1 == code.len() {
+            push_lines(
+                &mut out,
+                &[
+                    format!("m.set_pc({:#05x});", next),
+                    "m.retire();".to_string(),
+                ],
+            );
+        } else {
+            push_lines(
+                &mut out,
+                &[
+                    "if m.retire() {".to_string(),
+                    format!("{}m.set_pc({:#05x});", INDENT, next),
+                    format!("{}return Ok(());", INDENT),
+                    "}".to_string(),
+                ],
+            );
+        }
+    }
+    out.push_str("    Ok(())\n");
+    out.push_str("}\n");
+    out
+}
+
+fn push_lines(out: &mut String, lines: &[String]) {
+    for line in lines {
+        writeln!(out, "{}{}", INDENT, line).unwrap();
+    }
+}
+
+enum Statement {
+    /// Continues with the next instruction
+    Effect(Vec<String>),
+    /// Sets pc and retires the instruction, ends the block
+    Exit(Vec<String>),
+}
+
+/// Code of the instruction at `addr`, None when it's left to the interpreter
+fn statement(addr: usize, instruction: Instruction) -> Option<Statement> {
+    use Instruction::*;
+    // repeats the instruction like the interpreter does while it waits
+    let wait = [
+        format!("{}m.set_pc({:#05x});", INDENT, addr),
+        format!("{}m.retire();", INDENT),
+        format!("{}return Ok(());", INDENT),
+    ];
+    let effect = |lines: &[&str]| {
+        Some(Statement::Effect(
+            lines.iter().map(|l| l.to_string()).collect(),
+        ))
+    };
+    let skip = |condition: String| {
+        Some(Statement::Exit(vec![
+            condition,
+            format!(
+                "m.set_pc(if skip {{ {:#05x} }} else {{ {:#05x} }});",
+                addr + 4,
+                addr + 2
+            ),
+            "m.retire();".to_string(),
+        ]))
+    };
+    let execute = format!("m.execute(Instruction::{:?})?;", instruction);
+    // errors are reported at the pc of the instruction
+    let at = format!("m.set_pc({:#05x});", addr);
+    match instruction {
+        ClearScreen => effect(&["m.clear_screen();"]),
+        MovImm(x, nn) => effect(&[&format!("m.v()[{:#x}] = {:#04x};", x, nn)]),
+        AddImm(x, nn) => effect(&[
+            "let v = m.v();",
+            &format!("v[{:#x}] = v[{:#x}].wrapping_add({:#04x});", x, x, nn),
+        ]),
+        MovReg(x, y) => effect(&["let v = m.v();", &format!("v[{:#x}] = v[{:#x}];", x, y)]),
+        AddReg(x, y) => effect(&[
+            "let v = m.v();",
+            &format!(
+                "let (value, carry) = v[{:#x}].overflowing_add(v[{:#x}]);",
+                x, y
+            ),
+            &format!("v[{:#x}] = value;", x),
+            "v[0xf] = carry as u8;",
+        ]),
+        SubReg(x, y) | SubReverse(x, y) => {
+            let (a, b) = if let SubReg(..) = instruction {
+                (x, y)
+            } else {
+                (y, x)
+            };
+            effect(&[
+                "let v = m.v();",
+                &format!(
+                    "let (value, borrow) = v[{:#x}].overflowing_sub(v[{:#x}]);",
+                    a, b
+                ),
+                &format!("v[{:#x}] = value;", x),
+                "v[0xf] = !borrow as u8;",
+            ])
+        }
+        Or(..) | And(..) | Xor(..) | ShiftRight(..) | ShiftLeft(..) => effect(&[&execute]),
+        Font(_) => effect(&[&execute]),
+        Bcd(_) | Store(_) | Load(_) => effect(&[&at, &execute]),
+        MovI(nnn) => effect(&[&format!("m.set_i({:#05x});", nnn)]),
+        AddI(x) => effect(&[
+            &format!("let value = m.v()[{:#x}] as u16;", x),
+            "m.set_i(m.i().wrapping_add(value));",
+        ]),
+        Rand(x, nn) => effect(&[
+            "let value = m.random();",
+            &format!("m.v()[{:#x}] = value & {:#04x};", x, nn),
+        ]),
+        Draw(..) => {
+            let mut lines = vec![
+                at,
+                format!("if !m.execute(Instruction::{:?})? {{", instruction),
+            ];
+            // pc is already set
+            lines.extend(wait[1..].iter().cloned());
+            lines.push("}".to_string());
+            Some(Statement::Effect(lines))
+        }
+        WaitKey(x) => {
+            let mut lines = vec![
+                "match m.pressed_key() {".to_string(),
+                format!("{}Some(key) => m.v()[{:#x}] = key,", INDENT, x),
+                format!("{}None => {{", INDENT),
+            ];
+            lines.extend(wait.iter().map(|l| format!("{}{}", INDENT, l)));
+            lines.push(format!("{}}}", INDENT));
+            lines.push("}".to_string());
+            Some(Statement::Effect(lines))
+        }
+        GetDelay(x) => effect(&[
+            "let value = m.delay_timer();",
+            &format!("m.v()[{:#x}] = value;", x),
+        ]),
+        SetDelay(x) => effect(&[
+            &format!("let value = m.v()[{:#x}];", x),
+            "m.set_delay_timer(value);",
+        ]),
+        SetSound(x) => effect(&[
+            &format!("let value = m.v()[{:#x}];", x),
+            "m.set_sound_timer(value);",
+        ]),
+        Jump(target) => Some(Statement::Exit(vec![
+            format!("m.set_pc({:#05x});", target),
+            "m.retire();".to_string(),
+        ])),
+        Call(_) | Return | JumpV0(_) => Some(Statement::Exit(vec![
+            // calls push the address after pc
+            at,
+            execute,
+            "m.retire();".to_string(),
+        ])),
+        SkipEqImm(x, nn) => skip(format!("let skip = m.v()[{:#x}] == {:#04x};", x, nn)),
+        SkipNeImm(x, nn) => skip(format!("let skip = m.v()[{:#x}] != {:#04x};", x, nn)),
+        SkipEqReg(x, y) => skip(format!(
+            "let v = m.v();\n{}let skip = v[{:#x}] == v[{:#x}];",
+            INDENT, x, y
+        )),
+        SkipNeReg(x, y) => skip(format!(
+            "let v = m.v();\n{}let skip = v[{:#x}] != v[{:#x}];",
+            INDENT, x, y
+        )),
+        SkipKey(x) => skip(format!(
+            "let key = m.v()[{:#x}];\n{}let skip = m.key_pressed(key);",
+            x, INDENT
+        )),
+        SkipNotKey(x) => skip(format!(
+            "let key = m.v()[{:#x}];\n{}let skip = !m.key_pressed(key);",
+            x, INDENT
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recompile() {
+        // 200: mov I, 20a; 202: bcd V0; 204: skp V0 == 1; 206: jmp 200;
+        // 208: jmp 20a; 20a: jmp 20a (overwritten by the bcd)
+        let rom = [
+            0xa2, 0x0a, 0xf0, 0x33, 0x30, 0x01, 0x12, 0x00, 0x12, 0x0a, 0x12, 0x0a,
+        ];
+        let module = recompile(&Cfg::new(&rom), "test.ch8");
+        assert!(module.contains("//! Self-modifying, interpreted: 20a\n"));
+        assert!(module.contains("0x200 if unchanged(m, 0x200, 0x206) => block_200(m)?,"));
+        assert!(!module.contains("block_20a"));
+        assert!(module.contains(
+            "    // 204: skipifeq V0, 01\n    \
+             let skip = m.v()[0x0] == 0x01;\n    \
+             m.set_pc(if skip { 0x208 } else { 0x206 });\n    m.retire();\n    Ok(())\n}\n"
+        ));
+    }
+}
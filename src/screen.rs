@@ -1,15 +1,31 @@
 extern crate sdl2;
 
+mod font;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Scancode;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
-use sdl2::Sdl;
+use sdl2::{EventPump, Sdl};
+use std::fmt;
+use std::str::FromStr;
 
 use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::overlay::{Overlay, KEYPAD_LAYOUT};
+
+/// Result of processing window events
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    /// Window was closed
+    pub quit: bool,
+    /// Screen content was lost and has to be drawn again
+    pub redraw: bool,
+}
 
 pub trait Screen {
-    /// Creates new Screen
+    // Creates new Screen
     //fn new() -> Result<Self, String>
     //where
     //    Self: Sized;
@@ -25,6 +41,20 @@ pub trait Screen {
 
     /// Visualize all changes
     fn present(&mut self);
+
+    /// Handles pending window events, `keys` is updated with the keypad
+    /// state
+    fn poll_events(&mut self, _keys: &mut [bool; 16]) -> Input {
+        Input::default()
+    }
+
+    /// Whether the debug overlay is currently shown
+    fn overlay_visible(&self) -> bool {
+        false
+    }
+
+    /// Draw debug panes next to the game screen
+    fn draw_overlay(&mut self, _overlay: &Overlay) {}
 }
 
 /// Stabs for testing without Screen
@@ -40,22 +70,264 @@ impl Screen for NoScreen {
     fn present(&mut self) {}
 }
 
+/// Keyboard keys of game actions named as in the chip-8-database
+const ACTIONS: [(&str, Scancode); 12] = [
+    ("up", Scancode::Up),
+    ("down", Scancode::Down),
+    ("left", Scancode::Left),
+    ("right", Scancode::Right),
+    ("a", Scancode::Space),
+    ("b", Scancode::Return),
+    ("player2Up", Scancode::I),
+    ("player2Down", Scancode::K),
+    ("player2Left", Scancode::J),
+    ("player2Right", Scancode::L),
+    ("player2A", Scancode::U),
+    ("player2B", Scancode::O),
+];
+
+/// Keyboard keys of the hex keypad keys 0-F
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Scancode; 16],
+    /// Additional keys of game actions, e.g. arrows for movement
+    actions: [Option<Scancode>; 16],
+}
+
+impl Default for Keymap {
+    /// Left side of QWERTY keyboard is used for the 4x4 layout
+    fn default() -> Self {
+        const KEYBOARD: [[Scancode; 4]; 4] = [
+            [
+                Scancode::Num1,
+                Scancode::Num2,
+                Scancode::Num3,
+                Scancode::Num4,
+            ],
+            [Scancode::Q, Scancode::W, Scancode::E, Scancode::R],
+            [Scancode::A, Scancode::S, Scancode::D, Scancode::F],
+            [Scancode::Z, Scancode::X, Scancode::C, Scancode::V],
+        ];
+        // every key is assigned by the layout
+        let mut keys = [Scancode::X; 16];
+        for (row, layout) in KEYBOARD.iter().zip(KEYPAD_LAYOUT.iter()) {
+            for (&code, &key) in row.iter().zip(layout.iter()) {
+                keys[key as usize] = code;
+            }
+        }
+        Keymap {
+            keys,
+            actions: [None; 16],
+        }
+    }
+}
+
+impl Keymap {
+    /// Keypad key mapped to `scancode`
+    fn key(&self, scancode: Scancode) -> Option<usize> {
+        self.keys
+            .iter()
+            .position(|&code| code == scancode)
+            .or_else(|| self.actions.iter().position(|&code| code == Some(scancode)))
+    }
+
+    /// Maps the keyboard key of a game action, e.g. "up" is the up arrow,
+    /// to the keypad `key`
+    pub fn bind_action(&mut self, action: &str, key: u8) -> Result<(), String> {
+        let &(_, code) = ACTIONS
+            .iter()
+            .find(|(name, _)| *name == action)
+            .ok_or_else(|| format!("Unknown action '{}'", action))?;
+        let key = self
+            .actions
+            .get_mut(key as usize)
+            .ok_or_else(|| format!("Invalid keypad key {} for '{}'", key, action))?;
+        *key = Some(code);
+        Ok(())
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    /// Comma separated key names for the keypad keys 0-F, e.g.
+    /// `X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V`
+    fn from_str(s: &str) -> Result<Keymap, String> {
+        let names: Vec<&str> = s.split(',').map(str::trim).collect();
+        if names.len() != 16 {
+            return Err(format!(
+                "Keymap needs 16 keys for keypad keys 0-F, got {}",
+                names.len()
+            ));
+        }
+        let mut keys = [Scancode::X; 16];
+        for (key, name) in keys.iter_mut().zip(names) {
+            *key = Scancode::from_name(name).ok_or_else(|| format!("Unknown key '{}'", name))?;
+        }
+        Ok(Keymap {
+            keys,
+            actions: [None; 16],
+        })
+    }
+}
+
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = self.keys.iter().map(|code| code.name()).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// Colors of lit and unlit pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            foreground: Color::WHITE,
+            background: Color::BLACK,
+        }
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s.trim().trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+        _ => Err(format!("Invalid color '{}', expected RRGGBB", s)),
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Foreground and background colors, e.g. `#ffcc00,#202020`
+    fn from_str(s: &str) -> Result<Palette, String> {
+        let (foreground, background) = s
+            .split_once(',')
+            .ok_or_else(|| format!("Invalid palette '{}', expected <fg>,<bg>", s))?;
+        Ok(Palette {
+            foreground: parse_color(foreground)?,
+            background: parse_color(background)?,
+        })
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |c: Color| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+        write!(f, "{},{}", hex(self.foreground), hex(self.background))
+    }
+}
+
+/// Display effects
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Effects {
+    /// Gap between pixels
+    pub grid: bool,
+    /// Every other window line is left dark
+    pub scanlines: bool,
+}
+
+impl FromStr for Effects {
+    type Err = String;
+
+    /// Comma separated effect names or `none`
+    fn from_str(s: &str) -> Result<Effects, String> {
+        let mut effects = Effects::default();
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "none" => {}
+                "grid" => effects.grid = true,
+                "scanlines" => effects.scanlines = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown effect '{}', expected grid, scanlines or none",
+                        name
+                    ))
+                }
+            }
+        }
+        Ok(effects)
+    }
+}
+
+impl fmt::Display for Effects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = [(self.grid, "grid"), (self.scanlines, "scanlines")]
+            .iter()
+            .filter(|e| e.0)
+            .map(|e| e.1)
+            .collect();
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+/// Settings of the SDL window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Size of a CHIP-8 pixel in window pixels
+    pub scale: u32,
+    pub palette: Palette,
+    pub keymap: Keymap,
+    pub effects: Effects,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scale: 16,
+            palette: Palette::default(),
+            keymap: Keymap::default(),
+            effects: Effects::default(),
+        }
+    }
+}
+
 pub struct SdlScreen {
     canvas: Canvas<Window>,
+    events: EventPump,
+    /// Debug panes are shown, toggled by F1
+    overlay: bool,
+    options: Options,
 }
 
 impl SdlScreen {
-    const PX_SIZE: u32 = 16;
-    const WIDTH: u32 = SCREEN_WIDTH * SdlScreen::PX_SIZE;
-    const HEIGHT: u32 = SCREEN_HEIGHT * SdlScreen::PX_SIZE;
+    const OVERLAY_KEY: Scancode = Scancode::F1;
+    /// Size of a font pixel
+    const TEXT_SCALE: u32 = 2;
+    const CHAR_WIDTH: i32 = ((font::GLYPH_WIDTH + 1) * SdlScreen::TEXT_SCALE) as i32;
+    const LINE_HEIGHT: i32 = ((font::GLYPH_HEIGHT + 1) * SdlScreen::TEXT_SCALE) as i32;
+    const MARGIN: i32 = 8;
+    /// Panel on the right side holds disassembly and registers
+    const SIDE_WIDTH: u32 = 34 * SdlScreen::CHAR_WIDTH as u32 + 2 * SdlScreen::MARGIN as u32;
+    /// Panel under the game screen holds memory, stack and keypad
+    const BOTTOM_HEIGHT: u32 = 9 * SdlScreen::LINE_HEIGHT as u32 + 2 * SdlScreen::MARGIN as u32;
+
+    const PANEL_COLOR: Color = Color::RGB(0x20, 0x20, 0x28);
+    const TEXT_COLOR: Color = Color::RGB(0xc0, 0xc0, 0xc0);
+    const TITLE_COLOR: Color = Color::RGB(0xe0, 0xc0, 0x40);
+    const PC_COLOR: Color = Color::RGB(0x40, 0xe0, 0x40);
+    const BREAKPOINT_COLOR: Color = Color::RGB(0xe0, 0x40, 0x40);
 
-    fn from_sdl_conext(sdl_context: &Sdl) -> Result<Self, String> {
+    fn from_sdl_conext(sdl_context: &Sdl, options: Options) -> Result<Self, String> {
+        if options.scale == 0 {
+            return Err("Scale must be at least 1".to_string());
+        }
         let video_subsystem = sdl_context.video()?;
         let window = video_subsystem
             .window(
                 "Chiper: CHIP-8 emulator",
-                SdlScreen::WIDTH,
-                SdlScreen::HEIGHT,
+                SCREEN_WIDTH * options.scale,
+                SCREEN_HEIGHT * options.scale,
             )
             .position_centered()
             .build()
@@ -67,48 +339,266 @@ impl SdlScreen {
             .build()
             .map_err(|e| e.to_string())?;
 
-        Ok(SdlScreen { canvas })
+        let events = sdl_context.event_pump()?;
+
+        Ok(SdlScreen {
+            canvas,
+            events,
+            overlay: false,
+            options,
+        })
+    }
+
+    /// Size of the game screen
+    fn width(&self) -> u32 {
+        SCREEN_WIDTH * self.options.scale
+    }
+
+    fn height(&self) -> u32 {
+        SCREEN_HEIGHT * self.options.scale
+    }
+
+    fn fill_px(&mut self, x: i32, y: i32, lit: bool) {
+        let scale = self.options.scale;
+        let effects = self.options.effects;
+        let cell = Rect::new(x * scale as i32, y * scale as i32, scale, scale);
+        self.canvas.set_draw_color(self.options.palette.background);
+        self.canvas.fill_rect(cell).unwrap();
+        if !lit {
+            return;
+        }
+        // the gap is at the right and bottom edges of a pixel
+        let size = if effects.grid && scale > 2 {
+            scale - 1
+        } else {
+            scale
+        };
+        let rects: Vec<Rect> = if effects.scanlines && scale > 1 {
+            (0..size)
+                .step_by(2)
+                .map(|row| Rect::new(cell.x(), cell.y() + row as i32, size, 1))
+                .collect()
+        } else {
+            vec![Rect::new(cell.x(), cell.y(), size, size)]
+        };
+        self.canvas.set_draw_color(self.options.palette.foreground);
+        self.canvas.fill_rects(&rects).unwrap();
+    }
+
+    fn toggle_overlay(&mut self) {
+        self.overlay = !self.overlay;
+        let (width, height) = if self.overlay {
+            (
+                self.width() + SdlScreen::SIDE_WIDTH,
+                self.height() + SdlScreen::BOTTOM_HEIGHT,
+            )
+        } else {
+            (self.width(), self.height())
+        };
+        self.canvas.window_mut().set_size(width, height).unwrap();
+        self.canvas.set_draw_color(SdlScreen::PANEL_COLOR);
+        self.canvas.clear();
+    }
+
+    fn draw_text(&mut self, x: i32, y: i32, text: &str, color: Color) {
+        let scale = SdlScreen::TEXT_SCALE;
+        let mut rects = Vec::new();
+        for (idx, c) in text.chars().enumerate() {
+            let left = x + idx as i32 * SdlScreen::CHAR_WIDTH;
+            for (row, bits) in font::glyph(c).iter().enumerate() {
+                for col in 0..font::GLYPH_WIDTH {
+                    if bits & (1 << (font::GLYPH_WIDTH - 1 - col)) != 0 {
+                        rects.push(Rect::new(
+                            left + (col * scale) as i32,
+                            y + row as i32 * scale as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
+            }
+        }
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rects(&rects).unwrap();
+    }
+
+    fn draw_pane(&mut self, x: i32, y: i32, title: &str, lines: &[String]) {
+        self.draw_text(x, y, title, SdlScreen::TITLE_COLOR);
+        let mut y = y + SdlScreen::LINE_HEIGHT;
+        for line in lines {
+            self.draw_text(x, y, line, SdlScreen::TEXT_COLOR);
+            y += SdlScreen::LINE_HEIGHT;
+        }
+    }
+
+    fn draw_keypad(&mut self, x: i32, y: i32, keys: &[bool; 16]) {
+        self.draw_text(x, y, "KEYPAD", SdlScreen::TITLE_COLOR);
+        let cell = 3 * SdlScreen::CHAR_WIDTH;
+        for (row, row_keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            let top = y + (row as i32 + 1) * SdlScreen::LINE_HEIGHT;
+            for (col, &key) in row_keys.iter().enumerate() {
+                let left = x + col as i32 * cell;
+                let label = format!("{:X}", key);
+                if keys[key as usize] {
+                    self.canvas.set_draw_color(SdlScreen::TEXT_COLOR);
+                    self.canvas
+                        .fill_rect(Rect::new(
+                            left,
+                            top - 2,
+                            2 * SdlScreen::CHAR_WIDTH as u32,
+                            SdlScreen::LINE_HEIGHT as u32,
+                        ))
+                        .unwrap();
+                    self.draw_text(left + 4, top, &label, SdlScreen::PANEL_COLOR);
+                } else {
+                    self.draw_text(left + 4, top, &label, SdlScreen::TEXT_COLOR);
+                }
+            }
+        }
     }
 }
 
 impl Screen for SdlScreen {
     fn clear(&mut self) {
-        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.set_draw_color(self.options.palette.background);
         self.canvas.clear();
     }
 
     fn draw_px(&mut self, x: i32, y: i32) {
-        self.canvas.set_draw_color(Color::WHITE);
+        self.fill_px(x, y, true);
+    }
+
+    fn clear_px(&mut self, x: i32, y: i32) {
+        self.fill_px(x, y, false);
+    }
+
+    fn present(&mut self) {
+        self.canvas.present();
+    }
+
+    fn poll_events(&mut self, keys: &mut [bool; 16]) -> Input {
+        let mut input = Input::default();
+        let events: Vec<Event> = self.events.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. } => input.quit = true,
+                Event::KeyDown {
+                    scancode: Some(SdlScreen::OVERLAY_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    self.toggle_overlay();
+                    input.redraw = true;
+                }
+                Event::KeyDown {
+                    scancode: Some(code),
+                    ..
+                } => {
+                    if let Some(key) = self.options.keymap.key(code) {
+                        keys[key] = true;
+                    }
+                }
+                Event::KeyUp {
+                    scancode: Some(code),
+                    ..
+                } => {
+                    if let Some(key) = self.options.keymap.key(code) {
+                        keys[key] = false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        input
+    }
+
+    fn overlay_visible(&self) -> bool {
+        self.overlay
+    }
+
+    fn draw_overlay(&mut self, overlay: &Overlay) {
+        // side panel
+        self.canvas.set_draw_color(SdlScreen::PANEL_COLOR);
         self.canvas
             .fill_rect(Rect::new(
-                x * SdlScreen::PX_SIZE as i32,
-                y * SdlScreen::PX_SIZE as i32,
-                SdlScreen::PX_SIZE,
-                SdlScreen::PX_SIZE,
+                self.width() as i32,
+                0,
+                SdlScreen::SIDE_WIDTH,
+                self.height() + SdlScreen::BOTTOM_HEIGHT,
             ))
             .unwrap();
-    }
-
-    fn clear_px(&mut self, x: i32, y: i32) {
-        self.canvas.set_draw_color(Color::BLACK);
         self.canvas
             .fill_rect(Rect::new(
-                x * SdlScreen::PX_SIZE as i32,
-                y * SdlScreen::PX_SIZE as i32,
-                SdlScreen::PX_SIZE,
-                SdlScreen::PX_SIZE,
+                0,
+                self.height() as i32,
+                self.width(),
+                SdlScreen::BOTTOM_HEIGHT,
             ))
             .unwrap();
-    }
 
-    fn present(&mut self) {
-        self.canvas.present();
+        let x = self.width() as i32 + SdlScreen::MARGIN;
+        let mut y = SdlScreen::MARGIN;
+        self.draw_text(x, y, "DISASSEMBLY", SdlScreen::TITLE_COLOR);
+        for line in &overlay.disassembly {
+            y += SdlScreen::LINE_HEIGHT;
+            let current = line.addr == overlay.pc;
+            let marker = match (line.breakpoint, current) {
+                (true, true) => "*>",
+                (true, false) => "* ",
+                (false, true) => " >",
+                (false, false) => "  ",
+            };
+            let color = if current {
+                SdlScreen::PC_COLOR
+            } else if line.breakpoint {
+                SdlScreen::BREAKPOINT_COLOR
+            } else {
+                SdlScreen::TEXT_COLOR
+            };
+            self.draw_text(x, y, &format!("{}{}", marker, line.text), color);
+        }
+        y += 2 * SdlScreen::LINE_HEIGHT;
+        self.draw_pane(x, y, "REGISTERS", &overlay.registers);
+
+        // bottom panel
+        let y = self.height() as i32 + SdlScreen::MARGIN;
+        let mut x = SdlScreen::MARGIN;
+        self.draw_pane(x, y, "MEMORY AT I", &overlay.memory);
+        x += 32 * SdlScreen::CHAR_WIDTH;
+        self.draw_pane(x, y, "CALL STACK", &overlay.stack);
+        x += 12 * SdlScreen::CHAR_WIDTH;
+        self.draw_keypad(x, y, &overlay.keys);
     }
 }
 
-pub fn sdl_init() -> Result<SdlScreen, String> {
+pub fn sdl_init(options: Options) -> Result<SdlScreen, String> {
     let sdl_context = sdl2::init()?;
-    let mut screen = SdlScreen::from_sdl_conext(&sdl_context)?;
+    let mut screen = SdlScreen::from_sdl_conext(&sdl_context, options)?;
     screen.clear();
     Ok(screen)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette() {
+        let palette: Palette = "#ffcc00, 202020".parse().unwrap();
+        assert_eq!(palette.foreground, Color::RGB(0xff, 0xcc, 0x00));
+        assert_eq!(palette.background, Color::RGB(0x20, 0x20, 0x20));
+        assert_eq!(palette.to_string(), "#ffcc00,#202020");
+        assert!("#ffcc00".parse::<Palette>().is_err());
+        assert!("#ffcc0,#000000".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn test_effects() {
+        let effects: Effects = "scanlines, grid".parse().unwrap();
+        assert!(effects.grid && effects.scanlines);
+        assert_eq!(effects.to_string(), "grid,scanlines");
+        assert_eq!("none".parse::<Effects>().unwrap(), Effects::default());
+        assert_eq!(Effects::default().to_string(), "none");
+        assert!("blur".parse::<Effects>().is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Execution traces
+//!
+//! A trace has one line per executed instruction showing the machine state
+//! right before the instruction is executed. Line format is a text with
+//! `{field}` placeholders, the default one is [`DEFAULT_FORMAT`]:
+//!
+//! ```text
+//! 0200 6005 V0:00 V1:00 V2:00 ... VF:00 I:0000 SP:0f00 DT:00 ST:00  mov V0, 05
+//! ```
+//!
+//! Fields, numbers are zero padded hex:
+//!
+//! | field          | value                                      |
+//! |----------------|--------------------------------------------|
+//! | `{cycle}`      | number of the instruction, decimal         |
+//! | `{pc}`         | address of the instruction                 |
+//! | `{opcode}`     | instruction word                           |
+//! | `{ins}`        | disassembly                                |
+//! | `{v0}`..`{vf}` | single data register                       |
+//! | `{regs}`       | all data registers as `V0:00 V1:00 ...`    |
+//! | `{i}`, `{sp}`  | address register and stack pointer         |
+//! | `{dt}`, `{st}` | delay and sound timers                     |
+//!
+//! Traces of other emulators can be compared with [`diff`] once the format
+//! is set to produce the same lines.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::chip8::Chip8;
+use crate::screen::Screen;
+
+pub const DEFAULT_FORMAT: &str = "{pc} {opcode} {regs} I:{i} SP:{sp} DT:{dt} ST:{st}  {ins}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Cycle,
+    Pc,
+    Opcode,
+    Instruction,
+    V(usize),
+    Regs,
+    I,
+    Sp,
+    Dt,
+    St,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Field, String> {
+        let field = match s.to_lowercase().as_str() {
+            "cycle" => Field::Cycle,
+            "pc" => Field::Pc,
+            "opcode" => Field::Opcode,
+            "ins" => Field::Instruction,
+            "regs" => Field::Regs,
+            "i" => Field::I,
+            "sp" => Field::Sp,
+            "dt" => Field::Dt,
+            "st" => Field::St,
+            name => match name.strip_prefix('v') {
+                Some(reg) if reg.len() == 1 => {
+                    Field::V(usize::from_str_radix(reg, 16).map_err(|_| unknown_field(s))?)
+                }
+                _ => return Err(unknown_field(s)),
+            },
+        };
+        Ok(field)
+    }
+}
+
+fn unknown_field(name: &str) -> String {
+    format!(
+        "Unknown trace field '{}', expected one of cycle, pc, opcode, ins, v0..vf, regs, i, sp, dt, st",
+        name
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+/// Trace line format, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        DEFAULT_FORMAT
+            .parse()
+            .expect("Invalid default trace format")
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "Unclosed '{' in trace format".to_string())?
+                + start;
+            segments.push(Segment::Field(rest[start + 1..end].trim().parse()?));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Format {
+            source: s.to_string(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Format {
+    /// Describes the instruction at pc, which is about to be executed
+    pub fn render<T: Screen>(&self, chip8: &Chip8<T>) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Field(field) => out.push_str(&field_value(chip8, *field)),
+            }
+        }
+        out
+    }
+}
+
+fn field_value<T: Screen>(chip8: &Chip8<T>, field: Field) -> String {
+    match field {
+        Field::Cycle => chip8.cycles.to_string(),
+        Field::Pc => format!("{:04x}", chip8.pc),
+        Field::Opcode => match chip8.memory.get(chip8.pc..chip8.pc + 2) {
+            Some(word) => format!("{:02x}{:02x}", word[0], word[1]),
+            None => "????".to_string(),
+        },
+        Field::Instruction => match chip8.current_instruction() {
+            // squeeze tabs used to align the terminal output
+            Some(ins) => ins
+                .to_string()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => "UNKNOWN".to_string(),
+        },
+        Field::V(x) => format!("{:02x}", chip8.v[x]),
+        Field::Regs => {
+            let regs: Vec<String> = (0..16)
+                .map(|x| format!("V{:X}:{:02x}", x, chip8.v[x]))
+                .collect();
+            regs.join(" ")
+        }
+        Field::I => format!("{:04x}", chip8.i),
+        Field::Sp => format!("{:04x}", chip8.sp),
+        Field::Dt => format!("{:02x}", chip8.dt),
+        Field::St => format!("{:02x}", chip8.st),
+    }
+}
+
+/// Writes a trace line for every executed instruction
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    format: Format,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>, format: Format) -> Tracer {
+        Tracer { out, format }
+    }
+
+    pub fn record<T: Screen>(&mut self, chip8: &Chip8<T>) -> io::Result<()> {
+        writeln!(self.out, "{}", self.format.render(chip8))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// First difference between two traces
+#[derive(Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of matching instructions before the divergence
+    pub index: usize,
+    /// Line numbers of the diverging instruction, counted from 1. A trace
+    /// which ended earlier points past its last line
+    pub lines: (usize, usize),
+    /// Matching lines right before the divergence
+    pub before: Vec<String>,
+    /// Diverging line and a few following ones from each trace
+    pub after: (Vec<String>, Vec<String>),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Traces diverge after {} instructions (line {} of the first, line {} of the second)",
+            self.index, self.lines.0, self.lines.1
+        )?;
+        for line in &self.before {
+            writeln!(f, "  {}", line)?;
+        }
+        let (a, b) = &self.after;
+        match (a.first(), b.first()) {
+            (Some(a), Some(b)) => {
+                let changes: Vec<String> = a
+                    .split_whitespace()
+                    .zip(b.split_whitespace())
+                    .filter(|(a, b)| a != b)
+                    .map(|(a, b)| format!("{} != {}", a, b))
+                    .collect();
+                if !changes.is_empty() {
+                    writeln!(f, "Differs in: {}", changes.join(", "))?;
+                }
+            }
+            (None, _) => writeln!(f, "First trace ends here")?,
+            (_, None) => writeln!(f, "Second trace ends here")?,
+        }
+        for line in a {
+            writeln!(f, "- {}", line)?;
+        }
+        for line in b {
+            writeln!(f, "+ {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lines of a trace, comments (`#`) and blank lines are skipped, whitespace
+/// and case are normalized. Original line numbers are kept
+fn trace_lines(trace: &str) -> Vec<(usize, String)> {
+    trace
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(num, line)| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            (num, words.join(" ").to_lowercase())
+        })
+        .collect()
+}
+
+/// Compares two traces and finds the first diverging instruction, `context`
+/// lines are kept around it.
+///
+/// Traces may be started at different points, e.g. when one emulator skips
+/// its boot code, so the later start is looked up in the other trace first.
+pub fn diff(a: &str, b: &str, context: usize) -> Option<Divergence> {
+    let (a, b) = (trace_lines(a), trace_lines(b));
+    let find = |lines: &[(usize, String)], line: Option<&(usize, String)>| {
+        line.and_then(|(_, first)| lines.iter().position(|(_, l)| l == first))
+    };
+    let (start_a, start_b) = match (find(&a, b.first()), find(&b, a.first())) {
+        (_, Some(start_b)) => (0, start_b),
+        (Some(start_a), None) => (start_a, 0),
+        (None, None) => (0, 0),
+    };
+    let (a, b) = (&a[start_a..], &b[start_b..]);
+    let index = a
+        .iter()
+        .zip(b.iter())
+        .position(|((_, a), (_, b))| a != b)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))?;
+
+    let line_num = |lines: &[(usize, String)], all_lines: usize| match lines.get(index) {
+        Some((num, _)) => *num,
+        None => all_lines + 1,
+    };
+    let text = |lines: &[(usize, String)]| -> Vec<String> {
+        lines
+            .iter()
+            .skip(index)
+            .take(context + 1)
+            .map(|(_, l)| l.clone())
+            .collect()
+    };
+    Some(Divergence {
+        index,
+        lines: (
+            line_num(a, a.last().map_or(start_a, |(n, _)| *n)),
+            line_num(b, b.last().map_or(start_b, |(n, _)| *n)),
+        ),
+        before: a[index.saturating_sub(context)..index]
+            .iter()
+            .map(|(_, l)| l.clone())
+            .collect(),
+        after: (text(a), text(b)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::NoScreen;
+
+    #[test]
+    fn test_format() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[0x6a, 0x05]);
+        chip8.v[0xa] = 0x42;
+        assert_eq!(
+            Format::default().render(&chip8),
+            "0200 6a05 V0:00 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 \
+             VA:42 VB:00 VC:00 VD:00 VE:00 VF:00 I:0000 SP:0f00 DT:00 ST:00  mov Va, 05"
+        );
+        let format: Format = "{cycle}: PC={PC} VA={va}".parse().unwrap();
+        assert_eq!(format.render(&chip8), "0: PC=0200 VA=42");
+        assert!("{pc".parse::<Format>().is_err());
+        assert!("{vg}".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_tracer() {
+        let mut chip8 = Chip8::new(NoScreen {});
+        chip8.load_from_slice(&[
+            0x60, 0x05, // mov V0, 05
+            0x70, 0x01, // add V0, 01
+        ]);
+        let path = std::env::temp_dir().join("chiper_test_tracer.trace");
+        let file = std::fs::File::create(&path).unwrap();
+        let format = "{pc} {v0} {ins}".parse().unwrap();
+        chip8.set_tracer(Tracer::new(Box::new(file), format));
+        chip8.emulate_op().unwrap();
+        chip8.emulate_op().unwrap();
+        chip8.flush_trace().unwrap();
+        let trace = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(trace, "0200 00 mov V0, 05\n0202 05 add V0, 01\n");
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = "# header\n0200 V0:00\n0202 V0:05\n0204 V0:06\n0206 V0:07\n";
+        let b = "0200 V0:00\n0202  V0:05\n0204 V0:07\n0206 V0:08\n";
+        let div = diff(a, b, 1).unwrap();
+        assert_eq!(div.index, 2);
+        assert_eq!(div.lines, (4, 3));
+        assert_eq!(div.before, vec!["0202 v0:05"]);
+        assert_eq!(div.after.0, vec!["0204 v0:06", "0206 v0:07"]);
+        assert!(div.to_string().contains("Differs in: v0:06 != v0:07"));
+
+        assert_eq!(diff(a, a, 3), None);
+    }
+
+    #[test]
+    fn test_diff_alignment() {
+        let a = "0200 00\n0202 01\n0204 02\n";
+        // boot code isn't part of the other trace
+        let b = "0000 ff\n0002 ff\n0200 00\n0202 01\n";
+        let div = diff(a, b, 2).unwrap();
+        assert_eq!(div.index, 2);
+        assert_eq!(div.lines, (3, 5));
+        assert_eq!(div.after.1, Vec::<String>::new());
+        assert!(div.to_string().contains("Second trace ends here"));
+    }
+}
@@ -0,0 +1,92 @@
+//! The bundled roms draw the same screens as before
+//!
+//! Every rom runs a fixed amount of frames with a fixed seed and the input
+//! script under golden/ if there is one, then the framebuffer is compared
+//! with the golden image. A mismatch prints a diff and saves the new image
+//! under the target directory. After an intended change the golden images
+//! are regenerated with `CHIPER_BLESS=1 cargo test --test golden`.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chiper::chip8::Chip8;
+use chiper::framebuffer::Lores;
+use chiper::input::Script;
+use chiper::screen::NoScreen;
+
+/// Set to regenerate the golden images
+const BLESS_ENV: &str = "CHIPER_BLESS";
+
+const SEED: u64 = 1;
+
+const ROMS: [(&str, &str, u64); 4] = [
+    ("Fishie.ch8", "fishie", 100),
+    ("Maze.ch8", "maze", 200),
+    ("partircle.ch8", "partircle", 300),
+    ("pong.ch8", "pong", 300),
+];
+
+fn run(rom: &str, name: &str, frames: u64) -> Lores {
+    let script = match fs::read_to_string(format!("tests/golden/{}.input", name)) {
+        Ok(text) => text.parse().unwrap(),
+        Err(_) => Script::default(),
+    };
+    let mut chip8 = Chip8::new(NoScreen {});
+    chip8.load_rom(&format!("roms/{}", rom)).unwrap();
+    chip8.set_seed(SEED);
+    script.run(&mut chip8, frames).unwrap();
+    Lores::from_bytes(chip8.framebuffer())
+}
+
+/// `#` and `.` for pixels set and clear in both, `+` for pixels set only
+/// in `actual` and `-` for pixels set only in `golden`
+fn diff(golden: &Lores, actual: &Lores) -> String {
+    let mut text = String::new();
+    for y in 0..32 {
+        for x in 0..64 {
+            text.push(match (golden.pixel(x, y), actual.pixel(x, y)) {
+                (true, true) => '#',
+                (false, false) => '.',
+                (false, true) => '+',
+                (true, false) => '-',
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[test]
+fn test_golden_framebuffers() {
+    let bless = env::var_os(BLESS_ENV).is_some();
+    let mut mismatches = Vec::new();
+    for (rom, name, frames) in ROMS {
+        let actual = run(rom, name, frames);
+        let path = format!("tests/golden/{}.png", name);
+        if bless {
+            fs::write(&path, actual.to_png().unwrap()).unwrap();
+            continue;
+        }
+        let golden = fs::read(&path)
+            .and_then(|image| Lores::from_png(&image))
+            .unwrap_or_else(|e| panic!("{}: {}, bless with {}=1", path, e, BLESS_ENV));
+        if golden != actual {
+            let new = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.png", name));
+            fs::write(&new, actual.to_png().unwrap()).unwrap();
+            mismatches.push(format!(
+                "{} differs after {} frames, + is set and - is clear now, new image at {}:\n{}",
+                path,
+                frames,
+                new.display(),
+                diff(&golden, &actual)
+            ));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{}\nbless with {}=1 if the changes are intended",
+        mismatches.join("\n"),
+        BLESS_ENV
+    );
+}
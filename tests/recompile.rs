@@ -0,0 +1,79 @@
+//! The recompiled bundled roms go through the same frames as the interpreter
+//!
+//! The modules under recompiled/ are generated, e.g. with
+//! `chiper recompile roms/pong.ch8 > tests/recompiled/pong.rs`.
+
+use std::fs;
+
+use chiper::cfg::Cfg;
+use chiper::chip8::{Chip8, Compiled};
+use chiper::recompile::recompile;
+use chiper::screen::NoScreen;
+
+#[path = "recompiled/fishie.rs"]
+mod fishie;
+#[path = "recompiled/maze.rs"]
+mod maze;
+#[path = "recompiled/partircle.rs"]
+mod partircle;
+#[path = "recompiled/pong.rs"]
+mod pong;
+#[path = "recompiled/space_invader_alien_sprite.rs"]
+mod space_invader_alien_sprite;
+
+const FRAMES: u64 = 600;
+
+fn roms() -> Vec<(&'static str, &'static str, Compiled<NoScreen>)> {
+    vec![
+        ("Fishie.ch8", "fishie", fishie::run),
+        ("Maze.ch8", "maze", maze::run),
+        ("partircle.ch8", "partircle", partircle::run),
+        ("pong.ch8", "pong", pong::run),
+        (
+            "space_invader_alien_sprite.ch8",
+            "space_invader_alien_sprite",
+            space_invader_alien_sprite::run,
+        ),
+    ]
+}
+
+/// Framebuffer after every frame, interpreted or with the compiled code
+fn frames(rom: &str, compiled: Option<Compiled<NoScreen>>) -> Vec<Vec<u8>> {
+    let mut chip8 = Chip8::new(NoScreen {});
+    chip8.load_rom(&format!("roms/{}", rom)).unwrap();
+    chip8.set_seed(1);
+    (0..FRAMES)
+        .map(|_| {
+            match compiled {
+                Some(compiled) => chip8.run_compiled(1, compiled),
+                None => chip8.run_frames(1),
+            }
+            .unwrap();
+            chip8.framebuffer().to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn test_modules_are_current() {
+    for (rom, module, _) in roms() {
+        let bytes = fs::read(format!("roms/{}", rom)).unwrap();
+        let expected = fs::read_to_string(format!("tests/recompiled/{}.rs", module)).unwrap();
+        assert!(
+            recompile(&Cfg::new(&bytes), rom) == expected,
+            "tests/recompiled/{}.rs is outdated",
+            module
+        );
+    }
+}
+
+#[test]
+fn test_same_frames() {
+    for (rom, _, run) in roms() {
+        let interpreted = frames(rom, None);
+        let compiled = frames(rom, Some(run));
+        for (frame, (a, b)) in interpreted.iter().zip(&compiled).enumerate() {
+            assert!(a == b, "{} differs at frame {}", rom, frame);
+        }
+    }
+}
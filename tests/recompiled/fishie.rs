@@ -0,0 +1,143 @@
+//! Fishie.ch8 recompiled by `chiper recompile`
+//!
+//! Pass `run` to `Chip8::run_compiled`, code without a block and blocks
+//! overwritten since run on the interpreter.
+
+#![allow(clippy::all, unreachable_code, unused_imports)]
+
+use chiper::chip8::{Chip8, ExecError};
+use chiper::instruction::Instruction;
+use chiper::screen::Screen;
+
+/// The rom, loaded at 0x200
+const ROM: [u8; 160] = [
+    0x00, 0xe0, 0xa2, 0x20, 0x62, 0x08, 0x60, 0xf8, 0x70, 0x08, 0x61, 0x10, 0x40, 0x20, 0x12, 0x0e,
+    0xd1, 0x08, 0xf2, 0x1e, 0x71, 0x08, 0x41, 0x30, 0x12, 0x08, 0x12, 0x10, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x3c, 0x3c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x3e, 0x3f, 0x3f, 0x3b, 0x39, 0x38, 0x38, 0x38, 0x00, 0x00, 0x80, 0xc1, 0xe7, 0xff, 0x7e, 0x3c,
+    0x00, 0x1f, 0xff, 0xf9, 0xc0, 0x80, 0x03, 0x03, 0x00, 0x80, 0xe0, 0xf0, 0x78, 0x38, 0x1c, 0x1c,
+    0x38, 0x38, 0x39, 0x3b, 0x3f, 0x3f, 0x3e, 0x3c, 0x78, 0xfc, 0xfe, 0xcf, 0x87, 0x03, 0x01, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x80, 0xe3, 0xff, 0x7f, 0x1c, 0x38, 0x38, 0x70, 0xf0, 0xe0, 0xc0, 0x00,
+    0x3c, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Runs the block at pc, returns false when there's none
+pub fn run<T: Screen>(m: &mut Chip8<T>) -> Result<bool, ExecError> {
+    match m.pc() {
+        0x200 if unchanged(m, 0x200, 0x208) => block_200(m)?,
+        0x208 if unchanged(m, 0x208, 0x20e) => block_208(m)?,
+        0x20e if unchanged(m, 0x20e, 0x210) => block_20e(m)?,
+        0x210 if unchanged(m, 0x210, 0x218) => block_210(m)?,
+        0x218 if unchanged(m, 0x218, 0x21a) => block_218(m)?,
+        0x21a if unchanged(m, 0x21a, 0x21c) => block_21a(m)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The code from `start` to `end` is still the compiled one
+fn unchanged<T: Screen>(m: &Chip8<T>, start: usize, end: usize) -> bool {
+    m.memory()[start..end] == ROM[start - 0x200..end - 0x200]
+}
+
+fn block_200<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 200: dclr
+    m.clear_screen();
+    if m.retire() {
+        m.set_pc(0x202);
+        return Ok(());
+    }
+    // 202: mov I, 220
+    m.set_i(0x220);
+    if m.retire() {
+        m.set_pc(0x204);
+        return Ok(());
+    }
+    // 204: mov V2, 08
+    m.v()[0x2] = 0x08;
+    if m.retire() {
+        m.set_pc(0x206);
+        return Ok(());
+    }
+    // 206: mov V0, f8
+    m.v()[0x0] = 0xf8;
+    m.set_pc(0x208);
+    m.retire();
+    Ok(())
+}
+
+fn block_208<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 208: add V0, 08
+    let v = m.v();
+    v[0x0] = v[0x0].wrapping_add(0x08);
+    if m.retire() {
+        m.set_pc(0x20a);
+        return Ok(());
+    }
+    // 20a: mov V1, 10
+    m.v()[0x1] = 0x10;
+    if m.retire() {
+        m.set_pc(0x20c);
+        return Ok(());
+    }
+    // 20c: skipifne V0, 20
+    let skip = m.v()[0x0] != 0x20;
+    m.set_pc(if skip { 0x210 } else { 0x20e });
+    m.retire();
+    Ok(())
+}
+
+fn block_20e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 20e: jmp 20e
+    m.set_pc(0x20e);
+    m.retire();
+    Ok(())
+}
+
+fn block_210<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 210: draw V1, V0, 8
+    m.set_pc(0x210);
+    if !m.execute(Instruction::Draw(1, 0, 8))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x212);
+        return Ok(());
+    }
+    // 212: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x214);
+        return Ok(());
+    }
+    // 214: add V1, 08
+    let v = m.v();
+    v[0x1] = v[0x1].wrapping_add(0x08);
+    if m.retire() {
+        m.set_pc(0x216);
+        return Ok(());
+    }
+    // 216: skipifne V1, 30
+    let skip = m.v()[0x1] != 0x30;
+    m.set_pc(if skip { 0x21a } else { 0x218 });
+    m.retire();
+    Ok(())
+}
+
+fn block_218<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 218: jmp 208
+    m.set_pc(0x208);
+    m.retire();
+    Ok(())
+}
+
+fn block_21a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 21a: jmp 210
+    m.set_pc(0x210);
+    m.retire();
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Maze.ch8 recompiled by `chiper recompile`
+//!
+//! Pass `run` to `Chip8::run_compiled`, code without a block and blocks
+//! overwritten since run on the interpreter.
+
+#![allow(clippy::all, unreachable_code, unused_imports)]
+
+use chiper::chip8::{Chip8, ExecError};
+use chiper::instruction::Instruction;
+use chiper::screen::Screen;
+
+/// The rom, loaded at 0x200
+const ROM: [u8; 34] = [
+    0xa2, 0x1e, 0xc2, 0x01, 0x32, 0x01, 0xa2, 0x1a, 0xd0, 0x14, 0x70, 0x04, 0x30, 0x40, 0x12, 0x00,
+    0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40, 0x20, 0x10, 0x20, 0x40,
+    0x80, 0x10,
+];
+
+/// Runs the block at pc, returns false when there's none
+pub fn run<T: Screen>(m: &mut Chip8<T>) -> Result<bool, ExecError> {
+    match m.pc() {
+        0x200 if unchanged(m, 0x200, 0x206) => block_200(m)?,
+        0x206 if unchanged(m, 0x206, 0x208) => block_206(m)?,
+        0x208 if unchanged(m, 0x208, 0x20e) => block_208(m)?,
+        0x20e if unchanged(m, 0x20e, 0x210) => block_20e(m)?,
+        0x210 if unchanged(m, 0x210, 0x216) => block_210(m)?,
+        0x216 if unchanged(m, 0x216, 0x218) => block_216(m)?,
+        0x218 if unchanged(m, 0x218, 0x21a) => block_218(m)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The code from `start` to `end` is still the compiled one
+fn unchanged<T: Screen>(m: &Chip8<T>, start: usize, end: usize) -> bool {
+    m.memory()[start..end] == ROM[start - 0x200..end - 0x200]
+}
+
+fn block_200<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 200: mov I, 21e
+    m.set_i(0x21e);
+    if m.retire() {
+        m.set_pc(0x202);
+        return Ok(());
+    }
+    // 202: rnd V2, 01
+    let value = m.random();
+    m.v()[0x2] = value & 0x01;
+    if m.retire() {
+        m.set_pc(0x204);
+        return Ok(());
+    }
+    // 204: skipifeq V2, 01
+    let skip = m.v()[0x2] == 0x01;
+    m.set_pc(if skip { 0x208 } else { 0x206 });
+    m.retire();
+    Ok(())
+}
+
+fn block_206<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 206: mov I, 21a
+    m.set_i(0x21a);
+    m.set_pc(0x208);
+    m.retire();
+    Ok(())
+}
+
+fn block_208<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 208: draw V0, V1, 4
+    m.set_pc(0x208);
+    if !m.execute(Instruction::Draw(0, 1, 4))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x20a);
+        return Ok(());
+    }
+    // 20a: add V0, 04
+    let v = m.v();
+    v[0x0] = v[0x0].wrapping_add(0x04);
+    if m.retire() {
+        m.set_pc(0x20c);
+        return Ok(());
+    }
+    // 20c: skipifeq V0, 40
+    let skip = m.v()[0x0] == 0x40;
+    m.set_pc(if skip { 0x210 } else { 0x20e });
+    m.retire();
+    Ok(())
+}
+
+fn block_20e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 20e: jmp 200
+    m.set_pc(0x200);
+    m.retire();
+    Ok(())
+}
+
+fn block_210<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 210: mov V0, 00
+    m.v()[0x0] = 0x00;
+    if m.retire() {
+        m.set_pc(0x212);
+        return Ok(());
+    }
+    // 212: add V1, 04
+    let v = m.v();
+    v[0x1] = v[0x1].wrapping_add(0x04);
+    if m.retire() {
+        m.set_pc(0x214);
+        return Ok(());
+    }
+    // 214: skipifeq V1, 20
+    let skip = m.v()[0x1] == 0x20;
+    m.set_pc(if skip { 0x218 } else { 0x216 });
+    m.retire();
+    Ok(())
+}
+
+fn block_216<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 216: jmp 200
+    m.set_pc(0x200);
+    m.retire();
+    Ok(())
+}
+
+fn block_218<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 218: jmp 218
+    m.set_pc(0x218);
+    m.retire();
+    Ok(())
+}
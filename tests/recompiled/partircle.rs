@@ -0,0 +1,872 @@
+//! partircle.ch8 recompiled by `chiper recompile`
+//!
+//! Pass `run` to `Chip8::run_compiled`, code without a block and blocks
+//! overwritten since run on the interpreter.
+
+#![allow(clippy::all, unreachable_code, unused_imports)]
+
+use chiper::chip8::{Chip8, ExecError};
+use chiper::instruction::Instruction;
+use chiper::screen::Screen;
+
+/// The rom, loaded at 0x200
+const ROM: [u8; 353] = [
+    0xa3, 0x21, 0x60, 0x00, 0x61, 0x00, 0x62, 0x08, 0xd0, 0x15, 0xf2, 0x1e, 0x80, 0x24, 0xd0, 0x15,
+    0xf2, 0x1e, 0x80, 0x24, 0xd0, 0x15, 0xf2, 0x1e, 0x80, 0x24, 0xd0, 0x15, 0xf2, 0x1e, 0x80, 0x24,
+    0xd0, 0x15, 0xf2, 0x1e, 0x80, 0x24, 0xd0, 0x15, 0xf2, 0x1e, 0x80, 0x24, 0xd0, 0x15, 0xf2, 0x1e,
+    0x80, 0x24, 0xd0, 0x15, 0x66, 0x05, 0x67, 0x02, 0x6a, 0x00, 0x12, 0xb8, 0x6b, 0x00, 0x6c, 0x00,
+    0xa2, 0xd8, 0xfb, 0x1e, 0xf3, 0x65, 0x22, 0xce, 0x22, 0x5c, 0x12, 0x62, 0x22, 0xce, 0x22, 0x5c,
+    0x7b, 0x04, 0x7c, 0x01, 0x5c, 0x60, 0x12, 0x40, 0x12, 0x3c, 0x12, 0x00, 0xa3, 0x20, 0xde, 0xd1,
+    0x00, 0xee, 0xa2, 0xd8, 0xfb, 0x1e, 0xf3, 0x65, 0x80, 0x24, 0x81, 0x34, 0x8e, 0x00, 0x8d, 0x10,
+    0x8e, 0xe6, 0x8d, 0xd6, 0x84, 0xe0, 0x65, 0xc2, 0x84, 0x54, 0x4f, 0x01, 0x12, 0x92, 0x4d, 0x00,
+    0x63, 0x01, 0x84, 0xd0, 0x65, 0xe1, 0x84, 0x54, 0x4f, 0x01, 0x12, 0x92, 0x33, 0x02, 0x73, 0x01,
+    0x12, 0x94, 0x22, 0x9c, 0xa2, 0xd8, 0xfb, 0x1e, 0xf3, 0x55, 0x12, 0x4c, 0xa3, 0x00, 0xfa, 0x1e,
+    0xf0, 0x65, 0x82, 0x00, 0x7a, 0x01, 0x64, 0x1f, 0x8a, 0x42, 0x60, 0x20, 0x61, 0x1e, 0x80, 0x0e,
+    0x81, 0x1e, 0xc3, 0x03, 0x73, 0xf8, 0x00, 0xee, 0x6b, 0x00, 0x6c, 0x00, 0x22, 0x9c, 0xa2, 0xd8,
+    0xfb, 0x1e, 0xf3, 0x55, 0x7b, 0x04, 0x7c, 0x01, 0x5c, 0x60, 0x12, 0xbc, 0x12, 0x3c, 0x8e, 0x00,
+    0x8d, 0x10, 0x8e, 0xe6, 0x8d, 0xd6, 0x00, 0xee, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xf8, 0xfa, 0xf9, 0xfe, 0xfb, 0xfc, 0xfd, 0xff, 0x02, 0x01, 0x03, 0x05, 0x04, 0x06, 0x07, 0x08,
+    0x06, 0x07, 0x04, 0x05, 0x03, 0x01, 0x02, 0xfe, 0xff, 0xfc, 0xfb, 0xfd, 0xfa, 0xf9, 0xf8, 0xfa,
+    0x80, 0xf7, 0x06, 0x77, 0x06, 0x36, 0x00, 0x00, 0x00, 0xc7, 0x6c, 0xcf, 0x0c, 0x0c, 0x00, 0x00,
+    0x00, 0x9f, 0xd9, 0xdf, 0xd9, 0xd9, 0x00, 0x00, 0x00, 0x3f, 0x8c, 0x0c, 0x8c, 0x8c, 0x00, 0x00,
+    0x00, 0x67, 0x6c, 0x6c, 0x6c, 0x67, 0x00, 0x00, 0x00, 0xb0, 0x30, 0x30, 0x30, 0xbe, 0x00, 0x00,
+    0x00, 0xf9, 0xc3, 0xf1, 0xc0, 0xfb, 0x00, 0x00, 0x00, 0xef, 0x00, 0xce, 0x60, 0xcc, 0x00, 0x00,
+    0x00,
+];
+
+/// Runs the block at pc, returns false when there's none
+pub fn run<T: Screen>(m: &mut Chip8<T>) -> Result<bool, ExecError> {
+    match m.pc() {
+        0x200 if unchanged(m, 0x200, 0x23c) => block_200(m)?,
+        0x23c if unchanged(m, 0x23c, 0x240) => block_23c(m)?,
+        0x240 if unchanged(m, 0x240, 0x248) => block_240(m)?,
+        0x248 if unchanged(m, 0x248, 0x24a) => block_248(m)?,
+        0x24a if unchanged(m, 0x24a, 0x24c) => block_24a(m)?,
+        0x24c if unchanged(m, 0x24c, 0x24e) => block_24c(m)?,
+        0x24e if unchanged(m, 0x24e, 0x250) => block_24e(m)?,
+        0x250 if unchanged(m, 0x250, 0x256) => block_250(m)?,
+        0x256 if unchanged(m, 0x256, 0x258) => block_256(m)?,
+        0x258 if unchanged(m, 0x258, 0x25a) => block_258(m)?,
+        0x25c if unchanged(m, 0x25c, 0x262) => block_25c(m)?,
+        0x262 if unchanged(m, 0x262, 0x27c) => block_262(m)?,
+        0x27c if unchanged(m, 0x27c, 0x27e) => block_27c(m)?,
+        0x27e if unchanged(m, 0x27e, 0x280) => block_27e(m)?,
+        0x280 if unchanged(m, 0x280, 0x282) => block_280(m)?,
+        0x282 if unchanged(m, 0x282, 0x28a) => block_282(m)?,
+        0x28a if unchanged(m, 0x28a, 0x28c) => block_28a(m)?,
+        0x28c if unchanged(m, 0x28c, 0x28e) => block_28c(m)?,
+        0x28e if unchanged(m, 0x28e, 0x290) => block_28e(m)?,
+        0x290 if unchanged(m, 0x290, 0x292) => block_290(m)?,
+        0x292 if unchanged(m, 0x292, 0x294) => block_292(m)?,
+        0x294 if unchanged(m, 0x294, 0x29c) => block_294(m)?,
+        0x29c if unchanged(m, 0x29c, 0x2b8) => block_29c(m)?,
+        0x2b8 if unchanged(m, 0x2b8, 0x2bc) => block_2b8(m)?,
+        0x2bc if unchanged(m, 0x2bc, 0x2be) => block_2bc(m)?,
+        0x2be if unchanged(m, 0x2be, 0x2ca) => block_2be(m)?,
+        0x2ca if unchanged(m, 0x2ca, 0x2cc) => block_2ca(m)?,
+        0x2cc if unchanged(m, 0x2cc, 0x2ce) => block_2cc(m)?,
+        0x2ce if unchanged(m, 0x2ce, 0x2d8) => block_2ce(m)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The code from `start` to `end` is still the compiled one
+fn unchanged<T: Screen>(m: &Chip8<T>, start: usize, end: usize) -> bool {
+    m.memory()[start..end] == ROM[start - 0x200..end - 0x200]
+}
+
+fn block_200<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 200: mov I, 321
+    m.set_i(0x321);
+    if m.retire() {
+        m.set_pc(0x202);
+        return Ok(());
+    }
+    // 202: mov V0, 00
+    m.v()[0x0] = 0x00;
+    if m.retire() {
+        m.set_pc(0x204);
+        return Ok(());
+    }
+    // 204: mov V1, 00
+    m.v()[0x1] = 0x00;
+    if m.retire() {
+        m.set_pc(0x206);
+        return Ok(());
+    }
+    // 206: mov V2, 08
+    m.v()[0x2] = 0x08;
+    if m.retire() {
+        m.set_pc(0x208);
+        return Ok(());
+    }
+    // 208: draw V0, V1, 5
+    m.set_pc(0x208);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x20a);
+        return Ok(());
+    }
+    // 20a: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x20c);
+        return Ok(());
+    }
+    // 20c: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x20e);
+        return Ok(());
+    }
+    // 20e: draw V0, V1, 5
+    m.set_pc(0x20e);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x210);
+        return Ok(());
+    }
+    // 210: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x212);
+        return Ok(());
+    }
+    // 212: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x214);
+        return Ok(());
+    }
+    // 214: draw V0, V1, 5
+    m.set_pc(0x214);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x216);
+        return Ok(());
+    }
+    // 216: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x218);
+        return Ok(());
+    }
+    // 218: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x21a);
+        return Ok(());
+    }
+    // 21a: draw V0, V1, 5
+    m.set_pc(0x21a);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x21c);
+        return Ok(());
+    }
+    // 21c: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x21e);
+        return Ok(());
+    }
+    // 21e: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x220);
+        return Ok(());
+    }
+    // 220: draw V0, V1, 5
+    m.set_pc(0x220);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x222);
+        return Ok(());
+    }
+    // 222: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x224);
+        return Ok(());
+    }
+    // 224: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x226);
+        return Ok(());
+    }
+    // 226: draw V0, V1, 5
+    m.set_pc(0x226);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x228);
+        return Ok(());
+    }
+    // 228: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x22a);
+        return Ok(());
+    }
+    // 22a: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x22c);
+        return Ok(());
+    }
+    // 22c: draw V0, V1, 5
+    m.set_pc(0x22c);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x22e);
+        return Ok(());
+    }
+    // 22e: add I, V2
+    let value = m.v()[0x2] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x230);
+        return Ok(());
+    }
+    // 230: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x232);
+        return Ok(());
+    }
+    // 232: draw V0, V1, 5
+    m.set_pc(0x232);
+    if !m.execute(Instruction::Draw(0, 1, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x234);
+        return Ok(());
+    }
+    // 234: mov V6, 05
+    m.v()[0x6] = 0x05;
+    if m.retire() {
+        m.set_pc(0x236);
+        return Ok(());
+    }
+    // 236: mov V7, 02
+    m.v()[0x7] = 0x02;
+    if m.retire() {
+        m.set_pc(0x238);
+        return Ok(());
+    }
+    // 238: mov Va, 00
+    m.v()[0xa] = 0x00;
+    if m.retire() {
+        m.set_pc(0x23a);
+        return Ok(());
+    }
+    // 23a: jmp 2b8
+    m.set_pc(0x2b8);
+    m.retire();
+    Ok(())
+}
+
+fn block_23c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 23c: mov Vb, 00
+    m.v()[0xb] = 0x00;
+    if m.retire() {
+        m.set_pc(0x23e);
+        return Ok(());
+    }
+    // 23e: mov Vc, 00
+    m.v()[0xc] = 0x00;
+    m.set_pc(0x240);
+    m.retire();
+    Ok(())
+}
+
+fn block_240<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 240: mov I, 2d8
+    m.set_i(0x2d8);
+    if m.retire() {
+        m.set_pc(0x242);
+        return Ok(());
+    }
+    // 242: add I, Vb
+    let value = m.v()[0xb] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x244);
+        return Ok(());
+    }
+    // 244: movm V0-V3, I
+    m.set_pc(0x244);
+    m.execute(Instruction::Load(3))?;
+    if m.retire() {
+        m.set_pc(0x246);
+        return Ok(());
+    }
+    // 246: call 2ce
+    m.set_pc(0x246);
+    m.execute(Instruction::Call(718))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_248<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 248: call 25c
+    m.set_pc(0x248);
+    m.execute(Instruction::Call(604))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_24a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 24a: jmp 262
+    m.set_pc(0x262);
+    m.retire();
+    Ok(())
+}
+
+fn block_24c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 24c: call 2ce
+    m.set_pc(0x24c);
+    m.execute(Instruction::Call(718))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_24e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 24e: call 25c
+    m.set_pc(0x24e);
+    m.execute(Instruction::Call(604))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_250<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 250: add Vb, 04
+    let v = m.v();
+    v[0xb] = v[0xb].wrapping_add(0x04);
+    if m.retire() {
+        m.set_pc(0x252);
+        return Ok(());
+    }
+    // 252: add Vc, 01
+    let v = m.v();
+    v[0xc] = v[0xc].wrapping_add(0x01);
+    if m.retire() {
+        m.set_pc(0x254);
+        return Ok(());
+    }
+    // 254: skipifeq Vc, V6
+    let v = m.v();
+    let skip = v[0xc] == v[0x6];
+    m.set_pc(if skip { 0x258 } else { 0x256 });
+    m.retire();
+    Ok(())
+}
+
+fn block_256<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 256: jmp 240
+    m.set_pc(0x240);
+    m.retire();
+    Ok(())
+}
+
+fn block_258<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 258: jmp 23c
+    m.set_pc(0x23c);
+    m.retire();
+    Ok(())
+}
+
+fn block_25c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 25c: mov I, 320
+    m.set_i(0x320);
+    if m.retire() {
+        m.set_pc(0x25e);
+        return Ok(());
+    }
+    // 25e: draw Ve, Vd, 1
+    m.set_pc(0x25e);
+    if !m.execute(Instruction::Draw(14, 13, 1))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x260);
+        return Ok(());
+    }
+    // 260: ret
+    m.set_pc(0x260);
+    m.execute(Instruction::Return)?;
+    m.retire();
+    Ok(())
+}
+
+fn block_262<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 262: mov I, 2d8
+    m.set_i(0x2d8);
+    if m.retire() {
+        m.set_pc(0x264);
+        return Ok(());
+    }
+    // 264: add I, Vb
+    let value = m.v()[0xb] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x266);
+        return Ok(());
+    }
+    // 266: movm V0-V3, I
+    m.set_pc(0x266);
+    m.execute(Instruction::Load(3))?;
+    if m.retire() {
+        m.set_pc(0x268);
+        return Ok(());
+    }
+    // 268: addwc V0, V2
+    let v = m.v();
+    let (value, carry) = v[0x0].overflowing_add(v[0x2]);
+    v[0x0] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x26a);
+        return Ok(());
+    }
+    // 26a: addwc V1, V3
+    let v = m.v();
+    let (value, carry) = v[0x1].overflowing_add(v[0x3]);
+    v[0x1] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x26c);
+        return Ok(());
+    }
+    // 26c: mov Ve, V0
+    let v = m.v();
+    v[0xe] = v[0x0];
+    if m.retire() {
+        m.set_pc(0x26e);
+        return Ok(());
+    }
+    // 26e: mov Vd, V1
+    let v = m.v();
+    v[0xd] = v[0x1];
+    if m.retire() {
+        m.set_pc(0x270);
+        return Ok(());
+    }
+    // 270: shr Ve, Ve
+    m.execute(Instruction::ShiftRight(14, 14))?;
+    if m.retire() {
+        m.set_pc(0x272);
+        return Ok(());
+    }
+    // 272: shr Vd, Vd
+    m.execute(Instruction::ShiftRight(13, 13))?;
+    if m.retire() {
+        m.set_pc(0x274);
+        return Ok(());
+    }
+    // 274: mov V4, Ve
+    let v = m.v();
+    v[0x4] = v[0xe];
+    if m.retire() {
+        m.set_pc(0x276);
+        return Ok(());
+    }
+    // 276: mov V5, c2
+    m.v()[0x5] = 0xc2;
+    if m.retire() {
+        m.set_pc(0x278);
+        return Ok(());
+    }
+    // 278: addwc V4, V5
+    let v = m.v();
+    let (value, carry) = v[0x4].overflowing_add(v[0x5]);
+    v[0x4] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x27a);
+        return Ok(());
+    }
+    // 27a: skipifne Vf, 01
+    let skip = m.v()[0xf] != 0x01;
+    m.set_pc(if skip { 0x27e } else { 0x27c });
+    m.retire();
+    Ok(())
+}
+
+fn block_27c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 27c: jmp 292
+    m.set_pc(0x292);
+    m.retire();
+    Ok(())
+}
+
+fn block_27e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 27e: skipifne Vd, 00
+    let skip = m.v()[0xd] != 0x00;
+    m.set_pc(if skip { 0x282 } else { 0x280 });
+    m.retire();
+    Ok(())
+}
+
+fn block_280<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 280: mov V3, 01
+    m.v()[0x3] = 0x01;
+    m.set_pc(0x282);
+    m.retire();
+    Ok(())
+}
+
+fn block_282<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 282: mov V4, Vd
+    let v = m.v();
+    v[0x4] = v[0xd];
+    if m.retire() {
+        m.set_pc(0x284);
+        return Ok(());
+    }
+    // 284: mov V5, e1
+    m.v()[0x5] = 0xe1;
+    if m.retire() {
+        m.set_pc(0x286);
+        return Ok(());
+    }
+    // 286: addwc V4, V5
+    let v = m.v();
+    let (value, carry) = v[0x4].overflowing_add(v[0x5]);
+    v[0x4] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x288);
+        return Ok(());
+    }
+    // 288: skipifne Vf, 01
+    let skip = m.v()[0xf] != 0x01;
+    m.set_pc(if skip { 0x28c } else { 0x28a });
+    m.retire();
+    Ok(())
+}
+
+fn block_28a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 28a: jmp 292
+    m.set_pc(0x292);
+    m.retire();
+    Ok(())
+}
+
+fn block_28c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 28c: skipifeq V3, 02
+    let skip = m.v()[0x3] == 0x02;
+    m.set_pc(if skip { 0x290 } else { 0x28e });
+    m.retire();
+    Ok(())
+}
+
+fn block_28e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 28e: add V3, 01
+    let v = m.v();
+    v[0x3] = v[0x3].wrapping_add(0x01);
+    m.set_pc(0x290);
+    m.retire();
+    Ok(())
+}
+
+fn block_290<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 290: jmp 294
+    m.set_pc(0x294);
+    m.retire();
+    Ok(())
+}
+
+fn block_292<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 292: call 29c
+    m.set_pc(0x292);
+    m.execute(Instruction::Call(668))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_294<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 294: mov I, 2d8
+    m.set_i(0x2d8);
+    if m.retire() {
+        m.set_pc(0x296);
+        return Ok(());
+    }
+    // 296: add I, Vb
+    let value = m.v()[0xb] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x298);
+        return Ok(());
+    }
+    // 298: movm I, V0-V3
+    m.set_pc(0x298);
+    m.execute(Instruction::Store(3))?;
+    if m.retire() {
+        m.set_pc(0x29a);
+        return Ok(());
+    }
+    // 29a: jmp 24c
+    m.set_pc(0x24c);
+    m.retire();
+    Ok(())
+}
+
+fn block_29c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 29c: mov I, 300
+    m.set_i(0x300);
+    if m.retire() {
+        m.set_pc(0x29e);
+        return Ok(());
+    }
+    // 29e: add I, Va
+    let value = m.v()[0xa] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x2a0);
+        return Ok(());
+    }
+    // 2a0: movm V0-V0, I
+    m.set_pc(0x2a0);
+    m.execute(Instruction::Load(0))?;
+    if m.retire() {
+        m.set_pc(0x2a2);
+        return Ok(());
+    }
+    // 2a2: mov V2, V0
+    let v = m.v();
+    v[0x2] = v[0x0];
+    if m.retire() {
+        m.set_pc(0x2a4);
+        return Ok(());
+    }
+    // 2a4: add Va, 01
+    let v = m.v();
+    v[0xa] = v[0xa].wrapping_add(0x01);
+    if m.retire() {
+        m.set_pc(0x2a6);
+        return Ok(());
+    }
+    // 2a6: mov V4, 1f
+    m.v()[0x4] = 0x1f;
+    if m.retire() {
+        m.set_pc(0x2a8);
+        return Ok(());
+    }
+    // 2a8: and Va, V4
+    m.execute(Instruction::And(10, 4))?;
+    if m.retire() {
+        m.set_pc(0x2aa);
+        return Ok(());
+    }
+    // 2aa: mov V0, 20
+    m.v()[0x0] = 0x20;
+    if m.retire() {
+        m.set_pc(0x2ac);
+        return Ok(());
+    }
+    // 2ac: mov V1, 1e
+    m.v()[0x1] = 0x1e;
+    if m.retire() {
+        m.set_pc(0x2ae);
+        return Ok(());
+    }
+    // 2ae: shl V0, V0
+    m.execute(Instruction::ShiftLeft(0, 0))?;
+    if m.retire() {
+        m.set_pc(0x2b0);
+        return Ok(());
+    }
+    // 2b0: shl V1, V1
+    m.execute(Instruction::ShiftLeft(1, 1))?;
+    if m.retire() {
+        m.set_pc(0x2b2);
+        return Ok(());
+    }
+    // 2b2: rnd V3, 03
+    let value = m.random();
+    m.v()[0x3] = value & 0x03;
+    if m.retire() {
+        m.set_pc(0x2b4);
+        return Ok(());
+    }
+    // 2b4: add V3, f8
+    let v = m.v();
+    v[0x3] = v[0x3].wrapping_add(0xf8);
+    if m.retire() {
+        m.set_pc(0x2b6);
+        return Ok(());
+    }
+    // 2b6: ret
+    m.set_pc(0x2b6);
+    m.execute(Instruction::Return)?;
+    m.retire();
+    Ok(())
+}
+
+fn block_2b8<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2b8: mov Vb, 00
+    m.v()[0xb] = 0x00;
+    if m.retire() {
+        m.set_pc(0x2ba);
+        return Ok(());
+    }
+    // 2ba: mov Vc, 00
+    m.v()[0xc] = 0x00;
+    m.set_pc(0x2bc);
+    m.retire();
+    Ok(())
+}
+
+fn block_2bc<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2bc: call 29c
+    m.set_pc(0x2bc);
+    m.execute(Instruction::Call(668))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_2be<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2be: mov I, 2d8
+    m.set_i(0x2d8);
+    if m.retire() {
+        m.set_pc(0x2c0);
+        return Ok(());
+    }
+    // 2c0: add I, Vb
+    let value = m.v()[0xb] as u16;
+    m.set_i(m.i().wrapping_add(value));
+    if m.retire() {
+        m.set_pc(0x2c2);
+        return Ok(());
+    }
+    // 2c2: movm I, V0-V3
+    m.set_pc(0x2c2);
+    m.execute(Instruction::Store(3))?;
+    if m.retire() {
+        m.set_pc(0x2c4);
+        return Ok(());
+    }
+    // 2c4: add Vb, 04
+    let v = m.v();
+    v[0xb] = v[0xb].wrapping_add(0x04);
+    if m.retire() {
+        m.set_pc(0x2c6);
+        return Ok(());
+    }
+    // 2c6: add Vc, 01
+    let v = m.v();
+    v[0xc] = v[0xc].wrapping_add(0x01);
+    if m.retire() {
+        m.set_pc(0x2c8);
+        return Ok(());
+    }
+    // 2c8: skipifeq Vc, V6
+    let v = m.v();
+    let skip = v[0xc] == v[0x6];
+    m.set_pc(if skip { 0x2cc } else { 0x2ca });
+    m.retire();
+    Ok(())
+}
+
+fn block_2ca<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2ca: jmp 2bc
+    m.set_pc(0x2bc);
+    m.retire();
+    Ok(())
+}
+
+fn block_2cc<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2cc: jmp 23c
+    m.set_pc(0x23c);
+    m.retire();
+    Ok(())
+}
+
+fn block_2ce<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2ce: mov Ve, V0
+    let v = m.v();
+    v[0xe] = v[0x0];
+    if m.retire() {
+        m.set_pc(0x2d0);
+        return Ok(());
+    }
+    // 2d0: mov Vd, V1
+    let v = m.v();
+    v[0xd] = v[0x1];
+    if m.retire() {
+        m.set_pc(0x2d2);
+        return Ok(());
+    }
+    // 2d2: shr Ve, Ve
+    m.execute(Instruction::ShiftRight(14, 14))?;
+    if m.retire() {
+        m.set_pc(0x2d4);
+        return Ok(());
+    }
+    // 2d4: shr Vd, Vd
+    m.execute(Instruction::ShiftRight(13, 13))?;
+    if m.retire() {
+        m.set_pc(0x2d6);
+        return Ok(());
+    }
+    // 2d6: ret
+    m.set_pc(0x2d6);
+    m.execute(Instruction::Return)?;
+    m.retire();
+    Ok(())
+}
@@ -0,0 +1,971 @@
+//! pong.ch8 recompiled by `chiper recompile`
+//!
+//! Pass `run` to `Chip8::run_compiled`, code without a block and blocks
+//! overwritten since run on the interpreter.
+
+#![allow(clippy::all, unreachable_code, unused_imports)]
+
+use chiper::chip8::{Chip8, ExecError};
+use chiper::instruction::Instruction;
+use chiper::screen::Screen;
+
+/// The rom, loaded at 0x200
+const ROM: [u8; 246] = [
+    0x6a, 0x02, 0x6b, 0x0c, 0x6c, 0x3f, 0x6d, 0x0c, 0xa2, 0xea, 0xda, 0xb6, 0xdc, 0xd6, 0x6e, 0x00,
+    0x22, 0xd4, 0x66, 0x03, 0x68, 0x02, 0x60, 0x60, 0xf0, 0x15, 0xf0, 0x07, 0x30, 0x00, 0x12, 0x1a,
+    0xc7, 0x17, 0x77, 0x08, 0x69, 0xff, 0xa2, 0xf0, 0xd6, 0x71, 0xa2, 0xea, 0xda, 0xb6, 0xdc, 0xd6,
+    0x60, 0x01, 0xe0, 0xa1, 0x7b, 0xfe, 0x60, 0x04, 0xe0, 0xa1, 0x7b, 0x02, 0x60, 0x1f, 0x8b, 0x02,
+    0xda, 0xb6, 0x60, 0x0c, 0xe0, 0xa1, 0x7d, 0xfe, 0x60, 0x0d, 0xe0, 0xa1, 0x7d, 0x02, 0x60, 0x1f,
+    0x8d, 0x02, 0xdc, 0xd6, 0xa2, 0xf0, 0xd6, 0x71, 0x86, 0x84, 0x87, 0x94, 0x60, 0x3f, 0x86, 0x02,
+    0x61, 0x1f, 0x87, 0x12, 0x46, 0x02, 0x12, 0x78, 0x46, 0x3f, 0x12, 0x82, 0x47, 0x1f, 0x69, 0xff,
+    0x47, 0x00, 0x69, 0x01, 0xd6, 0x71, 0x12, 0x2a, 0x68, 0x02, 0x63, 0x01, 0x80, 0x70, 0x80, 0xb5,
+    0x12, 0x8a, 0x68, 0xfe, 0x63, 0x0a, 0x80, 0x70, 0x80, 0xd5, 0x3f, 0x01, 0x12, 0xa2, 0x61, 0x02,
+    0x80, 0x15, 0x3f, 0x01, 0x12, 0xba, 0x80, 0x15, 0x3f, 0x01, 0x12, 0xc8, 0x80, 0x15, 0x3f, 0x01,
+    0x12, 0xc2, 0x60, 0x20, 0xf0, 0x18, 0x22, 0xd4, 0x8e, 0x34, 0x22, 0xd4, 0x66, 0x3e, 0x33, 0x01,
+    0x66, 0x03, 0x68, 0xfe, 0x33, 0x01, 0x68, 0x02, 0x12, 0x16, 0x79, 0xff, 0x49, 0xfe, 0x69, 0xff,
+    0x12, 0xc8, 0x79, 0x01, 0x49, 0x02, 0x69, 0x01, 0x60, 0x04, 0xf0, 0x18, 0x76, 0x01, 0x46, 0x40,
+    0x76, 0xfe, 0x12, 0x6c, 0xa2, 0xf2, 0xfe, 0x33, 0xf2, 0x65, 0xf1, 0x29, 0x64, 0x14, 0x65, 0x00,
+    0xd4, 0x55, 0x74, 0x15, 0xf2, 0x29, 0xd4, 0x55, 0x00, 0xee, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80,
+    0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Runs the block at pc, returns false when there's none
+pub fn run<T: Screen>(m: &mut Chip8<T>) -> Result<bool, ExecError> {
+    match m.pc() {
+        0x200 if unchanged(m, 0x200, 0x212) => block_200(m)?,
+        0x212 if unchanged(m, 0x212, 0x216) => block_212(m)?,
+        0x216 if unchanged(m, 0x216, 0x21a) => block_216(m)?,
+        0x21a if unchanged(m, 0x21a, 0x21e) => block_21a(m)?,
+        0x21e if unchanged(m, 0x21e, 0x220) => block_21e(m)?,
+        0x220 if unchanged(m, 0x220, 0x22a) => block_220(m)?,
+        0x22a if unchanged(m, 0x22a, 0x234) => block_22a(m)?,
+        0x234 if unchanged(m, 0x234, 0x236) => block_234(m)?,
+        0x236 if unchanged(m, 0x236, 0x23a) => block_236(m)?,
+        0x23a if unchanged(m, 0x23a, 0x23c) => block_23a(m)?,
+        0x23c if unchanged(m, 0x23c, 0x246) => block_23c(m)?,
+        0x246 if unchanged(m, 0x246, 0x248) => block_246(m)?,
+        0x248 if unchanged(m, 0x248, 0x24c) => block_248(m)?,
+        0x24c if unchanged(m, 0x24c, 0x24e) => block_24c(m)?,
+        0x24e if unchanged(m, 0x24e, 0x266) => block_24e(m)?,
+        0x266 if unchanged(m, 0x266, 0x268) => block_266(m)?,
+        0x268 if unchanged(m, 0x268, 0x26a) => block_268(m)?,
+        0x26a if unchanged(m, 0x26a, 0x26c) => block_26a(m)?,
+        0x26c if unchanged(m, 0x26c, 0x26e) => block_26c(m)?,
+        0x26e if unchanged(m, 0x26e, 0x270) => block_26e(m)?,
+        0x270 if unchanged(m, 0x270, 0x272) => block_270(m)?,
+        0x272 if unchanged(m, 0x272, 0x274) => block_272(m)?,
+        0x274 if unchanged(m, 0x274, 0x278) => block_274(m)?,
+        0x278 if unchanged(m, 0x278, 0x282) => block_278(m)?,
+        0x282 if unchanged(m, 0x282, 0x28a) => block_282(m)?,
+        0x28a if unchanged(m, 0x28a, 0x28c) => block_28a(m)?,
+        0x28c if unchanged(m, 0x28c, 0x28e) => block_28c(m)?,
+        0x28e if unchanged(m, 0x28e, 0x294) => block_28e(m)?,
+        0x294 if unchanged(m, 0x294, 0x296) => block_294(m)?,
+        0x296 if unchanged(m, 0x296, 0x29a) => block_296(m)?,
+        0x29a if unchanged(m, 0x29a, 0x29c) => block_29a(m)?,
+        0x29c if unchanged(m, 0x29c, 0x2a0) => block_29c(m)?,
+        0x2a0 if unchanged(m, 0x2a0, 0x2a2) => block_2a0(m)?,
+        0x2a2 if unchanged(m, 0x2a2, 0x2a8) => block_2a2(m)?,
+        0x2a8 if unchanged(m, 0x2a8, 0x2ac) => block_2a8(m)?,
+        0x2ac if unchanged(m, 0x2ac, 0x2b0) => block_2ac(m)?,
+        0x2b0 if unchanged(m, 0x2b0, 0x2b2) => block_2b0(m)?,
+        0x2b2 if unchanged(m, 0x2b2, 0x2b6) => block_2b2(m)?,
+        0x2b6 if unchanged(m, 0x2b6, 0x2b8) => block_2b6(m)?,
+        0x2b8 if unchanged(m, 0x2b8, 0x2ba) => block_2b8(m)?,
+        0x2ba if unchanged(m, 0x2ba, 0x2be) => block_2ba(m)?,
+        0x2be if unchanged(m, 0x2be, 0x2c0) => block_2be(m)?,
+        0x2c0 if unchanged(m, 0x2c0, 0x2c2) => block_2c0(m)?,
+        0x2c2 if unchanged(m, 0x2c2, 0x2c6) => block_2c2(m)?,
+        0x2c6 if unchanged(m, 0x2c6, 0x2c8) => block_2c6(m)?,
+        0x2c8 if unchanged(m, 0x2c8, 0x2d0) => block_2c8(m)?,
+        0x2d0 if unchanged(m, 0x2d0, 0x2d2) => block_2d0(m)?,
+        0x2d2 if unchanged(m, 0x2d2, 0x2d4) => block_2d2(m)?,
+        0x2d4 if unchanged(m, 0x2d4, 0x2ea) => block_2d4(m)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The code from `start` to `end` is still the compiled one
+fn unchanged<T: Screen>(m: &Chip8<T>, start: usize, end: usize) -> bool {
+    m.memory()[start..end] == ROM[start - 0x200..end - 0x200]
+}
+
+fn block_200<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 200: mov Va, 02
+    m.v()[0xa] = 0x02;
+    if m.retire() {
+        m.set_pc(0x202);
+        return Ok(());
+    }
+    // 202: mov Vb, 0c
+    m.v()[0xb] = 0x0c;
+    if m.retire() {
+        m.set_pc(0x204);
+        return Ok(());
+    }
+    // 204: mov Vc, 3f
+    m.v()[0xc] = 0x3f;
+    if m.retire() {
+        m.set_pc(0x206);
+        return Ok(());
+    }
+    // 206: mov Vd, 0c
+    m.v()[0xd] = 0x0c;
+    if m.retire() {
+        m.set_pc(0x208);
+        return Ok(());
+    }
+    // 208: mov I, 2ea
+    m.set_i(0x2ea);
+    if m.retire() {
+        m.set_pc(0x20a);
+        return Ok(());
+    }
+    // 20a: draw Va, Vb, 6
+    m.set_pc(0x20a);
+    if !m.execute(Instruction::Draw(10, 11, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x20c);
+        return Ok(());
+    }
+    // 20c: draw Vc, Vd, 6
+    m.set_pc(0x20c);
+    if !m.execute(Instruction::Draw(12, 13, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x20e);
+        return Ok(());
+    }
+    // 20e: mov Ve, 00
+    m.v()[0xe] = 0x00;
+    if m.retire() {
+        m.set_pc(0x210);
+        return Ok(());
+    }
+    // 210: call 2d4
+    m.set_pc(0x210);
+    m.execute(Instruction::Call(724))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_212<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 212: mov V6, 03
+    m.v()[0x6] = 0x03;
+    if m.retire() {
+        m.set_pc(0x214);
+        return Ok(());
+    }
+    // 214: mov V8, 02
+    m.v()[0x8] = 0x02;
+    m.set_pc(0x216);
+    m.retire();
+    Ok(())
+}
+
+fn block_216<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 216: mov V0, 60
+    m.v()[0x0] = 0x60;
+    if m.retire() {
+        m.set_pc(0x218);
+        return Ok(());
+    }
+    // 218: mov DT, V0
+    let value = m.v()[0x0];
+    m.set_delay_timer(value);
+    m.set_pc(0x21a);
+    m.retire();
+    Ok(())
+}
+
+fn block_21a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 21a: mov V0, DT
+    let value = m.delay_timer();
+    m.v()[0x0] = value;
+    if m.retire() {
+        m.set_pc(0x21c);
+        return Ok(());
+    }
+    // 21c: skipifeq V0, 00
+    let skip = m.v()[0x0] == 0x00;
+    m.set_pc(if skip { 0x220 } else { 0x21e });
+    m.retire();
+    Ok(())
+}
+
+fn block_21e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 21e: jmp 21a
+    m.set_pc(0x21a);
+    m.retire();
+    Ok(())
+}
+
+fn block_220<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 220: rnd V7, 17
+    let value = m.random();
+    m.v()[0x7] = value & 0x17;
+    if m.retire() {
+        m.set_pc(0x222);
+        return Ok(());
+    }
+    // 222: add V7, 08
+    let v = m.v();
+    v[0x7] = v[0x7].wrapping_add(0x08);
+    if m.retire() {
+        m.set_pc(0x224);
+        return Ok(());
+    }
+    // 224: mov V9, ff
+    m.v()[0x9] = 0xff;
+    if m.retire() {
+        m.set_pc(0x226);
+        return Ok(());
+    }
+    // 226: mov I, 2f0
+    m.set_i(0x2f0);
+    if m.retire() {
+        m.set_pc(0x228);
+        return Ok(());
+    }
+    // 228: draw V6, V7, 1
+    m.set_pc(0x228);
+    if !m.execute(Instruction::Draw(6, 7, 1))? {
+        m.retire();
+        return Ok(());
+    }
+    m.set_pc(0x22a);
+    m.retire();
+    Ok(())
+}
+
+fn block_22a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 22a: mov I, 2ea
+    m.set_i(0x2ea);
+    if m.retire() {
+        m.set_pc(0x22c);
+        return Ok(());
+    }
+    // 22c: draw Va, Vb, 6
+    m.set_pc(0x22c);
+    if !m.execute(Instruction::Draw(10, 11, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x22e);
+        return Ok(());
+    }
+    // 22e: draw Vc, Vd, 6
+    m.set_pc(0x22e);
+    if !m.execute(Instruction::Draw(12, 13, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x230);
+        return Ok(());
+    }
+    // 230: mov V0, 01
+    m.v()[0x0] = 0x01;
+    if m.retire() {
+        m.set_pc(0x232);
+        return Ok(());
+    }
+    // 232: skipifnkey V0
+    let key = m.v()[0x0];
+    let skip = !m.key_pressed(key);
+    m.set_pc(if skip { 0x236 } else { 0x234 });
+    m.retire();
+    Ok(())
+}
+
+fn block_234<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 234: add Vb, fe
+    let v = m.v();
+    v[0xb] = v[0xb].wrapping_add(0xfe);
+    m.set_pc(0x236);
+    m.retire();
+    Ok(())
+}
+
+fn block_236<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 236: mov V0, 04
+    m.v()[0x0] = 0x04;
+    if m.retire() {
+        m.set_pc(0x238);
+        return Ok(());
+    }
+    // 238: skipifnkey V0
+    let key = m.v()[0x0];
+    let skip = !m.key_pressed(key);
+    m.set_pc(if skip { 0x23c } else { 0x23a });
+    m.retire();
+    Ok(())
+}
+
+fn block_23a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 23a: add Vb, 02
+    let v = m.v();
+    v[0xb] = v[0xb].wrapping_add(0x02);
+    m.set_pc(0x23c);
+    m.retire();
+    Ok(())
+}
+
+fn block_23c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 23c: mov V0, 1f
+    m.v()[0x0] = 0x1f;
+    if m.retire() {
+        m.set_pc(0x23e);
+        return Ok(());
+    }
+    // 23e: and Vb, V0
+    m.execute(Instruction::And(11, 0))?;
+    if m.retire() {
+        m.set_pc(0x240);
+        return Ok(());
+    }
+    // 240: draw Va, Vb, 6
+    m.set_pc(0x240);
+    if !m.execute(Instruction::Draw(10, 11, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x242);
+        return Ok(());
+    }
+    // 242: mov V0, 0c
+    m.v()[0x0] = 0x0c;
+    if m.retire() {
+        m.set_pc(0x244);
+        return Ok(());
+    }
+    // 244: skipifnkey V0
+    let key = m.v()[0x0];
+    let skip = !m.key_pressed(key);
+    m.set_pc(if skip { 0x248 } else { 0x246 });
+    m.retire();
+    Ok(())
+}
+
+fn block_246<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 246: add Vd, fe
+    let v = m.v();
+    v[0xd] = v[0xd].wrapping_add(0xfe);
+    m.set_pc(0x248);
+    m.retire();
+    Ok(())
+}
+
+fn block_248<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 248: mov V0, 0d
+    m.v()[0x0] = 0x0d;
+    if m.retire() {
+        m.set_pc(0x24a);
+        return Ok(());
+    }
+    // 24a: skipifnkey V0
+    let key = m.v()[0x0];
+    let skip = !m.key_pressed(key);
+    m.set_pc(if skip { 0x24e } else { 0x24c });
+    m.retire();
+    Ok(())
+}
+
+fn block_24c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 24c: add Vd, 02
+    let v = m.v();
+    v[0xd] = v[0xd].wrapping_add(0x02);
+    m.set_pc(0x24e);
+    m.retire();
+    Ok(())
+}
+
+fn block_24e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 24e: mov V0, 1f
+    m.v()[0x0] = 0x1f;
+    if m.retire() {
+        m.set_pc(0x250);
+        return Ok(());
+    }
+    // 250: and Vd, V0
+    m.execute(Instruction::And(13, 0))?;
+    if m.retire() {
+        m.set_pc(0x252);
+        return Ok(());
+    }
+    // 252: draw Vc, Vd, 6
+    m.set_pc(0x252);
+    if !m.execute(Instruction::Draw(12, 13, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x254);
+        return Ok(());
+    }
+    // 254: mov I, 2f0
+    m.set_i(0x2f0);
+    if m.retire() {
+        m.set_pc(0x256);
+        return Ok(());
+    }
+    // 256: draw V6, V7, 1
+    m.set_pc(0x256);
+    if !m.execute(Instruction::Draw(6, 7, 1))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x258);
+        return Ok(());
+    }
+    // 258: addwc V6, V8
+    let v = m.v();
+    let (value, carry) = v[0x6].overflowing_add(v[0x8]);
+    v[0x6] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x25a);
+        return Ok(());
+    }
+    // 25a: addwc V7, V9
+    let v = m.v();
+    let (value, carry) = v[0x7].overflowing_add(v[0x9]);
+    v[0x7] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x25c);
+        return Ok(());
+    }
+    // 25c: mov V0, 3f
+    m.v()[0x0] = 0x3f;
+    if m.retire() {
+        m.set_pc(0x25e);
+        return Ok(());
+    }
+    // 25e: and V6, V0
+    m.execute(Instruction::And(6, 0))?;
+    if m.retire() {
+        m.set_pc(0x260);
+        return Ok(());
+    }
+    // 260: mov V1, 1f
+    m.v()[0x1] = 0x1f;
+    if m.retire() {
+        m.set_pc(0x262);
+        return Ok(());
+    }
+    // 262: and V7, V1
+    m.execute(Instruction::And(7, 1))?;
+    if m.retire() {
+        m.set_pc(0x264);
+        return Ok(());
+    }
+    // 264: skipifne V6, 02
+    let skip = m.v()[0x6] != 0x02;
+    m.set_pc(if skip { 0x268 } else { 0x266 });
+    m.retire();
+    Ok(())
+}
+
+fn block_266<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 266: jmp 278
+    m.set_pc(0x278);
+    m.retire();
+    Ok(())
+}
+
+fn block_268<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 268: skipifne V6, 3f
+    let skip = m.v()[0x6] != 0x3f;
+    m.set_pc(if skip { 0x26c } else { 0x26a });
+    m.retire();
+    Ok(())
+}
+
+fn block_26a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 26a: jmp 282
+    m.set_pc(0x282);
+    m.retire();
+    Ok(())
+}
+
+fn block_26c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 26c: skipifne V7, 1f
+    let skip = m.v()[0x7] != 0x1f;
+    m.set_pc(if skip { 0x270 } else { 0x26e });
+    m.retire();
+    Ok(())
+}
+
+fn block_26e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 26e: mov V9, ff
+    m.v()[0x9] = 0xff;
+    m.set_pc(0x270);
+    m.retire();
+    Ok(())
+}
+
+fn block_270<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 270: skipifne V7, 00
+    let skip = m.v()[0x7] != 0x00;
+    m.set_pc(if skip { 0x274 } else { 0x272 });
+    m.retire();
+    Ok(())
+}
+
+fn block_272<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 272: mov V9, 01
+    m.v()[0x9] = 0x01;
+    m.set_pc(0x274);
+    m.retire();
+    Ok(())
+}
+
+fn block_274<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 274: draw V6, V7, 1
+    m.set_pc(0x274);
+    if !m.execute(Instruction::Draw(6, 7, 1))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x276);
+        return Ok(());
+    }
+    // 276: jmp 22a
+    m.set_pc(0x22a);
+    m.retire();
+    Ok(())
+}
+
+fn block_278<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 278: mov V8, 02
+    m.v()[0x8] = 0x02;
+    if m.retire() {
+        m.set_pc(0x27a);
+        return Ok(());
+    }
+    // 27a: mov V3, 01
+    m.v()[0x3] = 0x01;
+    if m.retire() {
+        m.set_pc(0x27c);
+        return Ok(());
+    }
+    // 27c: mov V0, V7
+    let v = m.v();
+    v[0x0] = v[0x7];
+    if m.retire() {
+        m.set_pc(0x27e);
+        return Ok(());
+    }
+    // 27e: subwc V0, Vb
+    let v = m.v();
+    let (value, borrow) = v[0x0].overflowing_sub(v[0xb]);
+    v[0x0] = value;
+    v[0xf] = !borrow as u8;
+    if m.retire() {
+        m.set_pc(0x280);
+        return Ok(());
+    }
+    // 280: jmp 28a
+    m.set_pc(0x28a);
+    m.retire();
+    Ok(())
+}
+
+fn block_282<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 282: mov V8, fe
+    m.v()[0x8] = 0xfe;
+    if m.retire() {
+        m.set_pc(0x284);
+        return Ok(());
+    }
+    // 284: mov V3, 0a
+    m.v()[0x3] = 0x0a;
+    if m.retire() {
+        m.set_pc(0x286);
+        return Ok(());
+    }
+    // 286: mov V0, V7
+    let v = m.v();
+    v[0x0] = v[0x7];
+    if m.retire() {
+        m.set_pc(0x288);
+        return Ok(());
+    }
+    // 288: subwc V0, Vd
+    let v = m.v();
+    let (value, borrow) = v[0x0].overflowing_sub(v[0xd]);
+    v[0x0] = value;
+    v[0xf] = !borrow as u8;
+    m.set_pc(0x28a);
+    m.retire();
+    Ok(())
+}
+
+fn block_28a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 28a: skipifeq Vf, 01
+    let skip = m.v()[0xf] == 0x01;
+    m.set_pc(if skip { 0x28e } else { 0x28c });
+    m.retire();
+    Ok(())
+}
+
+fn block_28c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 28c: jmp 2a2
+    m.set_pc(0x2a2);
+    m.retire();
+    Ok(())
+}
+
+fn block_28e<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 28e: mov V1, 02
+    m.v()[0x1] = 0x02;
+    if m.retire() {
+        m.set_pc(0x290);
+        return Ok(());
+    }
+    // 290: subwc V0, V1
+    let v = m.v();
+    let (value, borrow) = v[0x0].overflowing_sub(v[0x1]);
+    v[0x0] = value;
+    v[0xf] = !borrow as u8;
+    if m.retire() {
+        m.set_pc(0x292);
+        return Ok(());
+    }
+    // 292: skipifeq Vf, 01
+    let skip = m.v()[0xf] == 0x01;
+    m.set_pc(if skip { 0x296 } else { 0x294 });
+    m.retire();
+    Ok(())
+}
+
+fn block_294<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 294: jmp 2ba
+    m.set_pc(0x2ba);
+    m.retire();
+    Ok(())
+}
+
+fn block_296<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 296: subwc V0, V1
+    let v = m.v();
+    let (value, borrow) = v[0x0].overflowing_sub(v[0x1]);
+    v[0x0] = value;
+    v[0xf] = !borrow as u8;
+    if m.retire() {
+        m.set_pc(0x298);
+        return Ok(());
+    }
+    // 298: skipifeq Vf, 01
+    let skip = m.v()[0xf] == 0x01;
+    m.set_pc(if skip { 0x29c } else { 0x29a });
+    m.retire();
+    Ok(())
+}
+
+fn block_29a<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 29a: jmp 2c8
+    m.set_pc(0x2c8);
+    m.retire();
+    Ok(())
+}
+
+fn block_29c<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 29c: subwc V0, V1
+    let v = m.v();
+    let (value, borrow) = v[0x0].overflowing_sub(v[0x1]);
+    v[0x0] = value;
+    v[0xf] = !borrow as u8;
+    if m.retire() {
+        m.set_pc(0x29e);
+        return Ok(());
+    }
+    // 29e: skipifeq Vf, 01
+    let skip = m.v()[0xf] == 0x01;
+    m.set_pc(if skip { 0x2a2 } else { 0x2a0 });
+    m.retire();
+    Ok(())
+}
+
+fn block_2a0<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2a0: jmp 2c2
+    m.set_pc(0x2c2);
+    m.retire();
+    Ok(())
+}
+
+fn block_2a2<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2a2: mov V0, 20
+    m.v()[0x0] = 0x20;
+    if m.retire() {
+        m.set_pc(0x2a4);
+        return Ok(());
+    }
+    // 2a4: mov ST, V0
+    let value = m.v()[0x0];
+    m.set_sound_timer(value);
+    if m.retire() {
+        m.set_pc(0x2a6);
+        return Ok(());
+    }
+    // 2a6: call 2d4
+    m.set_pc(0x2a6);
+    m.execute(Instruction::Call(724))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_2a8<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2a8: addwc Ve, V3
+    let v = m.v();
+    let (value, carry) = v[0xe].overflowing_add(v[0x3]);
+    v[0xe] = value;
+    v[0xf] = carry as u8;
+    if m.retire() {
+        m.set_pc(0x2aa);
+        return Ok(());
+    }
+    // 2aa: call 2d4
+    m.set_pc(0x2aa);
+    m.execute(Instruction::Call(724))?;
+    m.retire();
+    Ok(())
+}
+
+fn block_2ac<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2ac: mov V6, 3e
+    m.v()[0x6] = 0x3e;
+    if m.retire() {
+        m.set_pc(0x2ae);
+        return Ok(());
+    }
+    // 2ae: skipifeq V3, 01
+    let skip = m.v()[0x3] == 0x01;
+    m.set_pc(if skip { 0x2b2 } else { 0x2b0 });
+    m.retire();
+    Ok(())
+}
+
+fn block_2b0<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2b0: mov V6, 03
+    m.v()[0x6] = 0x03;
+    m.set_pc(0x2b2);
+    m.retire();
+    Ok(())
+}
+
+fn block_2b2<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2b2: mov V8, fe
+    m.v()[0x8] = 0xfe;
+    if m.retire() {
+        m.set_pc(0x2b4);
+        return Ok(());
+    }
+    // 2b4: skipifeq V3, 01
+    let skip = m.v()[0x3] == 0x01;
+    m.set_pc(if skip { 0x2b8 } else { 0x2b6 });
+    m.retire();
+    Ok(())
+}
+
+fn block_2b6<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2b6: mov V8, 02
+    m.v()[0x8] = 0x02;
+    m.set_pc(0x2b8);
+    m.retire();
+    Ok(())
+}
+
+fn block_2b8<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2b8: jmp 216
+    m.set_pc(0x216);
+    m.retire();
+    Ok(())
+}
+
+fn block_2ba<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2ba: add V9, ff
+    let v = m.v();
+    v[0x9] = v[0x9].wrapping_add(0xff);
+    if m.retire() {
+        m.set_pc(0x2bc);
+        return Ok(());
+    }
+    // 2bc: skipifne V9, fe
+    let skip = m.v()[0x9] != 0xfe;
+    m.set_pc(if skip { 0x2c0 } else { 0x2be });
+    m.retire();
+    Ok(())
+}
+
+fn block_2be<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2be: mov V9, ff
+    m.v()[0x9] = 0xff;
+    m.set_pc(0x2c0);
+    m.retire();
+    Ok(())
+}
+
+fn block_2c0<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2c0: jmp 2c8
+    m.set_pc(0x2c8);
+    m.retire();
+    Ok(())
+}
+
+fn block_2c2<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2c2: add V9, 01
+    let v = m.v();
+    v[0x9] = v[0x9].wrapping_add(0x01);
+    if m.retire() {
+        m.set_pc(0x2c4);
+        return Ok(());
+    }
+    // 2c4: skipifne V9, 02
+    let skip = m.v()[0x9] != 0x02;
+    m.set_pc(if skip { 0x2c8 } else { 0x2c6 });
+    m.retire();
+    Ok(())
+}
+
+fn block_2c6<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2c6: mov V9, 01
+    m.v()[0x9] = 0x01;
+    m.set_pc(0x2c8);
+    m.retire();
+    Ok(())
+}
+
+fn block_2c8<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2c8: mov V0, 04
+    m.v()[0x0] = 0x04;
+    if m.retire() {
+        m.set_pc(0x2ca);
+        return Ok(());
+    }
+    // 2ca: mov ST, V0
+    let value = m.v()[0x0];
+    m.set_sound_timer(value);
+    if m.retire() {
+        m.set_pc(0x2cc);
+        return Ok(());
+    }
+    // 2cc: add V6, 01
+    let v = m.v();
+    v[0x6] = v[0x6].wrapping_add(0x01);
+    if m.retire() {
+        m.set_pc(0x2ce);
+        return Ok(());
+    }
+    // 2ce: skipifne V6, 40
+    let skip = m.v()[0x6] != 0x40;
+    m.set_pc(if skip { 0x2d2 } else { 0x2d0 });
+    m.retire();
+    Ok(())
+}
+
+fn block_2d0<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2d0: add V6, fe
+    let v = m.v();
+    v[0x6] = v[0x6].wrapping_add(0xfe);
+    m.set_pc(0x2d2);
+    m.retire();
+    Ok(())
+}
+
+fn block_2d2<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2d2: jmp 26c
+    m.set_pc(0x26c);
+    m.retire();
+    Ok(())
+}
+
+fn block_2d4<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 2d4: mov I, 2f2
+    m.set_i(0x2f2);
+    if m.retire() {
+        m.set_pc(0x2d6);
+        return Ok(());
+    }
+    // 2d6: bcd I, Ve
+    m.set_pc(0x2d6);
+    m.execute(Instruction::Bcd(14))?;
+    if m.retire() {
+        m.set_pc(0x2d8);
+        return Ok(());
+    }
+    // 2d8: movm V0-V2, I
+    m.set_pc(0x2d8);
+    m.execute(Instruction::Load(2))?;
+    if m.retire() {
+        m.set_pc(0x2da);
+        return Ok(());
+    }
+    // 2da: font I, V1
+    m.execute(Instruction::Font(1))?;
+    if m.retire() {
+        m.set_pc(0x2dc);
+        return Ok(());
+    }
+    // 2dc: mov V4, 14
+    m.v()[0x4] = 0x14;
+    if m.retire() {
+        m.set_pc(0x2de);
+        return Ok(());
+    }
+    // 2de: mov V5, 00
+    m.v()[0x5] = 0x00;
+    if m.retire() {
+        m.set_pc(0x2e0);
+        return Ok(());
+    }
+    // 2e0: draw V4, V5, 5
+    m.set_pc(0x2e0);
+    if !m.execute(Instruction::Draw(4, 5, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x2e2);
+        return Ok(());
+    }
+    // 2e2: add V4, 15
+    let v = m.v();
+    v[0x4] = v[0x4].wrapping_add(0x15);
+    if m.retire() {
+        m.set_pc(0x2e4);
+        return Ok(());
+    }
+    // 2e4: font I, V2
+    m.execute(Instruction::Font(2))?;
+    if m.retire() {
+        m.set_pc(0x2e6);
+        return Ok(());
+    }
+    // 2e6: draw V4, V5, 5
+    m.set_pc(0x2e6);
+    if !m.execute(Instruction::Draw(4, 5, 5))? {
+        m.retire();
+        return Ok(());
+    }
+    if m.retire() {
+        m.set_pc(0x2e8);
+        return Ok(());
+    }
+    // 2e8: ret
+    m.set_pc(0x2e8);
+    m.execute(Instruction::Return)?;
+    m.retire();
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! space_invader_alien_sprite.ch8 recompiled by `chiper recompile`
+//!
+//! Pass `run` to `Chip8::run_compiled`, code without a block and blocks
+//! overwritten since run on the interpreter.
+
+#![allow(clippy::all, unreachable_code, unused_imports)]
+
+use chiper::chip8::{Chip8, ExecError};
+use chiper::instruction::Instruction;
+use chiper::screen::Screen;
+
+/// The rom, loaded at 0x200
+const ROM: [u8; 16] = [
+    0x62, 0x0a, 0x63, 0x0c, 0xa2, 0x0a, 0xd2, 0x36, 0x12, 0x08, 0xba, 0x7c, 0xd6, 0xfe, 0x54, 0xaa,
+];
+
+/// Runs the block at pc, returns false when there's none
+pub fn run<T: Screen>(m: &mut Chip8<T>) -> Result<bool, ExecError> {
+    match m.pc() {
+        0x200 if unchanged(m, 0x200, 0x208) => block_200(m)?,
+        0x208 if unchanged(m, 0x208, 0x20a) => block_208(m)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The code from `start` to `end` is still the compiled one
+fn unchanged<T: Screen>(m: &Chip8<T>, start: usize, end: usize) -> bool {
+    m.memory()[start..end] == ROM[start - 0x200..end - 0x200]
+}
+
+fn block_200<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 200: mov V2, 0a
+    m.v()[0x2] = 0x0a;
+    if m.retire() {
+        m.set_pc(0x202);
+        return Ok(());
+    }
+    // 202: mov V3, 0c
+    m.v()[0x3] = 0x0c;
+    if m.retire() {
+        m.set_pc(0x204);
+        return Ok(());
+    }
+    // 204: mov I, 20a
+    m.set_i(0x20a);
+    if m.retire() {
+        m.set_pc(0x206);
+        return Ok(());
+    }
+    // 206: draw V2, V3, 6
+    m.set_pc(0x206);
+    if !m.execute(Instruction::Draw(2, 3, 6))? {
+        m.retire();
+        return Ok(());
+    }
+    m.set_pc(0x208);
+    m.retire();
+    Ok(())
+}
+
+fn block_208<T: Screen>(m: &mut Chip8<T>) -> Result<(), ExecError> {
+    // 208: jmp 208
+    m.set_pc(0x208);
+    m.retire();
+    Ok(())
+}